@@ -1,7 +1,7 @@
 extern crate aloxide;
 extern crate clap;
 
-use aloxide::{version::{Version, VersionParseError}};
+use aloxide::{Archive, Ruby, version::{Version, VersionParseError, VersionReq}};
 use clap::{Arg, ArgMatches, ArgSettings, App, AppSettings, SubCommand};
 
 macro_rules! error {
@@ -28,6 +28,8 @@ fn main() {
                 .about("Build a specific Ruby version")
                 .args(&[
                     Arg::with_name("version")
+                        .help("The version to build, or a requirement such \
+                               as '~> 3.1' to reuse an installed Ruby")
                         .takes_value(true)
                         .required(true),
                     Arg::with_name("output")
@@ -36,11 +38,19 @@ fn main() {
                         .help("Specifies where Ruby should be built")
                         .takes_value(true),
                 ]),
+            SubCommand::with_name("inspect")
+                .about("List the contents of a Ruby archive")
+                .args(&[
+                    Arg::with_name("archive")
+                        .takes_value(true)
+                        .required(true),
+                ]),
         ]);
     let matches = app.get_matches();
 
     match matches.subcommand() {
         ("build", Some(matches)) => build_ruby(matches),
+        ("inspect", Some(matches)) => inspect_archive(matches),
         _ => unreachable!(),
     }
 }
@@ -50,7 +60,34 @@ fn get_version(matches: &ArgMatches) -> Option<Result<Version, VersionParseError
     Some(Version::parser().require_minor().parse(version))
 }
 
+// Whether `version` is a requirement such as '~> 3.1' rather than a version
+fn is_requirement(version: &str) -> bool {
+    version.contains(|c| "~<>=!,".contains(c))
+}
+
 fn build_ruby(matches: &ArgMatches) {
+    // Reuse an installed Ruby if one satisfies the requirement, unless told
+    // where to build
+    let requirement = matches.value_of("version")
+        .filter(|version| is_requirement(version));
+    if let Some(requirement) = requirement {
+        if let Some(out) = matches.value_of("output") {
+            error!("A version is required to build into '{}'", out);
+        }
+        let req = match requirement.parse::<VersionReq>() {
+            Ok(req) => req,
+            Err(_) => error!("Invalid version requirement '{}'", requirement),
+        };
+        match Ruby::find(&req) {
+            Some(ruby) => {
+                let dir = ruby.out_dir().display();
+                println!("Using Ruby {} at {}", ruby.version(), dir);
+                return;
+            },
+            None => error!("No installed Ruby satisfies '{}'", requirement),
+        }
+    }
+
     let version = match get_version(matches) {
         Some(Ok(value)) => value,
         Some(Err(_)) => {
@@ -63,3 +100,24 @@ fn build_ruby(matches: &ArgMatches) {
 
     unimplemented!("TODO: Implement downloading Ruby {}", version);
 }
+
+fn inspect_archive(matches: &ArgMatches) {
+    let path = matches.value_of_os("archive").unwrap();
+    let entries = std::fs::File::open(path)
+        .and_then(|mut file| file.entries_list());
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(error) => {
+            error!("Failed to read {:?}: {}", path, error);
+        },
+    };
+
+    let verbose = matches.is_present("v");
+    for entry in entries {
+        if verbose && !entry.is_dir {
+            println!("{:>10} {}", entry.size, entry.path.display());
+        } else {
+            println!("{}", entry.path.display());
+        }
+    }
+}
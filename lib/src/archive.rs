@@ -1,66 +1,928 @@
+//! Utilities for unpacking Ruby archives.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fs;
-use std::io;
-use std::path::{Path, PathBuf};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::sync::mpsc;
+use std::thread;
 use tar::{Archive as Tar, EntryType, Header};
 use bzip2::read::BzDecoder as Bz;
+use flate2::read::GzDecoder as Gz;
 use crate::util::memchr;
 
-/// A type that contains the contents of a `.tar.bz2` archive.
+// The number of bytes needed to detect any `ArchiveFormat`
+const MAGIC_LEN: usize = 6;
+
+// Returns whether the entry at a path should be unpacked
+type Filter<'a> = dyn FnMut(&Path) -> bool + 'a;
+
+/// A type that contains the contents of an archive.
 ///
 /// **Note:** requires the `archive` or `download` feature (the default).
 pub trait Archive: io::Read {
-    /// Attempts to unpack the contents of `self` as a `.tar.bz2` archive into
-    /// `dst_dir`.
+    /// Attempts to unpack the contents of `self` into `dst_dir`.
+    ///
+    /// The format of `self` is detected from its leading bytes. See
+    /// `ArchiveFormat` for which formats are supported.
     ///
     /// Certain Ruby archives are packaged incorrectly and so this works to get
     /// around that issue.
-    fn unpack(&mut self, dst_dir: impl AsRef<Path>) -> io::Result<()>;
+    #[inline]
+    fn unpack(&mut self, dst_dir: impl AsRef<Path>) -> io::Result<()> {
+        self.unpack_with(dst_dir, UnpackOptions::new())
+    }
+
+    /// Attempts to unpack the contents of `self` into `dst_dir` with
+    /// `options`.
+    fn unpack_with(
+        &mut self,
+        dst_dir: impl AsRef<Path>,
+        options: UnpackOptions,
+    ) -> io::Result<()>;
+
+    /// Attempts to unpack the contents of `self` into `dst_dir` with
+    /// `options`, decompressing on a separate thread.
+    ///
+    /// This allows for decompression and writing files to happen in parallel,
+    /// which is faster on multi-core machines.
+    fn unpack_threaded(
+        &mut self,
+        dst_dir: impl AsRef<Path>,
+        options: UnpackOptions,
+    ) -> io::Result<()>
+        where Self: Send;
+
+    /// Reads through all of the contents of `self` without unpacking them,
+    /// returning an error if the archive is malformed or truncated.
+    fn verify(&mut self) -> io::Result<()>;
+
+    /// Returns the entries of `self` without unpacking them.
+    fn entries_list(&mut self) -> io::Result<Vec<ArchiveEntry>>;
 }
 
 impl<R: io::Read + ?Sized> Archive for R {
     #[inline]
-    fn unpack(&mut self, dst_dir: impl AsRef<Path>) -> io::Result<()> {
-        _unpack(Tar::new(&mut Bz::new(self)), dst_dir.as_ref())
+    fn unpack_with(
+        &mut self,
+        dst_dir: impl AsRef<Path>,
+        mut options: UnpackOptions,
+    ) -> io::Result<()> {
+        let mut reader = self;
+        let dst_dir = long_path(dst_dir.as_ref())?;
+        _unpack_any(&mut reader, &dst_dir, &mut options)
+    }
+
+    #[inline]
+    fn unpack_threaded(
+        &mut self,
+        dst_dir: impl AsRef<Path>,
+        mut options: UnpackOptions,
+    ) -> io::Result<()>
+        where Self: Send
+    {
+        let mut reader = self;
+        let dst_dir = long_path(dst_dir.as_ref())?;
+        _unpack_threaded(&mut reader, &dst_dir, &mut options)
+    }
+
+    #[inline]
+    fn verify(&mut self) -> io::Result<()> {
+        let mut reader = self;
+        _verify(&mut reader)
+    }
+
+    #[inline]
+    fn entries_list(&mut self) -> io::Result<Vec<ArchiveEntry>> {
+        let mut reader = self;
+        _entries_list(&mut reader)
+    }
+}
+
+/// An entry in an archive, as returned by
+/// [`Archive::entries_list`](trait.Archive.html#tymethod.entries_list).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ArchiveEntry {
+    /// The path of the entry within the archive.
+    pub path: PathBuf,
+    /// The size of the entry's contents in bytes.
+    pub size: u64,
+    /// Whether the entry is a directory.
+    pub is_dir: bool,
+}
+
+/// Options for
+/// [`Archive::unpack_with`](trait.Archive.html#tymethod.unpack_with).
+pub struct UnpackOptions<'a> {
+    filter: Option<Box<Filter<'a>>>,
+    strip_components: usize,
+    symlinks: bool,
+    hardlinks: bool,
+    preserve_mtime: bool,
+    preserve_permissions: bool,
+    progress: Option<Box<dyn FnMut(u64, u64) + 'a>>,
+    entries: u64,
+    bytes: u64,
+}
+
+impl Default for UnpackOptions<'_> {
+    #[inline]
+    fn default() -> Self {
+        UnpackOptions {
+            filter: None,
+            strip_components: 0,
+            symlinks: true,
+            hardlinks: true,
+            preserve_mtime: true,
+            preserve_permissions: true,
+            progress: None,
+            entries: 0,
+            bytes: 0,
+        }
+    }
+}
+
+impl<'a> UnpackOptions<'a> {
+    /// Creates a new instance that unpacks all entries.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only unpacks the entries whose paths within the archive `f` returns
+    /// `true` for.
+    ///
+    /// `f` is called for every entry, including those within directories that
+    /// were skipped. For example, skipping Ruby's tests can be done with:
+    ///
+    /// ```
+    /// # use aloxide::archive::UnpackOptions;
+    /// let options = UnpackOptions::new().filter(|path| {
+    ///     path.iter().nth(1).map_or(true, |dir| dir != "test")
+    /// });
+    /// ```
+    #[inline]
+    pub fn filter<F>(mut self, f: F) -> Self
+        where F: FnMut(&Path) -> bool + 'a
+    {
+        self.filter = Some(Box::new(f));
+        self
+    }
+
+    /// Removes the first `n` components from the path of each entry, skipping
+    /// entries that have no components left.
+    ///
+    /// Ruby archives place all of their contents in a single `ruby-x.y.z`
+    /// directory, so passing 1 unpacks the sources directly into `dst_dir`.
+    #[inline]
+    pub fn strip_components(mut self, n: usize) -> Self {
+        self.strip_components = n;
+        self
+    }
+
+    /// Whether to create symbolic links as links rather than as copies of
+    /// what they point to.
+    ///
+    /// The default value is `true`. Only applies to tarballs.
+    #[inline]
+    pub fn symlinks(mut self, symlinks: bool) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Whether to create hard links as links rather than as copies of the
+    /// files they point to.
+    ///
+    /// The default value is `true`. If a hard link can't be created, the file
+    /// is copied. Only applies to tarballs.
+    #[inline]
+    pub fn hardlinks(mut self, hardlinks: bool) -> Self {
+        self.hardlinks = hardlinks;
+        self
+    }
+
+    /// Whether to set the modification time of each file to that stored in the
+    /// archive, rather than the time it was unpacked.
+    ///
+    /// The default value is `true`, which keeps `make` from considering
+    /// generated files such as `configure` out of date. Only applies to
+    /// tarballs.
+    #[inline]
+    pub fn preserve_mtime(mut self, preserve: bool) -> Self {
+        self.preserve_mtime = preserve;
+        self
+    }
+
+    /// Whether to set the permissions of each file to those stored in the
+    /// archive.
+    ///
+    /// The default value is `true`. Otherwise, files are made `0o755` if any
+    /// of their executable bits are set and `0o644` if not. In either case,
+    /// setuid, setgid, and sticky bits are never set.
+    #[inline]
+    pub fn preserve_permissions(mut self, preserve: bool) -> Self {
+        self.preserve_permissions = preserve;
+        self
+    }
+
+    /// Sets a callback for reporting unpack progress.
+    ///
+    /// The callback is passed the number of entries unpacked so far and the
+    /// number of bytes written for them. It is called after each entry.
+    #[inline]
+    pub fn progress<F>(mut self, f: F) -> Self
+        where F: FnMut(u64, u64) + 'a
+    {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    // Reports that an entry of `size` bytes was unpacked
+    fn unpacked(&mut self, size: u64) {
+        self.entries += 1;
+        self.bytes += size;
+        if let Some(progress) = &mut self.progress {
+            progress(self.entries, self.bytes);
+        }
+    }
+
+    // Returns the path relative to the destination at which the entry at
+    // `path` should be unpacked, if at all
+    fn dst_path(&mut self, path: &Path) -> Option<PathBuf> {
+        if let Some(filter) = &mut self.filter {
+            if !filter(path) {
+                return None;
+            }
+        }
+        self.strip(path)
+    }
+
+    // Removes the first `strip_components` components of `path`
+    fn strip(&self, path: &Path) -> Option<PathBuf> {
+        let stripped: PathBuf = path.components()
+            .skip(self.strip_components)
+            .collect();
+        if stripped.as_os_str().is_empty() {
+            None
+        } else {
+            Some(stripped)
+        }
+    }
+
+    // Sets the permissions of the unpacked file at `path` that had `mode` in
+    // the archive
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = if self.preserve_permissions {
+                mode & 0o777
+            } else if mode & 0o111 != 0 {
+                0o755
+            } else {
+                0o644
+            };
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+        let _ = (path, mode);
+        Ok(())
+    }
+}
+
+/// The format of an archive, as detected by its leading "magic" bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ArchiveFormat {
+    /// A gzip-compressed tarball (`.tar.gz`).
+    Gzip,
+    /// A bzip2-compressed tarball (`.tar.bz2`).
+    Bzip2,
+    /// An xz-compressed tarball (`.tar.xz`).
+    ///
+    /// **Note:** unpacking requires the `xz` feature.
+    Xz,
+    /// A zip archive (`.zip`).
+    ///
+    /// **Note:** unpacking requires the `zip` feature.
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Detects the format of an archive that starts with `bytes`.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1F, 0x8B]) {
+            Some(ArchiveFormat::Gzip)
+        } else if bytes.starts_with(b"BZh") {
+            Some(ArchiveFormat::Bzip2)
+        } else if bytes.starts_with(b"\xFD7zXZ\x00") {
+            Some(ArchiveFormat::Xz)
+        } else if bytes.starts_with(b"PK\x03\x04") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the file extension used for archives of this format.
+    #[inline]
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Gzip  => "tar.gz",
+            ArchiveFormat::Bzip2 => "tar.bz2",
+            ArchiveFormat::Xz    => "tar.xz",
+            ArchiveFormat::Zip   => "zip",
+        }
+    }
+
+    /// Returns whether archives of this format can be unpacked with the
+    /// features enabled for this crate.
+    #[inline]
+    pub fn is_supported(self) -> bool {
+        match self {
+            ArchiveFormat::Gzip | ArchiveFormat::Bzip2 => true,
+            ArchiveFormat::Xz => cfg!(feature = "xz"),
+            ArchiveFormat::Zip => cfg!(feature = "zip"),
+        }
+    }
+
+    // The error returned when unpacking `self` is not supported
+    fn unsupported(self) -> io::Error {
+        let feature = match self {
+            ArchiveFormat::Xz => "xz",
+            _ => "zip",
+        };
+        let mesg = format!(
+            "Unpacking '.{}' archives requires the `{}` feature of `aloxide`",
+            self.extension(),
+            feature,
+        );
+        io::Error::new(io::ErrorKind::Unsupported, mesg)
+    }
+}
+
+/// Packs the contents of `src_dir` into a tarball compressed with `format`,
+/// placing them in the `prefix` directory within the archive.
+///
+/// Symbolic links are stored as links. Packing zip archives is not supported.
+pub fn pack(
+    src_dir: impl AsRef<Path>,
+    prefix: impl AsRef<Path>,
+    dst: impl io::Write,
+    format: ArchiveFormat,
+) -> io::Result<()> {
+    _pack(src_dir.as_ref(), prefix.as_ref(), Box::new(dst), format)
+}
+
+fn _pack(
+    src_dir: &Path,
+    prefix: &Path,
+    dst: Box<dyn io::Write + '_>,
+    format: ArchiveFormat,
+) -> io::Result<()> {
+    let mut dst = match format {
+        ArchiveFormat::Gzip => {
+            use flate2::{write::GzEncoder, Compression};
+            let encoder = GzEncoder::new(dst, Compression::best());
+            write_tar(encoder, src_dir, prefix)?.finish()?
+        },
+        ArchiveFormat::Bzip2 => {
+            use bzip2::{write::BzEncoder, Compression};
+            let encoder = BzEncoder::new(dst, Compression::Best);
+            write_tar(encoder, src_dir, prefix)?.finish()?
+        },
+        #[cfg(feature = "xz")]
+        ArchiveFormat::Xz => {
+            let encoder = xz2::write::XzEncoder::new(dst, 6);
+            write_tar(encoder, src_dir, prefix)?.finish()?
+        },
+        ArchiveFormat::Zip => {
+            let mesg = "Packing '.zip' archives is not supported";
+            return Err(io::Error::new(io::ErrorKind::Unsupported, mesg));
+        },
+        #[allow(unreachable_patterns)]
+        format => return Err(format.unsupported()),
+    };
+    dst.flush()
+}
+
+// Writes a tarball of `src_dir` to `encoder` and returns it to be finished
+fn write_tar<W: io::Write>(
+    encoder: W,
+    src_dir: &Path,
+    prefix: &Path,
+) -> io::Result<W> {
+    let mut builder = tar::Builder::new(encoder);
+    builder.follow_symlinks(false);
+    builder.append_dir_all(prefix, src_dir)?;
+    builder.into_inner()
+}
+
+// Returns `path` with the `\\?\` prefix on Windows, which lifts the
+// `MAX_PATH` limit on the length of the paths of unpacked files
+fn long_path(path: &Path) -> io::Result<Cow<'_, Path>> {
+    if !cfg!(windows) {
+        return Ok(Cow::Borrowed(path));
+    }
+
+    // Extended-length paths must be absolute without `.` or `..`
+    let path = std::path::absolute(path)?;
+
+    let mut long = match path.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(_) => {
+                let mut long = OsString::from(r"\\?\");
+                long.push(prefix.as_os_str());
+                long
+            },
+            Prefix::UNC(server, share) => {
+                let mut long = OsString::from(r"\\?\UNC\");
+                long.push(server);
+                long.push(r"\");
+                long.push(share);
+                long
+            },
+            // Already extended-length or a device
+            _ => return Ok(Cow::Owned(path)),
+        },
+        _ => return Ok(Cow::Owned(path)),
+    };
+
+    let mut is_root = true;
+    for component in path.components() {
+        if let Component::Normal(part) = component {
+            long.push(r"\");
+            long.push(part);
+            is_root = false;
+        }
+    }
+    if is_root {
+        long.push(r"\");
+    }
+    Ok(Cow::Owned(long.into()))
+}
+
+// Fills as much of `buf` as possible before the end of `reader`
+fn read_up_to(reader: &mut dyn io::Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {},
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(len)
+}
+
+// Detects the format of `reader`, returning it along with the bytes read
+fn detect(reader: &mut dyn io::Read) -> io::Result<(ArchiveFormat, Vec<u8>)> {
+    let mut magic = [0; MAGIC_LEN];
+    let magic_len = read_up_to(reader, &mut magic)?;
+    let magic = &magic[..magic_len];
+
+    let format = match ArchiveFormat::detect(magic) {
+        Some(format) => format,
+        None => {
+            let mesg = "Unknown archive format \
+                        (expected gzip, bzip2, xz, or zip)";
+            return Err(io::Error::new(io::ErrorKind::InvalidData, mesg));
+        },
+    };
+    if !format.is_supported() {
+        return Err(format.unsupported());
+    }
+    Ok((format, magic.to_vec()))
+}
+
+// Returns a reader over the decompressed contents of `reader`, which for zip
+// archives is the archive itself
+fn decoder<'r, R>(format: ArchiveFormat, reader: R) -> Box<dyn io::Read + 'r>
+    where R: io::Read + 'r
+{
+    match format {
+        ArchiveFormat::Gzip => Box::new(Gz::new(reader)),
+        ArchiveFormat::Bzip2 => Box::new(Bz::new(reader)),
+        #[cfg(feature = "xz")]
+        ArchiveFormat::Xz => Box::new(xz2::read::XzDecoder::new(reader)),
+        _ => Box::new(reader),
+    }
+}
+
+// Detects the format of `reader` and returns a reader over its decompressed
+// contents
+fn decode<'r>(
+    reader: &'r mut dyn io::Read,
+) -> io::Result<(ArchiveFormat, Box<dyn io::Read + 'r>)> {
+    let (format, magic) = detect(reader)?;
+
+    // Put back the bytes used for detecting the format
+    let reader = io::Cursor::new(magic).chain(reader);
+
+    Ok((format, decoder(format, reader)))
+}
+
+fn _unpack_any(
+    reader: &mut dyn io::Read,
+    dst_dir: &Path,
+    options: &mut UnpackOptions,
+) -> io::Result<()> {
+    let (format, mut reader) = decode(reader)?;
+    match format {
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => _unpack_zip(&mut *reader, dst_dir, options),
+        _ => _unpack(Tar::new(&mut *reader), dst_dir, options),
+    }
+}
+
+fn _unpack_threaded(
+    reader: &mut (dyn io::Read + Send),
+    dst_dir: &Path,
+    options: &mut UnpackOptions,
+) -> io::Result<()> {
+    // The number of decompressed bytes sent at a time and the number of
+    // chunks that can be queued up before decompression waits
+    const CHUNK_LEN: usize = 64 * 1024;
+    const CHUNK_CAP: usize = 64;
+
+    let (format, magic) = detect(reader)?;
+    let (sender, receiver) = mpsc::sync_channel(CHUNK_CAP);
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            let reader = io::Cursor::new(magic).chain(reader);
+            let mut decoder = decoder(format, reader);
+            loop {
+                let mut chunk = vec![0; CHUNK_LEN];
+                let result = read_up_to(&mut decoder, &mut chunk)
+                    .map(|len| { chunk.truncate(len); chunk });
+                let done = !matches!(&result, Ok(chunk) if !chunk.is_empty());
+
+                // Stop if unpacking stopped early
+                if sender.send(result).is_err() || done {
+                    break;
+                }
+            }
+        });
+
+        let mut reader = ChannelReader {
+            receiver,
+            chunk: Vec::new(),
+            pos: 0,
+        };
+        match format {
+            #[cfg(feature = "zip")]
+            ArchiveFormat::Zip => _unpack_zip(&mut reader, dst_dir, options),
+            _ => _unpack(Tar::new(&mut reader), dst_dir, options),
+        }
+    })
+}
+
+// Reads the chunks decompressed by `_unpack_threaded`
+struct ChannelReader {
+    receiver: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl io::Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            self.chunk = match self.receiver.recv() {
+                Ok(chunk) => chunk?,
+                // The sender hung up after the last chunk
+                Err(_) => return Ok(0),
+            };
+            self.pos = 0;
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..(self.pos + len)]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+fn _verify(reader: &mut dyn io::Read) -> io::Result<()> {
+    let (format, mut reader) = decode(reader)?;
+    match format {
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => {
+            use zip::read::read_zipfile_from_stream;
+
+            // Reading each file to the end checks its CRC-32
+            while let Some(mut file) = read_zipfile_from_stream(&mut reader)? {
+                io::copy(&mut file, &mut io::sink())?;
+            }
+        },
+        _ => {
+            let mut archive = Tar::new(&mut *reader);
+            for entry in archive.entries()?.raw(true) {
+                io::copy(&mut entry?, &mut io::sink())?;
+            }
+
+            // Reach the end of the compressed stream so that a truncated
+            // trailer is caught
+            io::copy(&mut archive.into_inner(), &mut io::sink())?;
+        },
+    }
+    Ok(())
+}
+
+fn _entries_list(reader: &mut dyn io::Read) -> io::Result<Vec<ArchiveEntry>> {
+    let (format, mut reader) = decode(reader)?;
+    let mut list = Vec::new();
+    match format {
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => {
+            use zip::read::read_zipfile_from_stream;
+
+            while let Some(file) = read_zipfile_from_stream(&mut reader)? {
+                list.push(ArchiveEntry {
+                    path: file.mangled_name(),
+                    size: file.size(),
+                    is_dir: file.is_dir(),
+                });
+            }
+        },
+        _ => {
+            for entry in Tar::new(&mut *reader).entries()?.raw(true) {
+                let entry = entry?;
+                list.push(ArchiveEntry {
+                    path: entry.path()?.into_owned(),
+                    size: entry.size(),
+                    is_dir: is_dir(entry.header()),
+                });
+            }
+        },
     }
+    Ok(list)
 }
 
 fn _unpack(
     mut archive: Tar<&mut dyn io::Read>,
     dst_dir: &Path,
+    options: &mut UnpackOptions,
 ) -> io::Result<()> {
     let entries = archive.entries()?.raw(true);
 
+    // Links already on disk are followed when checking where entries go, so
+    // this must be the real path
+    fs::create_dir_all(dst_dir)?;
+    let real_dst_dir = fs::canonicalize(dst_dir)?;
+
     // Reuse the same allocation instead of calling `.join()`, which allocates
     // a new path each time
     let mut path_buf_os = OsString::from(dst_dir);
 
+    // Links that get copied, along with their targets; these are handled last
+    // in case their targets come later in the archive
+    let mut copies: Vec<(PathBuf, PathBuf, bool)> = Vec::new();
+
+    // Symbolic links unpacked so far, along with their resolved targets
+    let mut symlinks: HashMap<PathBuf, PathBuf> = HashMap::new();
+
     for entry in entries {
         let mut entry = entry?;
-        let header = entry.header();
+        entry.set_preserve_mtime(options.preserve_mtime);
 
-        let entry_path = entry.path()?;
+        let entry_type = entry.header().entry_type();
+        let is_hard_link = entry_type.is_hard_link();
+        let is_link = is_hard_link || entry_type.is_symlink();
+
+        let entry_path = match options.dst_path(&sanitize(&entry.path()?)?) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        // The target of a link relative to `dst_dir`
+        let link_target = if is_link {
+            let target = entry.link_name()?.unwrap_or_default();
+            let resolved = if is_hard_link {
+                options.strip(&sanitize(&target)?)
+                    .and_then(|target| resolve(&target, &symlinks))
+            } else {
+                let link = real_path(&entry_path, &symlinks);
+                let parent = link.parent().unwrap_or(Path::new(""));
+                resolve(&parent.join(&target), &symlinks)
+            };
+            match resolved {
+                Some(resolved) => Some(resolved),
+                None => {
+                    let mesg = format!(
+                        "Link '{}' points outside of the archive",
+                        entry_path.display(),
+                    );
+                    let kind = io::ErrorKind::InvalidData;
+                    return Err(io::Error::new(kind, mesg));
+                },
+            }
+        } else {
+            None
+        };
 
         let mut path_buf = PathBuf::from(path_buf_os);
         path_buf.push(&entry_path);
 
-        if is_dir(&header) {
+        // Catches links that were retargeted after being resolved above
+        if let Some(parent) = path_buf.parent() {
+            check_inside(parent, &real_dst_dir, &entry_path)?;
+        }
+
+        let size = if is_dir(entry.header()) {
             fs::create_dir_all(&path_buf)?;
+            0
         } else {
             if let Some(parent) = path_buf.parent() {
                 fs::create_dir_all(parent)?;
             }
-            entry.unpack(&path_buf)?;
-        }
+            match link_target {
+                Some(target) if is_hard_link || !options.symlinks => {
+                    let target = dst_dir.join(target);
+                    copies.push((path_buf.clone(), target, is_hard_link));
+                },
+                Some(target) => {
+                    entry.unpack(&path_buf)?;
+                    symlinks.insert(real_path(&entry_path, &symlinks), target);
+                },
+                _ => {
+                    symlinks.remove(&real_path(&entry_path, &symlinks));
+                    entry.unpack(&path_buf)?;
+                    if entry_type.is_file() {
+                        let mode = entry.header().mode()?;
+                        options.set_permissions(&path_buf, mode)?;
+                    }
+                },
+            }
+            entry.size()
+        };
+        options.unpacked(size);
 
         path_buf_os = path_buf.into_os_string();
         path_buf_os.clear();
         path_buf_os.push(dst_dir);
     }
 
+    for (link, target, is_hard_link) in copies {
+        // Replace whatever was previously unpacked at `link`
+        let _ = fs::remove_file(&link);
+
+        let linked = is_hard_link
+            && options.hardlinks
+            && fs::hard_link(&target, &link).is_ok();
+        if linked {
+            continue;
+        }
+        copy_all(&target, &link, &real_dst_dir)?;
+    }
+
     Ok(())
 }
 
+// Fails if `dir` is or would be created outside of the canonical `dst_dir`
+// after following the links that already exist on disk
+fn check_inside(dir: &Path, dst_dir: &Path, entry: &Path) -> io::Result<()> {
+    // Only the part of `dir` that exists can lead elsewhere
+    let existing = dir.ancestors().find_map(|dir| fs::canonicalize(dir).ok());
+    match existing {
+        Some(existing) if existing.starts_with(dst_dir) => Ok(()),
+        _ => {
+            let mesg = format!(
+                "Archive entry '{}' would unpack outside of destination",
+                entry.display(),
+            );
+            Err(io::Error::new(io::ErrorKind::InvalidData, mesg))
+        },
+    }
+}
+
+// Copies the file or directory at `src` to `dst`, failing if any copied file
+// resolves outside of `dst_dir`
+fn copy_all(src: &Path, dst: &Path, dst_dir: &Path) -> io::Result<()> {
+    let copy = |src: &Path, dst: &Path| {
+        if !fs::canonicalize(src)?.starts_with(dst_dir) {
+            let mesg = format!(
+                "Link '{}' points outside of the archive",
+                dst.display(),
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidData, mesg));
+        }
+        fs::copy(src, dst).map(drop)
+    };
+
+    if !fs::metadata(src)?.is_dir() {
+        return copy(src, dst);
+    }
+    crate::util::walk_files(src, |path| {
+        let dst = match path.strip_prefix(src) {
+            Ok(relative) => dst.join(relative),
+            Err(_) => return Ok(()),
+        };
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        copy(&path, &dst)
+    })
+}
+
+#[cfg(feature = "zip")]
+fn _unpack_zip(
+    mut reader: &mut dyn io::Read,
+    dst_dir: &Path,
+    options: &mut UnpackOptions,
+) -> io::Result<()> {
+    use zip::read::read_zipfile_from_stream;
+
+    while let Some(mut file) = read_zipfile_from_stream(&mut reader)? {
+        let path = match options.dst_path(&file.mangled_name()) {
+            Some(path) => dst_dir.join(path),
+            None => continue,
+        };
+
+        if file.is_dir() {
+            fs::create_dir_all(&path)?;
+            options.unpacked(0);
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let size = io::copy(&mut file, &mut fs::File::create(&path)?)?;
+
+        if let Some(mode) = file.unix_mode() {
+            options.set_permissions(&path, mode)?;
+        }
+
+        options.unpacked(size);
+    }
+    Ok(())
+}
+
+// Returns `path` without `.` components, failing if it's absolute or contains
+// `..` so that it can't be unpacked outside of the destination directory
+fn sanitize(path: &Path) -> io::Result<PathBuf> {
+    let mut sanitized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir => {},
+            Component::ParentDir |
+            Component::RootDir |
+            Component::Prefix(_) => {
+                let mesg = format!(
+                    "Archive entry '{}' would unpack outside of destination",
+                    path.display(),
+                );
+                return Err(io::Error::new(io::ErrorKind::InvalidData, mesg));
+            },
+        }
+    }
+    Ok(sanitized)
+}
+
+// Returns the location of `path` (relative to the destination) after following
+// `symlinks` and `..`, or `None` if it resolves outside of the destination
+//
+// Links are followed so that a link through an earlier link, such as `sub/..`
+// where `sub` points to `..`, can't escape
+fn resolve(
+    path: &Path,
+    symlinks: &HashMap<PathBuf, PathBuf>,
+) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => {
+                resolved.push(part);
+
+                // Targets are stored already resolved
+                if let Some(target) = symlinks.get(&resolved) {
+                    resolved = target.clone();
+                }
+            },
+            Component::CurDir => {},
+            Component::ParentDir => if !resolved.pop() {
+                return None;
+            },
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(resolved)
+}
+
+// Returns the location of the sanitized entry `path` after following the
+// `symlinks` of its parent directories
+fn real_path(path: &Path, symlinks: &HashMap<PathBuf, PathBuf>) -> PathBuf {
+    let parent = path.parent()
+        .and_then(|parent| resolve(parent, symlinks))
+        .unwrap_or_default();
+    match path.file_name() {
+        Some(name) => parent.join(name),
+        None => parent,
+    }
+}
+
 fn is_dir(header: &Header) -> bool {
     match header.entry_type() {
         // This fixes an issue in some Ruby archives (namely 2.6.0) where some
@@ -78,3 +940,283 @@ fn ends_with_slash(name: &[u8; 100]) -> bool {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_format() {
+        let formats = [
+            (Some(ArchiveFormat::Gzip),  &b"\x1F\x8B\x08\x00"[..]),
+            (Some(ArchiveFormat::Bzip2), &b"BZh91AY&SY"[..]),
+            (Some(ArchiveFormat::Xz),    &b"\xFD7zXZ\x00\x00"[..]),
+            (Some(ArchiveFormat::Zip),   &b"PK\x03\x04\x14\x00"[..]),
+            (None,                       &b"ruby-2.6.0/"[..]),
+            (None,                       &b""[..]),
+        ];
+        for (format, bytes) in &formats {
+            assert_eq!(*format, ArchiveFormat::detect(bytes));
+        }
+    }
+
+    #[test]
+    fn unpack_unknown() {
+        let dir = std::env::temp_dir().join("aloxide-unpack-unknown");
+        let error = (&b"not an archive"[..]).unpack(&dir).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(!dir.exists());
+    }
+
+    // Returns a `.tar.bz2` archive of `entries` without validating the paths
+    fn tar_bz2(entries: &[(&str, EntryType, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for &(path, entry_type, data) in entries {
+            let mut header = Header::new_old();
+            header.as_old_mut().name[..path.len()]
+                .copy_from_slice(path.as_bytes());
+            header.set_entry_type(entry_type);
+            let is_link = entry_type.is_symlink() || entry_type.is_hard_link();
+            let data = if is_link {
+                header.set_link_name(data).unwrap();
+                ""
+            } else {
+                data
+            };
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, data.as_bytes()).unwrap();
+        }
+        let tar = builder.into_inner().unwrap();
+
+        let mut encoder = bzip2::write::BzEncoder::new(
+            Vec::new(),
+            bzip2::Compression::Default,
+        );
+        io::Write::write_all(&mut encoder, &tar).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn unpack_error(name: &str, entries: &[(&str, EntryType, &str)]) {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        let archive = tar_bz2(entries);
+        let error = (&archive[..]).unpack(dir.join("dst")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(!dir.join("evil.txt").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unpack_sanitized() {
+        let dir = std::env::temp_dir().join("aloxide-unpack-sanitized");
+        let _ = fs::remove_dir_all(&dir);
+        let archive = tar_bz2(&[
+            ("./ruby/a.txt", EntryType::Regular, "a"),
+            ("ruby/link", EntryType::Symlink, "./a.txt"),
+        ]);
+        (&archive[..]).unpack(&dir).unwrap();
+        assert!(dir.join("ruby/a.txt").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unpack_filter() {
+        let dir = std::env::temp_dir().join("aloxide-unpack-filter");
+        let _ = fs::remove_dir_all(&dir);
+        let archive = tar_bz2(&[
+            ("ruby/a.txt", EntryType::Regular, "a"),
+            ("ruby/test/b.txt", EntryType::Regular, "b"),
+        ]);
+        let options = UnpackOptions::new()
+            .filter(|path| !path.starts_with("ruby/test"));
+        (&archive[..]).unpack_with(&dir, options).unwrap();
+        assert!(dir.join("ruby/a.txt").exists());
+        assert!(!dir.join("ruby/test").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unpack_threaded() {
+        let dir = std::env::temp_dir().join("aloxide-unpack-threaded");
+        let _ = fs::remove_dir_all(&dir);
+
+        // Large enough to be decompressed across multiple chunks
+        let data = "a".repeat(1 << 20);
+        let archive = tar_bz2(&[
+            ("ruby/a.txt", EntryType::Regular, &data),
+            ("ruby/b.txt", EntryType::Regular, "b"),
+        ]);
+        (&archive[..]).unpack_threaded(&dir, UnpackOptions::new()).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("ruby/a.txt")).unwrap(), data);
+        assert_eq!(fs::read_to_string(dir.join("ruby/b.txt")).unwrap(), "b");
+
+        // Errors from the unpacking thread are returned, which requires gzip
+        // since the `bzip2` decoder treats truncated input as the end
+        let mut tar = Vec::new();
+        io::Read::read_to_end(
+            &mut bzip2::read::BzDecoder::new(&archive[..]),
+            &mut tar,
+        ).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        );
+        io::Write::write_all(&mut encoder, &tar).unwrap();
+        let archive = encoder.finish().unwrap();
+        let mut truncated = &archive[..(archive.len() / 2)];
+        let dst = dir.join("truncated");
+        let result = truncated.unpack_threaded(dst, UnpackOptions::new());
+        assert!(result.is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unpack_strip_components() {
+        let dir = std::env::temp_dir().join("aloxide-unpack-strip");
+        let _ = fs::remove_dir_all(&dir);
+        let archive = tar_bz2(&[
+            ("ruby/", EntryType::Directory, ""),
+            ("ruby/a.txt", EntryType::Regular, "a"),
+        ]);
+        let options = UnpackOptions::new().strip_components(1);
+        (&archive[..]).unpack_with(&dir, options).unwrap();
+        assert!(dir.join("a.txt").exists());
+        assert!(!dir.join("ruby").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unpack_progress() {
+        let dir = std::env::temp_dir().join("aloxide-unpack-progress");
+        let _ = fs::remove_dir_all(&dir);
+        let archive = tar_bz2(&[
+            ("ruby/", EntryType::Directory, ""),
+            ("ruby/a.txt", EntryType::Regular, "a"),
+            ("ruby/test/b.txt", EntryType::Regular, "bb"),
+        ]);
+        let mut reports = Vec::new();
+        let options = UnpackOptions::new()
+            .filter(|path| !path.starts_with("ruby/test"))
+            .progress(|entries, bytes| reports.push((entries, bytes)));
+        (&archive[..]).unpack_with(&dir, options).unwrap();
+        assert_eq!(reports, [(1, 0), (2, 1)]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unpack_links() {
+        let dir = std::env::temp_dir().join("aloxide-unpack-links");
+        let _ = fs::remove_dir_all(&dir);
+        let archive = tar_bz2(&[
+            ("ruby/a/b.txt", EntryType::Regular, "b"),
+            ("ruby/c.txt", EntryType::Link, "ruby/a/b.txt"),
+            ("ruby/d", EntryType::Symlink, "a"),
+        ]);
+        let options = UnpackOptions::new()
+            .strip_components(1)
+            .symlinks(false);
+        (&archive[..]).unpack_with(&dir, options).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("c.txt")).unwrap(), "b");
+        assert_eq!(fs::read_to_string(dir.join("d/b.txt")).unwrap(), "b");
+        assert!(!fs::symlink_metadata(dir.join("d")).unwrap().is_symlink());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn pack_unpack() {
+        let dir = std::env::temp_dir().join("aloxide-pack");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/bin")).unwrap();
+        fs::write(dir.join("src/bin/ruby"), "ruby").unwrap();
+
+        let mut archive = Vec::new();
+        pack(dir.join("src"), "ruby-2.6.0", &mut archive, ArchiveFormat::Gzip)
+            .unwrap();
+        assert_eq!(ArchiveFormat::detect(&archive), Some(ArchiveFormat::Gzip));
+
+        (&archive[..]).unpack(dir.join("dst")).unwrap();
+        let ruby = dir.join("dst/ruby-2.6.0/bin/ruby");
+        assert_eq!(fs::read_to_string(ruby).unwrap(), "ruby");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unpack_parent_dir() {
+        unpack_error("aloxide-unpack-parent", &[
+            ("ruby/a.txt", EntryType::Regular, "a"),
+            ("ruby/../../evil.txt", EntryType::Regular, "evil"),
+        ]);
+    }
+
+    #[test]
+    fn unpack_absolute() {
+        let path = std::env::temp_dir().join("aloxide-unpack-absolute.txt");
+        unpack_error("aloxide-unpack-absolute", &[
+            (path.to_str().unwrap(), EntryType::Regular, "evil"),
+        ]);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn unpack_symlink_escape() {
+        unpack_error("aloxide-unpack-symlink", &[
+            ("ruby/link", EntryType::Symlink, "../.."),
+            ("ruby/link/evil.txt", EntryType::Regular, "evil"),
+        ]);
+    }
+
+    #[test]
+    fn unpack_chained_symlink_escape() {
+        // `ruby/l2` looks like it points to `ruby`, but `ruby/sub` is `dst`
+        unpack_error("aloxide-unpack-chained-symlink", &[
+            ("ruby/sub", EntryType::Symlink, ".."),
+            ("ruby/l2", EntryType::Symlink, "sub/.."),
+            ("ruby/l2/evil.txt", EntryType::Regular, "evil"),
+        ]);
+    }
+
+    #[test]
+    fn unpack_retargeted_symlink_escape() {
+        // `ruby/x` resolves to `ruby` until `ruby/y` is made to point to `dst`
+        unpack_error("aloxide-unpack-retargeted-symlink", &[
+            ("ruby/x", EntryType::Symlink, "y/.."),
+            ("ruby/y", EntryType::Symlink, ".."),
+            ("ruby/x/evil.txt", EntryType::Regular, "evil"),
+        ]);
+    }
+
+    #[test]
+    fn verify_truncated() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let data = vec![b'a'; 4096];
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, "ruby/a.txt", &data[..]).unwrap();
+        let tar = builder.into_inner().unwrap();
+
+        let mut encoder = flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        );
+        io::Write::write_all(&mut encoder, &tar).unwrap();
+        let archive = encoder.finish().unwrap();
+
+        (&archive[..]).verify().unwrap();
+        assert!((&archive[..(archive.len() - 4)]).verify().is_err());
+    }
+
+    #[test]
+    fn entries_list() {
+        let archive = tar_bz2(&[
+            ("ruby/", EntryType::Directory, ""),
+            ("ruby/a.txt", EntryType::Regular, "abc"),
+        ]);
+        assert_eq!((&archive[..]).entries_list().unwrap(), [
+            ArchiveEntry { path: "ruby".into(), size: 0, is_dir: true },
+            ArchiveEntry { path: "ruby/a.txt".into(), size: 3, is_dir: false },
+        ]);
+    }
+}
@@ -0,0 +1,165 @@
+use std::env;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+
+use crate::{
+    Ruby,
+    RubyExecError,
+    Version,
+    RubyLinkError,
+    RubySrc,
+    src::{build::RubyBuildError, RubySrcDownloadError},
+    version::{RubyVersionError, VersionParseError},
+};
+
+/// Downloads, builds, and links to Ruby from within a Cargo build script.
+///
+/// This is configured via the following environment variables:
+///
+/// - `ALOXIDE_RUBY_VERSION`: the version of Ruby to build. If not set, the
+///   current `ruby` found in `PATH` is linked to instead.
+///
+/// - `ALOXIDE_STATIC_RUBY` or `RUBY_STATIC`: if set to a value other than
+///   "0", "false", "no", or "off", Ruby is built and linked as a static
+///   library.
+///
+/// - `ALOXIDE_RUBY_CACHE`: the directory to cache downloaded sources in. If
+///   not set, the default [`Cache`](struct.Cache.html) directory is used.
+///
+/// The sources are downloaded to and built within `OUT_DIR` for `TARGET`,
+/// both of which are set by Cargo. Cargo is also told when to rerun the build
+/// script via
+/// [`Ruby::emit_cargo_directives`](struct.Ruby.html#method.emit_cargo_directives).
+///
+/// # Examples
+///
+/// Within the `main` function of `build.rs`:
+///
+/// ```rust,no_run
+/// aloxide::auto_build().expect("Failed to build Ruby");
+/// ```
+pub fn auto_build() -> Result<Ruby, AutoBuildError> {
+    let mut out = std::io::stdout();
+    let static_lib = crate::link::env_flag(&mut out, "ALOXIDE_STATIC_RUBY")
+        .or_else(|| crate::link::env_flag(&mut out, "RUBY_STATIC"))
+        .unwrap_or(false);
+
+    let ruby = match env::var_os("ALOXIDE_RUBY_VERSION") {
+        Some(version) => {
+            let version = version.to_str()
+                .ok_or(VersionParseError::InvalidUnicode)
+                .and_then(str::parse)
+                .map_err(AutoBuildError::ParseVersion)?;
+            build(&version, static_lib)?
+        },
+        None => Ruby::current().map_err(AutoBuildError::Version)?,
+    };
+
+    ruby.link(static_lib).map_err(AutoBuildError::Link)?;
+    ruby.emit_cargo_directives().map_err(AutoBuildError::Exec)?;
+    Ok(ruby)
+}
+
+fn build(
+    version: &Version,
+    static_lib: bool,
+) -> Result<Ruby, AutoBuildError> {
+    let out_dir = env::var_os("OUT_DIR")
+        .map(PathBuf::from)
+        .ok_or(AutoBuildError::MissingEnvVar("OUT_DIR"))?;
+    let target = env::var("TARGET")
+        .map_err(|_| AutoBuildError::MissingEnvVar("TARGET"))?;
+
+    let cache = env::var_os("ALOXIDE_RUBY_CACHE");
+    let mut downloader = RubySrc::downloader(version, &out_dir).cache();
+    if let Some(cache) = &cache {
+        downloader = downloader.cache_dir(cache);
+    }
+
+    let src = downloader.download().map_err(|error| {
+        AutoBuildError::Download(Box::new(error))
+    })?;
+
+    src.builder(out_dir.join(format!("ruby-{}-out", version)), target)
+        .configure()
+            .inherit_cc()
+            .inherit_c_flags()
+            .shared_lib(!static_lib)
+            .disable_install_doc()
+        .build()
+        .map_err(|error| AutoBuildError::Build(Box::new(error)))
+}
+
+/// The error returned by [`auto_build`](fn.auto_build.html).
+#[derive(Debug)]
+pub enum AutoBuildError {
+    /// An environment variable set by Cargo is missing.
+    MissingEnvVar(&'static str),
+    /// `ALOXIDE_RUBY_VERSION` could not be parsed.
+    ParseVersion(VersionParseError),
+    /// Failed to download Ruby's sources.
+    Download(Box<RubySrcDownloadError>),
+    /// Failed to build Ruby.
+    Build(Box<RubyBuildError>),
+    /// Failed to get the version of the current Ruby.
+    Version(RubyVersionError),
+    /// Failed to link to Ruby.
+    Link(RubyLinkError),
+    /// Failed to execute the `ruby` binary.
+    Exec(RubyExecError),
+}
+
+impl std::error::Error for AutoBuildError {}
+
+impl Display for AutoBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use AutoBuildError::*;
+        match self {
+            MissingEnvVar(var) => {
+                write!(f, "Missing environment variable `{}`", var)
+            },
+            ParseVersion(error) => {
+                write!(f, "Failed to parse `ALOXIDE_RUBY_VERSION`: {:?}", error)
+            },
+            Download(error) => {
+                write!(f, "Failed to download the sources: {:?}", error)
+            },
+            Build(error) => write!(f, "Failed to build Ruby: {}", error),
+            Version(error) => {
+                write!(f, "Failed to get the version of `ruby`: {:?}", error)
+            },
+            Link(error) => write!(f, "Failed to link to Ruby: {:?}", error),
+            Exec(error) => write!(f, "Failed to execute `ruby`: {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Both cases are checked in one test since the environment is shared
+    #[test]
+    fn env_vars() {
+        env::remove_var("OUT_DIR");
+
+        env::set_var("ALOXIDE_RUBY_VERSION", "three");
+        match auto_build() {
+            Err(AutoBuildError::ParseVersion(_)) => {},
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        env::set_var("ALOXIDE_RUBY_VERSION", "3.2.2");
+        let error = auto_build().unwrap_err();
+        match error {
+            AutoBuildError::MissingEnvVar("OUT_DIR") => {},
+            _ => panic!("unexpected error: {:?}", error),
+        }
+        assert_eq!(
+            error.to_string(),
+            "Missing environment variable `OUT_DIR`",
+        );
+
+        env::remove_var("ALOXIDE_RUBY_VERSION");
+    }
+}
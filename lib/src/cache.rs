@@ -0,0 +1,223 @@
+//! Management of the cache of downloaded Ruby archives.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::DiskUsage;
+
+/// A directory of cached Ruby archives.
+///
+/// The time an archive was last used is tracked via its modification time,
+/// which is updated whenever the archive is reused by
+/// [`RubySrcDownloader`](src/download/struct.RubySrcDownloader.html).
+///
+/// **Note:** requires the `download` feature (the default).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// An archive stored in a [`Cache`](struct.Cache.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheEntry {
+    /// The path of the archive.
+    pub path: PathBuf,
+    /// The size of the archive in bytes.
+    pub size: u64,
+    /// The last time the archive was downloaded or reused.
+    pub last_used: SystemTime,
+}
+
+impl Cache {
+    /// Creates a new instance for the cache in `dir`.
+    #[inline]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache { dir: dir.into() }
+    }
+
+    /// Returns the default cache, which is "aloxide" in the user's cache
+    /// directory.
+    #[inline]
+    pub fn user() -> Option<Self> {
+        let mut dir = dirs::cache_dir()?;
+        dir.push("aloxide");
+        Some(Cache::new(dir))
+    }
+
+    /// Returns the directory of the cache.
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns all archives in the cache, least recently used first.
+    pub fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(entries);
+            },
+            Err(error) => return Err(error),
+        };
+
+        for entry in read_dir {
+            let entry = entry?;
+            let path = entry.path();
+
+            // Skip files used by downloads in progress
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("lock") | Some("part") => continue,
+                _ => {},
+            }
+
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            entries.push(CacheEntry {
+                path,
+                size: metadata.len(),
+                last_used: metadata.modified()?,
+            });
+        }
+
+        entries.sort_by_key(|entry| entry.last_used);
+        Ok(entries)
+    }
+
+    /// Returns the total size of all archives in the cache in bytes.
+    pub fn size(&self) -> io::Result<u64> {
+        Ok(self.entries()?.iter().map(|entry| entry.size).sum())
+    }
+
+    /// Returns the disk space used by each entry in the cache, including
+    /// partial downloads.
+    ///
+    /// Unlike [`size`](#method.size), this also includes files other than
+    /// archives.
+    pub fn disk_usage(&self) -> io::Result<DiskUsage> {
+        match crate::usage::dir_usage(&self.dir) {
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                Ok(DiskUsage::default())
+            },
+            result => result,
+        }
+    }
+
+    /// Marks the archive at `path` as having just been used.
+    pub fn mark_used(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_modified(SystemTime::now())
+    }
+
+    /// Removes the least recently used archives until the cache is at most
+    /// `max_size` bytes, returning the paths of the removed archives.
+    #[inline]
+    pub fn evict(&self, max_size: u64) -> io::Result<Vec<PathBuf>> {
+        self.evict_except(max_size, None)
+    }
+
+    // Evicts archives other than `keep`
+    pub(crate) fn evict_except(
+        &self,
+        max_size: u64,
+        keep: Option<&Path>,
+    ) -> io::Result<Vec<PathBuf>> {
+        let entries = self.entries()?;
+        let mut size: u64 = entries.iter().map(|entry| entry.size).sum();
+        let mut removed = Vec::new();
+
+        for entry in entries {
+            if size <= max_size {
+                break;
+            }
+            if Some(entry.path.as_path()) == keep {
+                continue;
+            }
+            fs::remove_file(&entry.path)?;
+            size -= entry.size;
+            removed.push(entry.path);
+        }
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // Creates a cache in a fresh temp dir with archives of the given sizes,
+    // the first being the least recently used
+    fn cache(name: &str, sizes: &[usize]) -> (Cache, Vec<PathBuf>) {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let start = SystemTime::now() - Duration::from_secs(3600);
+        let paths = sizes.iter().enumerate().map(|(i, &size)| {
+            let path = dir.join(format!("ruby-3.{}.0.tar.gz", i));
+            fs::write(&path, vec![0; size]).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(start + Duration::from_secs(i as u64 * 60))
+                .unwrap();
+            path
+        }).collect();
+
+        (Cache::new(dir), paths)
+    }
+
+    #[test]
+    fn entries() {
+        let (cache, paths) = cache("aloxide-cache-entries", &[10, 20, 30]);
+
+        // Files of downloads in progress are not archives
+        fs::write(cache.dir().join("ruby-3.3.0.tar.gz.part"), [0; 40]).unwrap();
+        fs::write(cache.dir().join("ruby-3.3.0.tar.gz.lock"), "").unwrap();
+        fs::create_dir(cache.dir().join("ruby-3.3.0")).unwrap();
+
+        let entries = cache.entries().unwrap();
+        let entry_paths: Vec<_> = entries.iter().map(|e| &e.path).collect();
+        assert_eq!(entry_paths, paths.iter().collect::<Vec<_>>());
+        assert_eq!(cache.size().unwrap(), 60);
+
+        // Using the oldest archive makes it the most recently used
+        cache.mark_used(&paths[0]).unwrap();
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.last().unwrap().path, paths[0]);
+
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn evict() {
+        let (cache, paths) = cache("aloxide-cache-evict", &[10, 20, 30]);
+        let part = cache.dir().join("ruby-3.3.0.tar.gz.part");
+        fs::write(&part, [0; 40]).unwrap();
+
+        // Already fits
+        assert!(cache.evict(60).unwrap().is_empty());
+
+        // Removes least recently used archives first
+        assert_eq!(cache.evict(50).unwrap(), &paths[..1]);
+        assert_eq!(cache.size().unwrap(), 50);
+
+        // Skips the archive being kept
+        let removed = cache.evict_except(20, Some(&paths[1])).unwrap();
+        assert_eq!(removed, &paths[2..]);
+        assert_eq!(cache.size().unwrap(), 20);
+        assert!(part.exists());
+
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Command;
+
+use crate::{ExecResult, Ruby, RubyExecError};
+
+/// A builder for a single invocation of `ruby`, as returned by
+/// [`Ruby::command`](struct.Ruby.html#method.command).
+///
+/// This allows for setting environment variables such as `RUBYOPT` or
+/// `GEM_HOME` without modifying the environment of the current process.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # let ruby: aloxide::Ruby = unimplemented!();
+/// let gems = ruby.command()
+///     .env("GEM_HOME", "path/to/gems")
+///     .script("print Gem.dir")
+///     .exec()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RubyCommand {
+    command: Command,
+}
+
+impl RubyCommand {
+    #[inline]
+    pub(crate) fn new(ruby: &Ruby) -> Self {
+        RubyCommand { command: Command::new(ruby.bin_path()) }
+    }
+
+    /// Adds `arg` to pass into `ruby`.
+    #[inline]
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.command.arg(arg);
+        self
+    }
+
+    /// Adds `args` to pass into `ruby`.
+    #[inline]
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item=S>,
+        S: AsRef<OsStr>,
+    {
+        self.command.args(args);
+        self
+    }
+
+    /// Adds `script` to run via `-e`.
+    #[inline]
+    pub fn script(self, script: impl AsRef<OsStr>) -> Self {
+        self.arg("-e").arg(script)
+    }
+
+    /// Sets the environment variable `key` to `val`.
+    #[inline]
+    pub fn env(
+        mut self,
+        key: impl AsRef<OsStr>,
+        val: impl AsRef<OsStr>,
+    ) -> Self {
+        self.command.env(key, val);
+        self
+    }
+
+    /// Sets multiple environment variables.
+    #[inline]
+    pub fn envs<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item=(K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.command.envs(vars);
+        self
+    }
+
+    /// Removes the environment variable `key`.
+    #[inline]
+    pub fn env_remove(mut self, key: impl AsRef<OsStr>) -> Self {
+        self.command.env_remove(key);
+        self
+    }
+
+    /// Sets the working directory.
+    #[inline]
+    pub fn current_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.command.current_dir(dir);
+        self
+    }
+
+    /// Runs `ruby` and returns its `stdout`, failing if it exits
+    /// unsuccessfully.
+    #[inline]
+    pub fn exec(mut self) -> Result<String, RubyExecError> {
+        RubyExecError::process(&mut self.command)
+    }
+
+    /// Runs `ruby` and returns its output and exit status.
+    ///
+    /// See [`Ruby::exec_full`](struct.Ruby.html#method.exec_full).
+    #[inline]
+    pub fn exec_full(mut self) -> Result<ExecResult, RubyExecError> {
+        ExecResult::from_output(self.command.output()?)
+    }
+}
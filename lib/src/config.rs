@@ -0,0 +1,430 @@
+//! Ruby's build configuration.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Prints each key and value of `RbConfig::CONFIG` terminated by a null byte
+pub(crate) const DUMP_SCRIPT: &str =
+    "RbConfig::CONFIG.each { |k, v| print k, \"\\0\", v, \"\\0\" }";
+
+/// The contents of `RbConfig::CONFIG` for a Ruby installation, as returned by
+/// [`Ruby::config`](../struct.Ruby.html#method.config).
+///
+/// Unlike [`Ruby::get_config`](../struct.Ruby.html#method.get_config), which
+/// runs `ruby` for each value, all values are retrieved at once.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct RbConfig {
+    map: BTreeMap<String, String>,
+}
+
+impl RbConfig {
+    // Parses the output of `DUMP_SCRIPT`
+    pub(crate) fn parse(dump: &str) -> Self {
+        let mut parts = dump.split('\0');
+        let mut map = BTreeMap::new();
+        while let (Some(key), Some(val)) = (parts.next(), parts.next()) {
+            map.insert(key.to_owned(), val.to_owned());
+        }
+        RbConfig { map }
+    }
+
+    pub(crate) fn from_map(map: BTreeMap<String, String>) -> Self {
+        RbConfig { map }
+    }
+
+    // Reads the `rbconfig.rb` installed in `prefix` without running `ruby`,
+    // which may not run on this machine, such as when cross compiling
+    pub(crate) fn read_installed(prefix: &Path) -> Option<Self> {
+        let path = find_rbconfig(prefix)?;
+        let rbconfig = fs::read_to_string(&path).ok()?;
+        Some(RbConfig::parse_rb(&rbconfig, prefix, path.parent()?))
+    }
+
+    // Parses the `CONFIG["key"] = "value"` lines of `rbconfig.rb` and expands
+    // the values as `RbConfig::CONFIG` does, where `topdir` is the directory
+    // of the file
+    pub(crate) fn parse_rb(
+        rbconfig: &str,
+        prefix: &Path,
+        topdir: &Path,
+    ) -> Self {
+        let mut raw = BTreeMap::new();
+        for line in rbconfig.lines() {
+            let line = match line.trim().strip_prefix("CONFIG[\"") {
+                Some(line) => line,
+                None => continue,
+            };
+            let (key, val) = match line.split_once("\"] = ") {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let val = match val {
+                // Set relative to where `rbconfig.rb` is installed
+                _ if key == "prefix" => prefix.display().to_string(),
+                _ if key == "topdir" => topdir.display().to_string(),
+                "DESTDIR" => String::new(),
+                _ => match parse_ruby_str(val) {
+                    Some(val) => val,
+                    None => continue,
+                },
+            };
+            raw.insert(key.to_owned(), val);
+        }
+
+        let map = raw.iter()
+            .map(|(key, val)| (key.clone(), expand(val, &raw, 0)))
+            .collect();
+        RbConfig { map }
+    }
+
+    /// Returns the value for `key`.
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.map.get(key).map(String::as_str)
+    }
+
+    /// Returns the value for `key` as a path, if it's non-empty.
+    #[inline]
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        self.get(key).filter(|val| !val.is_empty()).map(PathBuf::from)
+    }
+
+    /// Returns the value for `key` as a boolean.
+    ///
+    /// Values such as `ENABLE_SHARED` are "yes" or "no". This also accepts
+    /// "true"/"false" and "1"/"0", with an empty value being `false`. Any
+    /// other value results in `None`.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            "yes" | "true" | "1" => Some(true),
+            "no" | "false" | "0" | "" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Returns the value for `key` split into words as a shell would, such
+    /// as for `CFLAGS` or `LIBRUBYARG_STATIC`.
+    ///
+    /// Quotes and backslash escapes are removed.
+    #[inline]
+    pub fn get_flags(&self, key: &str) -> Option<Vec<String>> {
+        self.get(key).map(split_flags)
+    }
+
+    /// Returns whether there is a value for `key`.
+    #[inline]
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Returns an iterator over the keys and values, ordered by key.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.map.iter().map(|(key, val)| (key.as_str(), val.as_str()))
+    }
+
+    /// Returns the number of values.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns whether there are no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the `include` directory.
+    #[inline]
+    pub fn include_dir(&self) -> Option<&str> {
+        self.get("includedir")
+    }
+
+    /// Returns the directory containing the Ruby library's main header files.
+    #[inline]
+    pub fn header_dir(&self) -> Option<&str> {
+        self.get("rubyhdrdir")
+    }
+
+    /// Returns the directory containing the Ruby library's
+    /// architecture-specific header files.
+    #[inline]
+    pub fn arch_header_dir(&self) -> Option<&str> {
+        self.get("rubyarchhdrdir")
+    }
+
+    /// Returns the name of the Ruby library.
+    #[inline]
+    pub fn lib_name(&self, static_lib: bool) -> Option<String> {
+        let mut name = self.get("RUBY_SO_NAME")?.to_owned();
+        if static_lib {
+            name.push_str("-static");
+        }
+        Some(name)
+    }
+
+    /// Returns the value of `LIBRUBYARG`.
+    #[inline]
+    pub fn lib_args(&self) -> Option<&str> {
+        self.get("LIBRUBYARG")
+    }
+
+    /// Returns the value of `LIBS`.
+    #[inline]
+    pub fn libs(&self) -> Option<&str> {
+        self.get("LIBS")
+    }
+
+    /// Returns the value of `MAINLIBS`.
+    #[inline]
+    pub fn main_libs(&self) -> Option<&str> {
+        self.get("MAINLIBS")
+    }
+
+    /// Returns the value of `SOLIBS`.
+    ///
+    /// The returned value is a list of shared object libraries.
+    #[inline]
+    pub fn so_libs(&self) -> Option<&str> {
+        self.get("SOLIBS")
+    }
+
+    // Writes every key and value as `pub const RBCONFIG: &[(&str, &str)]`
+    pub(crate) fn write_rust_table(&self, out: &mut String) {
+        out.push_str("/// The contents of `RbConfig::CONFIG`.\n");
+        out.push_str("pub const RBCONFIG: &[(&str, &str)] = &[\n");
+        for (key, val) in self.iter() {
+            out.push_str(&format!("    ({:?}, {:?}),\n", key, val));
+        }
+        out.push_str("];\n");
+    }
+
+    /// The auxiliary libraries that should be dynamically linked to.
+    #[inline]
+    pub fn aux_libs(&self, static_lib: bool) -> Option<&str> {
+        if static_lib {
+            // Link to the same libraries as the main `ruby` program
+            self.main_libs()
+        } else {
+            self.libs()
+        }
+    }
+}
+
+// Returns the `rbconfig.rb` in `prefix/lib/ruby/{version}/{arch}`
+fn find_rbconfig(prefix: &Path) -> Option<PathBuf> {
+    let subdirs = |dir: &Path| -> Option<Vec<PathBuf>> {
+        let mut dirs: Vec<PathBuf> = fs::read_dir(dir).ok()?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.is_dir())
+            .collect();
+        dirs.sort();
+        Some(dirs)
+    };
+    subdirs(&prefix.join("lib").join("ruby"))?
+        .iter()
+        .filter_map(|version| subdirs(version))
+        .flatten()
+        .map(|arch| arch.join("rbconfig.rb"))
+        .find(|path| path.is_file())
+}
+
+// Parses a single or double-quoted Ruby string literal
+fn parse_ruby_str(literal: &str) -> Option<String> {
+    let mut chars = literal.trim().chars();
+    let quote = chars.next().filter(|&c| c == '"' || c == '\'')?;
+
+    let mut val = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            _ if c == quote => return Some(val),
+            '\\' => match chars.next()? {
+                'n' if quote == '"' => val.push('\n'),
+                't' if quote == '"' => val.push('\t'),
+                c if quote == '"' || c == '\\' || c == '\'' => val.push(c),
+                c => {
+                    val.push('\\');
+                    val.push(c);
+                },
+            },
+            c => val.push(c),
+        }
+    }
+    None
+}
+
+// Replaces each `$(key)` and `${key}` in `val` with the expanded value for
+// `key` in `raw` and each `$$` with `$`, like `RbConfig.expand`
+fn expand(val: &str, raw: &BTreeMap<String, String>, depth: usize) -> String {
+    // Guards against values that refer to themselves
+    const MAX_DEPTH: usize = 32;
+
+    let mut expanded = String::with_capacity(val.len());
+    let mut rest = val;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let close = match rest.as_bytes().get(1) {
+            Some(b'(') => ')',
+            Some(b'{') => '}',
+            next => {
+                // `$$` is an escaped `$`
+                let len = if next == Some(&b'$') { 2 } else { 1 };
+                expanded.push('$');
+                rest = &rest[len..];
+                continue;
+            },
+        };
+        let end = match rest.find(close) {
+            Some(end) => end,
+            None => break,
+        };
+        match raw.get(&rest[2..end]) {
+            Some(val) if depth < MAX_DEPTH => {
+                expanded.push_str(&expand(val, raw, depth + 1));
+            },
+            _ => expanded.push_str(&rest[..=end]),
+        }
+        rest = &rest[(end + 1)..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+// Splits `flags` into words like a POSIX shell, without expansions
+fn split_flags(flags: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = flags.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => {
+                let word = word.get_or_insert_with(String::new);
+                match chars.next() {
+                    Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                    Some(c) => {
+                        word.push('\\');
+                        word.push(c);
+                    },
+                    None => word.push('\\'),
+                }
+            },
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            },
+            (None, '\\') => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.next());
+            },
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let dump = "RUBY_SO_NAME\0ruby\0LIBS\0\0SOLIBS\0-lm -lc\0";
+        let config = RbConfig::parse(dump);
+        assert_eq!(config.len(), 3);
+        assert_eq!(config.lib_name(true).unwrap(), "ruby-static");
+        assert_eq!(config.libs(), Some(""));
+        assert_eq!(config.so_libs(), Some("-lm -lc"));
+        assert_eq!(config.get("MAINLIBS"), None);
+
+        assert!(RbConfig::parse("").is_empty());
+    }
+
+    #[test]
+    fn typed() {
+        let dump = "ENABLE_SHARED\0yes\0prefix\0/usr\0CFLAGS\0-O3 -g\0";
+        let config = RbConfig::parse(dump);
+        assert_eq!(config.get_bool("ENABLE_SHARED"), Some(true));
+        assert_eq!(config.get_path("prefix"), Some(PathBuf::from("/usr")));
+        assert_eq!(config.get_flags("CFLAGS").unwrap(), ["-O3", "-g"]);
+        assert_eq!(config.get_bool("CFLAGS"), None);
+    }
+
+    #[test]
+    fn rust_table() {
+        let config = RbConfig::parse("prefix\0/usr\0CFLAGS\0-D\"x\"\0");
+        let mut out = String::new();
+        config.write_rust_table(&mut out);
+        assert_eq!(out, concat!(
+            "/// The contents of `RbConfig::CONFIG`.\n",
+            "pub const RBCONFIG: &[(&str, &str)] = &[\n",
+            "    (\"CFLAGS\", \"-D\\\"x\\\"\"),\n",
+            "    (\"prefix\", \"/usr\"),\n",
+            "];\n",
+        ));
+    }
+
+    #[test]
+    fn parse_rb() {
+        let rbconfig = r#"
+  TOPDIR = File.dirname(__FILE__).chomp!("/lib/ruby/3.2.0/aarch64-linux")
+  DESTDIR = '' unless defined? DESTDIR
+  CONFIG = {}
+  CONFIG["DESTDIR"] = DESTDIR
+  CONFIG["MAJOR"] = "3"
+  CONFIG["INSTALL"] = '/usr/bin/install -c'
+  CONFIG["prefix"] = (TOPDIR || DESTDIR + "/usr/local")
+  CONFIG["RUBY_BASE_NAME"] = "ruby"
+  CONFIG["RUBY_SO_NAME"] = "$(RUBY_BASE_NAME)"
+  CONFIG["LIBRUBY_A"] = "lib$(RUBY_SO_NAME)-static.a"
+  CONFIG["libdir"] = "${exec_prefix}/lib"
+  CONFIG["exec_prefix"] = "$(prefix)"
+  CONFIG["configure_args"] = " '--with-opt=\"$$x\"' $(UNKNOWN)"
+  CONFIG["loop"] = "$(loop)"
+  CONFIG["topdir"] = File.dirname(__FILE__)
+  CONFIG.each_value do |val|
+"#;
+        let config = RbConfig::parse_rb(
+            rbconfig,
+            Path::new("/opt/ruby"),
+            Path::new("/opt/ruby/lib/ruby/3.2.0/aarch64-linux"),
+        );
+        assert_eq!(config.get("DESTDIR"), Some(""));
+        assert_eq!(config.get("MAJOR"), Some("3"));
+        assert_eq!(config.get("INSTALL"), Some("/usr/bin/install -c"));
+        assert_eq!(config.get("LIBRUBY_A"), Some("libruby-static.a"));
+        assert_eq!(config.get("libdir"), Some("/opt/ruby/lib"));
+        assert_eq!(
+            config.get("configure_args"),
+            Some(" '--with-opt=\"$x\"' $(UNKNOWN)"),
+        );
+        assert_eq!(config.get("loop"), Some("$(loop)"));
+        assert_eq!(
+            config.get_path("topdir").unwrap(),
+            Path::new("/opt/ruby/lib/ruby/3.2.0/aarch64-linux"),
+        );
+        assert_eq!(config.len(), 12);
+    }
+
+    #[test]
+    fn flags() {
+        let flags = r#" -lruby  -Wl,-rpath,'/my dir' "a\"b" c\ d '' "#;
+        assert_eq!(
+            split_flags(flags),
+            ["-lruby", "-Wl,-rpath,/my dir", "a\"b", "c d", ""],
+        );
+        assert!(split_flags("  ").is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::Version;
+
+// Returns the `ruby` executables that may exist on this machine, which may
+// include duplicates
+pub fn candidates() -> Vec<PathBuf> {
+    let bin_name = crate::Ruby::bin_name();
+    let mut bins = Vec::new();
+
+    if let Some(path) = env::var_os("PATH") {
+        bins.extend(env::split_paths(&path).map(|dir| dir.join(bin_name)));
+    }
+
+    // Directories containing an installation per version
+    let mut version_dirs = rubies_dirs();
+    version_dirs.extend(vec![
+        PathBuf::from("/opt/homebrew/Cellar/ruby"),
+        PathBuf::from("/usr/local/Cellar/ruby"),
+    ]);
+    if let Some(home) = home_dir() {
+        version_dirs.extend(vec![
+            home.join(".rvm/rubies"),
+            home.join(".rbenv/versions"),
+        ]);
+    }
+    if let Some(rbenv_root) = env::var_os("RBENV_ROOT") {
+        version_dirs.push(Path::new(&rbenv_root).join("versions"));
+    }
+    if let Some(asdf_dir) = asdf_dir() {
+        version_dirs.push(asdf_dir.join("installs/ruby"));
+    }
+
+    for dir in version_dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            bins.push(entry.path().join("bin").join(bin_name));
+        }
+    }
+
+    // Homebrew's unversioned `ruby` formula
+    for prefix in &["/opt/homebrew", "/usr/local"] {
+        bins.push(Path::new(prefix).join("opt/ruby/bin").join(bin_name));
+    }
+
+    bins.retain(|bin| bin.is_file());
+    bins
+}
+
+// The directories used by chruby and ruby-install
+pub fn rubies_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(home) = home_dir() {
+        dirs.push(home.join(".rubies"));
+    }
+    dirs.push(PathBuf::from("/opt/rubies"));
+    dirs
+}
+
+// Finds the installation of `version` within `rubies_dirs`, preferring exact
+// matches of names like "ruby-3.2.2" over ones like "ruby-3.2.2-p53"
+pub fn find_in_rubies_dirs(version: &Version) -> Option<PathBuf> {
+    let mut fuzzy = None;
+    for dir in rubies_dirs() {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            match name.to_str().map(|name| match_name(name, version)) {
+                Some(Some(true)) => return Some(entry.path()),
+                Some(Some(false)) if fuzzy.is_none() => {
+                    fuzzy = Some(entry.path());
+                },
+                _ => {},
+            }
+        }
+    }
+    fuzzy
+}
+
+// Returns whether `name` matches `version` exactly, or `None` if it doesn't
+// match at all
+fn match_name(name: &str, version: &Version) -> Option<bool> {
+    let name = name.strip_prefix("ruby-").unwrap_or(name);
+    if name.parse::<Version>().ok().as_ref() == Some(version) {
+        return Some(true);
+    }
+    let rest = name.strip_prefix(&version.to_string())?;
+    if rest.starts_with(['-', '.', '_']) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+// The directory where `asdf` keeps its installations
+pub fn asdf_dir() -> Option<PathBuf> {
+    match env::var_os("ASDF_DATA_DIR") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => Some(home_dir()?.join(".asdf")),
+    }
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rubies_dir_name() {
+        let version = Version::new(3, 2, 2);
+        assert_eq!(match_name("ruby-3.2.2", &version), Some(true));
+        assert_eq!(match_name("3.2.2", &version), Some(true));
+        assert_eq!(match_name("ruby-3.2.2-p53", &version), Some(false));
+        assert_eq!(match_name("ruby-3.2.20", &version), None);
+        assert_eq!(match_name("jruby-3.2.2", &version), None);
+    }
+}
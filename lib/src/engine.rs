@@ -0,0 +1,77 @@
+//! Ruby implementations.
+
+use std::fmt;
+
+/// A Ruby implementation, as identified by `RUBY_ENGINE`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Engine {
+    /// The reference implementation, also known as CRuby.
+    Mri,
+    /// [JRuby](https://www.jruby.org), which runs on the JVM.
+    JRuby,
+    /// [TruffleRuby](https://github.com/oracle/truffleruby), which runs on
+    /// GraalVM.
+    TruffleRuby,
+    /// Any other implementation.
+    Other(String),
+}
+
+impl fmt::Display for Engine {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl From<&str> for Engine {
+    #[inline]
+    fn from(name: &str) -> Self {
+        match name {
+            "ruby" => Engine::Mri,
+            "jruby" => Engine::JRuby,
+            "truffleruby" => Engine::TruffleRuby,
+            other => Engine::Other(other.to_owned()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Engine {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Engine {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        let name = String::deserialize(deserializer)?;
+        Ok(Engine::from(name.as_str()))
+    }
+}
+
+impl Engine {
+    /// Returns the value of `RUBY_ENGINE` for `self`.
+    #[inline]
+    pub fn name(&self) -> &str {
+        match self {
+            Engine::Mri => "ruby",
+            Engine::JRuby => "jruby",
+            Engine::TruffleRuby => "truffleruby",
+            Engine::Other(name) => name,
+        }
+    }
+
+    /// Returns whether `self` is the reference implementation, which is the
+    /// only one that can be linked to.
+    #[inline]
+    pub fn is_mri(&self) -> bool {
+        *self == Engine::Mri
+    }
+}
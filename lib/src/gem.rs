@@ -0,0 +1,108 @@
+//! Ruby gems installed for a Ruby installation.
+
+use std::fmt;
+
+use crate::{ExecResult, RubyExecError};
+
+// Prints the name, version, and whether each gem is a default gem,
+// terminated by null bytes
+pub(crate) const LIST_SCRIPT: &str = "\
+Gem::Specification.each do |s|
+  d = s.respond_to?(:default_gem?) && s.default_gem?
+  print s.name, \"\\0\", s.version, \"\\0\", d ? 1 : 0, \"\\0\"
+end";
+
+/// A gem installed for a Ruby installation, as returned by
+/// [`Ruby::gems`](../struct.Ruby.html#method.gems).
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Gem {
+    /// The name of the gem.
+    pub name: String,
+    /// The version of the gem, such as "2.1.4" or "1.0.0.pre".
+    pub version: String,
+    /// Whether the gem is a default gem, which is bundled with Ruby and
+    /// cannot be uninstalled.
+    pub default: bool,
+}
+
+impl Gem {
+    // Parses the output of `LIST_SCRIPT`
+    pub(crate) fn parse_list(list: &str) -> Vec<Gem> {
+        let mut parts = list.split('\0');
+        let mut gems = Vec::new();
+        while let (Some(name), Some(version), Some(default)) =
+            (parts.next(), parts.next(), parts.next())
+        {
+            gems.push(Gem {
+                name: name.to_owned(),
+                version: version.to_owned(),
+                default: default == "1",
+            });
+        }
+        gems
+    }
+}
+
+/// The error returned by
+/// [`Ruby::bundle_install`](../struct.Ruby.html#method.bundle_install).
+#[derive(Debug)]
+pub enum BundleInstallError {
+    /// Bundler is not installed in the Ruby installation's `bin` directory.
+    MissingBundler,
+    /// Failed to execute `bundle`.
+    Exec(RubyExecError),
+    /// `bundle install` exited unsuccessfully.
+    Fail(ExecResult),
+}
+
+impl std::error::Error for BundleInstallError {}
+
+impl fmt::Display for BundleInstallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BundleInstallError::MissingBundler => {
+                write!(f, "Bundler is not installed")
+            },
+            BundleInstallError::Exec(error) => error.fmt(f),
+            BundleInstallError::Fail(result) => {
+                write!(f, "`bundle install` failed ({})", result.status)?;
+                let stderr = result.stderr.trim();
+                if !stderr.is_empty() {
+                    write!(f, ":\n{}", stderr)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl From<RubyExecError> for BundleInstallError {
+    #[inline]
+    fn from(error: RubyExecError) -> Self {
+        BundleInstallError::Exec(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_list() {
+        let list = ["rake", "13.0.6", "0", "json", "2.6.1", "1", ""];
+        let gems = Gem::parse_list(&list.join("\0"));
+        assert_eq!(gems, [
+            Gem {
+                name: "rake".to_owned(),
+                version: "13.0.6".to_owned(),
+                default: false,
+            },
+            Gem {
+                name: "json".to_owned(),
+                version: "2.6.1".to_owned(),
+                default: true,
+            },
+        ]);
+        assert!(Gem::parse_list("").is_empty());
+    }
+}
@@ -95,7 +95,13 @@ extern crate cc;
 #[cfg(feature = "archive")]
 extern crate bzip2;
 #[cfg(feature = "archive")]
+extern crate flate2;
+#[cfg(feature = "archive")]
 extern crate tar;
+#[cfg(feature = "xz")]
+extern crate xz2;
+#[cfg(feature = "zip")]
+extern crate zip;
 
 #[cfg(feature = "download")]
 extern crate dirs;
@@ -105,42 +111,118 @@ extern crate ureq;
 #[cfg(feature = "memchr")]
 extern crate memchr;
 
-use std::ffi::OsStr;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "json")]
+extern crate serde_json;
+
+use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Display};
 use std::io;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
+use std::process::{Command, ExitStatus, Output};
 use std::string::FromUtf8Error;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 #[cfg(feature = "archive")]
-mod archive;
+pub mod archive;
 #[cfg(feature = "archive")]
-pub use archive::Archive;
+#[doc(inline)]
+pub use archive::{Archive, ArchiveEntry};
 
+mod command;
+mod discover;
 mod link;
+mod pkg_config;
+#[cfg(feature = "serde")]
+mod metadata;
+mod platform;
+mod usage;
 mod util;
+mod verify;
+pub mod config;
+pub mod engine;
+pub mod gem;
+#[cfg(feature = "download")]
+mod auto;
 pub mod src;
 pub mod version;
 
+#[cfg(feature = "download")]
+pub mod cache;
+#[cfg(feature = "download")]
+#[doc(inline)]
+pub use cache::Cache;
+
+#[cfg(feature = "download")]
+pub mod prebuilt;
+#[cfg(feature = "download")]
+#[doc(inline)]
+pub use prebuilt::RubyPrebuilt;
+
+#[cfg(feature = "download")]
+#[doc(inline)]
+pub use auto::{auto_build, AutoBuildError};
+
+#[cfg(feature = "serde")]
+#[doc(inline)]
+pub use metadata::RubyMetadata;
+
+#[cfg(feature = "json")]
+#[doc(inline)]
+pub use metadata::MetadataError;
+
 use version::RubyVersionError;
 
 #[doc(inline)]
 pub use self::{
+    command::RubyCommand,
+    config::RbConfig,
+    engine::Engine,
+    gem::{BundleInstallError, Gem},
     link::*,
     src::RubySrc,
-    version::Version,
+    usage::DiskUsage,
+    verify::{InstallProblem, VerifySymbolsError},
+    version::{Version, VersionReq},
 };
 
+/// The environment variables that affect how `aloxide` builds and links to
+/// Ruby.
+///
+/// Cargo is told to rerun the build script when any of these change via
+/// [`Ruby::emit_cargo_directives`](struct.Ruby.html#method.emit_cargo_directives).
+pub const ENV_VARS: &[&str] = &[
+    "ALOXIDE_RUBY_VERSION",
+    "ALOXIDE_STATIC_RUBY",
+    "RUBY_STATIC",
+    "ALOXIDE_NO_SYMLINK",
+    "ALOXIDE_RUBY_CACHE",
+    "CC",
+    "CFLAGS",
+    "STRIP",
+    "OPENSSL_DIR",
+];
+
 /// An existing Ruby installation
 ///
 /// Ruby's sources are located in [`src_dir`](#method.src_dir) and its build
 /// output in [`out_dir`](#method.out_dir).
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ruby {
     version: Version,
     out_dir: PathBuf,
     lib_dir: PathBuf,
     bin_path: PathBuf,
+    // Retrieved on first use by `config`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    config: OnceLock<RbConfig>,
+    // Retrieved on first use by `engine`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    engine: OnceLock<Engine>,
 }
 
 impl Ruby {
@@ -171,7 +253,14 @@ impl Ruby {
         let out_dir = out_dir.into();
         let lib_dir = out_dir.join("lib");
         let bin_path = out_dir.join("bin").join(Self::bin_name());
-        Ruby { version, out_dir, lib_dir, bin_path }
+        Ruby {
+            version,
+            out_dir,
+            lib_dir,
+            bin_path,
+            config: OnceLock::new(),
+            engine: OnceLock::new(),
+        }
     }
 
     /// Returns the current Ruby found in `PATH`.
@@ -190,7 +279,7 @@ impl Ruby {
     #[inline]
     pub fn from_cmd(ruby: &mut Command) -> Result<Ruby, RubyVersionError> {
         Ruby::from_path(RubyExecError::process(
-            ruby.args(&["-e", "print RbConfig::CONFIG['prefix']"])
+            ruby.args(["-e", "print RbConfig::CONFIG['prefix']"])
         )?)
     }
 
@@ -203,6 +292,81 @@ impl Ruby {
         Ok(ruby)
     }
 
+    /// Creates a new instance via `pkg-config`, without running `ruby`.
+    ///
+    /// `query` is either the name of a package, such as "ruby-3.2", or a
+    /// version such as "3.2" to find the package for. A version without a
+    /// teeny number matches any teeny version.
+    ///
+    /// The variables of the package, such as `rubyhdrdir` and `RUBY_SO_NAME`,
+    /// are used as the [configuration](#method.config). Since these are only
+    /// a subset of `RbConfig::CONFIG`, values not provided by the package are
+    /// empty. The [engine](#method.engine) is assumed to be
+    /// [`Engine::Mri`](engine/enum.Engine.html#variant.Mri).
+    pub fn from_pkg_config(query: &str) -> Result<Ruby, RubyVersionError> {
+        let package = pkg_config::probe(query)?;
+
+        let out_dir = package.var_path("prefix").unwrap_or_default();
+        let lib_dir = package.var_path("libdir")
+            .unwrap_or_else(|| out_dir.join("lib"));
+        let bin_path = package.var_path("ruby").unwrap_or_else(|| {
+            let bin_dir = package.var_path("bindir")
+                .unwrap_or_else(|| out_dir.join("bin"));
+            bin_dir.join(Self::bin_name())
+        });
+
+        let config = RbConfig::from_map(package.vars);
+        let ruby = Ruby {
+            version: package.version,
+            out_dir,
+            lib_dir,
+            bin_path,
+            config: OnceLock::new(),
+            engine: OnceLock::new(),
+        };
+        ruby.config.get_or_init(|| config);
+        ruby.engine.get_or_init(|| Engine::Mri);
+        Ok(ruby)
+    }
+
+    /// Finds the Ruby installations on this machine.
+    ///
+    /// This checks `PATH` as well as the directories used by
+    /// [`rvm`](https://github.com/rvm/rvm),
+    /// [`rbenv`](https://github.com/rbenv/rbenv),
+    /// [`asdf`](https://asdf-vm.com),
+    /// [`chruby`](https://github.com/postmodern/chruby) and
+    /// [`ruby-install`](https://github.com/postmodern/ruby-install)
+    /// (`~/.rubies` and `/opt/rubies`), and [Homebrew](https://brew.sh).
+    ///
+    /// Each installation is only returned once, even if found in multiple
+    /// places. Executables that fail to run are skipped.
+    pub fn discover() -> Vec<Ruby> {
+        let mut rubies: Vec<Ruby> = Vec::new();
+        for bin in discover::candidates() {
+            if let Ok(ruby) = Ruby::from_bin(&bin) {
+                let seen = rubies.iter().any(|r| r.out_dir == ruby.out_dir);
+                if !seen {
+                    rubies.push(ruby);
+                }
+            }
+        }
+        rubies
+    }
+
+    /// Finds the newest Ruby installation on this machine that satisfies
+    /// `req`.
+    ///
+    /// Installations are found via [`discover`](#method.discover). If further
+    /// requirements are needed, such as a shared library, filter the results
+    /// of that instead.
+    pub fn find(req: &VersionReq) -> Option<Ruby> {
+        Ruby::discover()
+            .into_iter()
+            .filter(|ruby| ruby.version().satisfies(req))
+            .max_by(|a, b| a.version().cmp(b.version()))
+    }
+
     /// Creates a new instance from the `ruby` binary installed via
     /// [`rvm`](https://github.com/rvm/rvm).
     #[inline]
@@ -223,6 +387,51 @@ impl Ruby {
             .arg("ruby"))
     }
 
+    /// Creates a new instance from the `ruby` binary installed via
+    /// [`asdf`](https://asdf-vm.com).
+    ///
+    /// The installation is found via `asdf where ruby`. If `asdf` is not in
+    /// `PATH`, the installation in `$ASDF_DATA_DIR` or `~/.asdf` is used.
+    pub fn from_asdf(version: &Version) -> Result<Ruby, RubyVersionError> {
+        let mut asdf = Command::new("asdf");
+        asdf.args(["where", "ruby"]).arg(version.to_string());
+
+        match RubyExecError::process(&mut asdf) {
+            Ok(dir) => Ruby::from_path(dir.trim()),
+            Err(RubyExecError::ExecFail(error)) => match discover::asdf_dir() {
+                Some(dir) => Ruby::from_path(dir
+                    .join("installs")
+                    .join("ruby")
+                    .join(version.to_string())),
+                None => Err(RubyExecError::ExecFail(error).into()),
+            },
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Creates a new instance from the installation of `version` in
+    /// `~/.rubies` or `/opt/rubies`, as used by
+    /// [`chruby`](https://github.com/postmodern/chruby) and
+    /// [`ruby-install`](https://github.com/postmodern/ruby-install).
+    ///
+    /// Directories named like "ruby-3.2.2" or "3.2.2" are preferred, with
+    /// ones like "ruby-3.2.2-p53" being used otherwise.
+    pub fn from_rubies_dir(
+        version: &Version,
+    ) -> Result<Ruby, RubyVersionError> {
+        match discover::find_in_rubies_dirs(version) {
+            Some(dir) => Ruby::from_path(dir),
+            None => {
+                let msg = format!(
+                    "Ruby {} not found in `~/.rubies` or `/opt/rubies`",
+                    version,
+                );
+                let error = io::Error::new(io::ErrorKind::NotFound, msg);
+                Err(RubyExecError::ExecFail(error).into())
+            },
+        }
+    }
+
     /// Returns the Ruby version.
     #[inline]
     pub fn version(&self) -> &Version {
@@ -234,6 +443,85 @@ impl Ruby {
         self.exec(Some("-v"))
     }
 
+    /// Returns the implementation of Ruby, as identified by `RUBY_ENGINE`.
+    ///
+    /// This is retrieved by running `ruby` once and then cached for the
+    /// lifetime of `self`.
+    pub fn engine(&self) -> Result<&Engine, RubyExecError> {
+        if let Some(engine) = self.engine.get() {
+            return Ok(engine);
+        }
+        let name = self.run("print RUBY_ENGINE")?;
+        Ok(self.engine.get_or_init(|| Engine::from(name.as_str())))
+    }
+
+    /// Returns the platform that Ruby was built for, such as "x86_64-linux" or
+    /// "arm64-darwin21".
+    ///
+    /// This is the same as `RUBY_PLATFORM`.
+    #[inline]
+    pub fn platform(&self) -> Result<String, RubyExecError> {
+        self.get_config("arch")
+    }
+
+    /// Returns the CPU architecture that Ruby was built for, such as "x86_64"
+    /// or "arm64".
+    #[inline]
+    pub fn arch(&self) -> Result<String, RubyExecError> {
+        self.get_config("target_cpu")
+    }
+
+    /// Returns the operating system that Ruby was built for, such as "linux"
+    /// or "darwin21".
+    #[inline]
+    pub fn target_os(&self) -> Result<String, RubyExecError> {
+        self.get_config("target_os")
+    }
+
+    /// Returns whether Ruby was built for the same architecture and operating
+    /// system as the Rust `target` triple, such as the `TARGET` environment
+    /// variable in build scripts.
+    ///
+    /// This catches linking to a library for the wrong platform, such as an
+    /// x86_64 library for an aarch64 target. Differences in naming, such as
+    /// "arm64" and "aarch64", are accounted for.
+    pub fn matches_rust_target(
+        &self,
+        target: &str,
+    ) -> Result<bool, RubyExecError> {
+        let config = self.config()?;
+        let get = |key| config.get(key).unwrap_or_default();
+        Ok(platform::matches_rust_target(
+            get("target_cpu"),
+            get("target_os"),
+            target,
+        ))
+    }
+
+    /// Returns the API version of the Ruby library, such as "3.2.0" for Ruby
+    /// 3.2.2.
+    ///
+    /// This is the value of `RbConfig::CONFIG['ruby_version']`, which
+    /// determines where extensions are installed and which versions of the
+    /// library they're compatible with. If Ruby was configured with
+    /// `--with-ruby-version=full`, this is the full program version instead.
+    pub fn api_version(&self) -> Result<Version, RubyVersionError> {
+        Ok(self.get_config("ruby_version")?.parse()?)
+    }
+
+    /// Returns whether extensions built for Ruby `version` are compatible
+    /// with this Ruby's library.
+    ///
+    /// Ruby keeps its ABI stable between teeny releases of the same
+    /// `major.minor` version.
+    pub fn abi_compatible_with(
+        &self,
+        version: &Version,
+    ) -> Result<bool, RubyVersionError> {
+        let api = self.api_version()?;
+        Ok(api.major == version.major && api.minor == version.minor)
+    }
+
     /// The directory of Ruby's installed files.
     #[inline]
     pub fn out_dir(&self) -> &Path {
@@ -252,6 +540,73 @@ impl Ruby {
         &self.bin_path
     }
 
+    // The directory containing `ruby` and its tools
+    pub(crate) fn bin_dir(&self) -> &Path {
+        self.bin_path.parent().unwrap_or(&self.out_dir)
+    }
+
+    /// Returns the path of the executable named `name` (e.g. `gem`, `irb`,
+    /// `rake`, or `bundle`) installed alongside `ruby`.
+    ///
+    /// On Windows, this finds `name` with an `.exe`, `.bat`, or `.cmd`
+    /// extension.
+    pub fn tool_path(&self, name: &str) -> PathBuf {
+        let bin_dir = self.bin_dir();
+        if cfg!(target_os = "windows") {
+            for ext in &["exe", "bat", "cmd"] {
+                let path = bin_dir.join(name).with_extension(ext);
+                if path.is_file() {
+                    return path;
+                }
+            }
+        }
+        bin_dir.join(name)
+    }
+
+    /// Returns a command for running the executable named `name` installed
+    /// alongside `ruby`.
+    ///
+    /// See [`tool_path`](#method.tool_path).
+    #[inline]
+    pub fn tool(&self, name: &str) -> Command {
+        Command::new(self.tool_path(name))
+    }
+
+    /// Executes the executable named `name` installed alongside `ruby` with
+    /// `args`.
+    ///
+    /// See [`tool_path`](#method.tool_path).
+    pub fn exec_tool<I, S>(
+        &self,
+        name: &str,
+        args: I,
+    ) -> Result<String, RubyExecError>
+    where
+        I: IntoIterator<Item=S>,
+        S: AsRef<OsStr>,
+    {
+        RubyExecError::process(self.tool(name).args(args))
+    }
+
+    /// Packs the contents of [`out_dir`](#method.out_dir) into a tarball at
+    /// `dst` compressed with `format`.
+    ///
+    /// The contents are placed in a `ruby-x.y.z` directory, which allows the
+    /// tarball to be used with
+    /// [`RubyPrebuilt::url`](prebuilt/struct.RubyPrebuilt.html#method.url).
+    ///
+    /// **Note:** requires the `archive` or `download` feature (the default).
+    #[cfg(feature = "archive")]
+    pub fn pack(
+        &self,
+        dst: impl AsRef<Path>,
+        format: archive::ArchiveFormat,
+    ) -> io::Result<()> {
+        let prefix = format!("ruby-{}", self.version.release());
+        let file = io::BufWriter::new(std::fs::File::create(dst)?);
+        archive::pack(&self.out_dir, prefix, file, format)
+    }
+
     /// Executes the `ruby` binary at `bin_path` with `args`.
     pub fn exec<I, S>(&self, args: I) -> Result<String, RubyExecError>
     where
@@ -261,9 +616,115 @@ impl Ruby {
         RubyExecError::process(Command::new(&self.bin_path).args(args))
     }
 
+    /// Executes the `ruby` binary at `bin_path` with `args`, returning its
+    /// output and exit status.
+    ///
+    /// Unlike [`exec`](#method.exec), this does not fail if `ruby` exits
+    /// unsuccessfully, and `stderr` is kept. This allows for observing
+    /// warnings, such as deprecation notices.
+    pub fn exec_full<I, S>(&self, args: I) -> Result<ExecResult, RubyExecError>
+    where
+        I: IntoIterator<Item=S>,
+        S: AsRef<OsStr>,
+    {
+        let output = Command::new(&self.bin_path).args(args).output()?;
+        ExecResult::from_output(output)
+    }
+
+    /// Executes the `ruby` binary at `bin_path` with `args` and the
+    /// environment variables `envs` set.
+    ///
+    /// Use [`command`](#method.command) for more control over how `ruby` is
+    /// run.
+    pub fn exec_with_env<I, S, E, K, V>(
+        &self,
+        args: I,
+        envs: E,
+    ) -> Result<String, RubyExecError>
+    where
+        I: IntoIterator<Item=S>,
+        S: AsRef<OsStr>,
+        E: IntoIterator<Item=(K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.command().args(args).envs(envs).exec()
+    }
+
+    /// Returns a builder for running the `ruby` binary at `bin_path`.
+    #[inline]
+    pub fn command(&self) -> RubyCommand {
+        RubyCommand::new(self)
+    }
+
     /// Runs `script` through the `ruby` interpreter at `bin_path`.
     pub fn run(&self, script: impl AsRef<OsStr>) -> Result<String, RubyExecError> {
-        self.exec(&["-e".as_ref(), script.as_ref()])
+        self.exec(["-e".as_ref(), script.as_ref()])
+    }
+
+    /// Runs the script at `path` with `args` through the `ruby` interpreter at
+    /// `bin_path`.
+    ///
+    /// Unlike [`run`](#method.run), this is not limited by the maximum length
+    /// of command-line arguments, which makes it better suited for long
+    /// generated scripts.
+    pub fn run_file<I, S>(
+        &self,
+        path: impl AsRef<Path>,
+        args: I,
+    ) -> Result<String, RubyExecError>
+    where
+        I: IntoIterator<Item=S>,
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new(&self.bin_path);
+        command.arg(path.as_ref()).args(args);
+        RubyExecError::process(&mut command)
+    }
+
+    /// Runs `script` through the `ruby` interpreter at `bin_path` and
+    /// deserializes the JSON of its resulting value.
+    ///
+    /// The value of the last expression in `script` is converted via
+    /// `to_json` and printed, so `script` shouldn't print anything itself.
+    ///
+    /// **Note:** requires the `json` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # let ruby: aloxide::Ruby = unimplemented!();
+    /// use std::collections::HashMap;
+    ///
+    /// let config: HashMap<String, String> = ruby
+    ///     .run_json("RbConfig::CONFIG")
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn run_json<T>(&self, script: impl Display) -> Result<T, RubyExecError>
+        where T: serde::de::DeserializeOwned
+    {
+        let script = format!(
+            "require 'json'\nprint((begin\n{}\nend).to_json)",
+            script,
+        );
+        let json = self.run(script)?;
+        serde_json::from_str(&json).map_err(RubyExecError::Json)
+    }
+
+    /// Runs `script` through the `ruby` interpreter at `bin_path`, killing it
+    /// if it doesn't finish within `timeout`.
+    ///
+    /// This returns [`RubyExecError::Timeout`](enum.RubyExecError.html#variant.Timeout)
+    /// if `ruby` was killed.
+    pub fn run_timeout(
+        &self,
+        script: impl AsRef<OsStr>,
+        timeout: Duration,
+    ) -> Result<String, RubyExecError> {
+        let mut command = Command::new(&self.bin_path);
+        command.arg("-e").arg(script);
+        RubyExecError::process_timeout(&mut command, timeout)
     }
 
     /// Runs multiple scripts through the `ruby` interpreter at `bin_path`
@@ -287,16 +748,93 @@ impl Ruby {
         RubyExecError::process(&mut command)
     }
 
+    /// Runs each of `scripts` through the `ruby` interpreter at `bin_path`
+    /// in its own process, returning the output of each in the same order.
+    ///
+    /// Unlike [`run_multiple`](#method.run_multiple), the outputs are not
+    /// concatenated and the failure of one script does not prevent the
+    /// others from running.
+    pub fn run_each<I, S>(
+        &self,
+        scripts: I,
+    ) -> Vec<Result<String, RubyExecError>>
+    where
+        I: IntoIterator<Item=S>,
+        S: AsRef<OsStr>,
+    {
+        scripts.into_iter().map(|script| self.run(script)).collect()
+    }
+
     fn _get_config(&self, key: &dyn Display) -> Result<String, RubyExecError> {
-        self.run(&format!("print RbConfig::CONFIG['{}']", key))
+        let key = key.to_string();
+        Ok(self.config()?.get(&key).unwrap_or_default().to_owned())
     }
 
-    /// Returns the configuration value for `key`.
+    /// Returns the configuration value for `key`, or an empty string if there
+    /// is none.
+    ///
+    /// This reads from [`config`](#method.config), so `ruby` is only run for
+    /// the first value retrieved.
     #[inline]
     pub fn get_config(&self, key: impl Display) -> Result<String, RubyExecError> {
         self._get_config(&key)
     }
 
+    /// Returns the configuration value for `key` as a path.
+    ///
+    /// See [`RbConfig::get_path`](config/struct.RbConfig.html#method.get_path).
+    #[inline]
+    pub fn config_path(
+        &self,
+        key: impl Display,
+    ) -> Result<Option<PathBuf>, RubyExecError> {
+        Ok(self.config()?.get_path(&key.to_string()))
+    }
+
+    /// Returns the configuration value for `key` as a boolean, or `false` if
+    /// there is none.
+    ///
+    /// See [`RbConfig::get_bool`](config/struct.RbConfig.html#method.get_bool).
+    #[inline]
+    pub fn config_bool(&self, key: impl Display) -> Result<bool, RubyExecError> {
+        let config = self.config()?;
+        Ok(config.get_bool(&key.to_string()).unwrap_or(false))
+    }
+
+    /// Returns the configuration value for `key` split into flags as a shell
+    /// would.
+    ///
+    /// See [`RbConfig::get_flags`](config/struct.RbConfig.html#method.get_flags).
+    #[inline]
+    pub fn config_flags(
+        &self,
+        key: impl Display,
+    ) -> Result<Vec<String>, RubyExecError> {
+        let config = self.config()?;
+        Ok(config.get_flags(&key.to_string()).unwrap_or_default())
+    }
+
+    /// Returns all configuration values.
+    ///
+    /// These are retrieved by running `ruby` once and then cached for the
+    /// lifetime of `self`. If `ruby` can't be run, such as when it was built
+    /// for another machine, the values are read from the installed
+    /// `rbconfig.rb` instead.
+    pub fn config(&self) -> Result<&RbConfig, RubyExecError> {
+        if let Some(config) = self.config.get() {
+            return Ok(config);
+        }
+        let config = match self.run(config::DUMP_SCRIPT) {
+            Ok(dump) => RbConfig::parse(&dump),
+            Err(RubyExecError::ExecFail(error)) => {
+                RbConfig::read_installed(&self.out_dir)
+                    .ok_or(RubyExecError::ExecFail(error))?
+            },
+            Err(error) => return Err(error),
+        };
+        Ok(self.config.get_or_init(|| config))
+    }
+
     /// Returns the `include` directory.
     #[inline]
     pub fn include_dir(&self) -> Result<String, RubyExecError> {
@@ -326,6 +864,74 @@ impl Ruby {
         Ok(name)
     }
 
+    /// Returns whether the static Ruby library exists in
+    /// [`lib_dir`](#method.lib_dir).
+    ///
+    /// This determines whether `true` can be passed to
+    /// [`link`](#method.link).
+    #[inline]
+    pub fn has_static_lib(&self) -> Result<bool, RubyExecError> {
+        Ok(self.static_lib_path()?.is_some())
+    }
+
+    /// Returns whether the shared Ruby library exists in
+    /// [`lib_dir`](#method.lib_dir).
+    ///
+    /// This determines whether `false` can be passed to
+    /// [`link`](#method.link).
+    #[inline]
+    pub fn has_shared_lib(&self) -> Result<bool, RubyExecError> {
+        Ok(self.shared_lib_path()?.is_some())
+    }
+
+    /// Returns the path of the static Ruby library, such as
+    /// "libruby-static.a", if it exists.
+    ///
+    /// This checks `LIBRUBY_A` before falling back to a name based on
+    /// `RUBY_SO_NAME`.
+    pub fn static_lib_path(&self) -> Result<Option<PathBuf>, RubyExecError> {
+        let config = self.config()?;
+        let mut names = Vec::new();
+        names.extend(config.get("LIBRUBY_A").map(str::to_owned));
+        if let Some(so_name) = config.get("RUBY_SO_NAME") {
+            names.push(format!("lib{}-static.a", so_name));
+            names.push(format!("{}-static.lib", so_name));
+        }
+        Ok(find_file(&[&self.lib_dir], &names))
+    }
+
+    /// Returns the path of the shared Ruby library, such as
+    /// "libruby.so.3.2.2", "libruby.3.2.dylib", or
+    /// "x64-vcruntime140-ruby320.dll", if it exists.
+    ///
+    /// This checks `LIBRUBY_SO` and then its aliases (`LIBRUBY_ALIASES`)
+    /// before falling back to a name based on `RUBY_SO_NAME`. Symbolic links
+    /// are resolved, so the returned path is that of the actual file.
+    pub fn shared_lib_path(&self) -> Result<Option<PathBuf>, RubyExecError> {
+        let config = self.config()?;
+        let mut names = Vec::new();
+        names.extend(config.get("LIBRUBY_SO").map(str::to_owned));
+        if let Some(aliases) = config.get("LIBRUBY_ALIASES") {
+            names.extend(aliases.split_whitespace().map(str::to_owned));
+        }
+        if let Some(so_name) = config.get("RUBY_SO_NAME") {
+            let ext = config.get("SOEXT").unwrap_or(if cfg!(windows) {
+                "dll"
+            } else if cfg!(target_os = "macos") {
+                "dylib"
+            } else {
+                "so"
+            });
+            names.push(format!("lib{}.{}", so_name, ext));
+            names.push(format!("{}.{}", so_name, ext));
+        }
+
+        // DLLs are installed alongside `ruby.exe` on Windows
+        let dirs = [&*self.lib_dir, self.bin_dir()];
+        let path = find_file(&dirs, &names);
+        Ok(path.map(|path| std::fs::canonicalize(&path).unwrap_or(path)))
+    }
+
     /// Returns the value of `RbConfig::CONFIG['LIBRUBYARG']`.
     #[inline]
     pub fn lib_args(&self) -> Result<String, RubyExecError> {
@@ -363,13 +969,478 @@ impl Ruby {
         }
     }
 
+    /// Returns the gems installed for this Ruby, including default gems.
+    pub fn gems(&self) -> Result<Vec<Gem>, RubyExecError> {
+        self.run(gem::LIST_SCRIPT).map(|list| Gem::parse_list(&list))
+    }
+
+    /// Returns the default gems, which are bundled with Ruby.
+    pub fn default_gems(&self) -> Result<Vec<Gem>, RubyExecError> {
+        let mut gems = self.gems()?;
+        gems.retain(|gem| gem.default);
+        Ok(gems)
+    }
+
+    /// Returns whether a gem named `name` is installed.
+    pub fn has_gem(&self, name: &str) -> Result<bool, RubyExecError> {
+        Ok(self.gems()?.iter().any(|gem| gem.name == name))
+    }
+
+    /// Returns the default value for `GEM_HOME`, derived from `RbConfig`.
+    ///
+    /// Unlike [`gem_dir`](#method.gem_dir), this ignores the environment of
+    /// the current process.
+    pub fn default_gem_home(&self) -> Result<PathBuf, RubyExecError> {
+        let config = self.config()?;
+        let get = |key| config.get(key).unwrap_or_default();
+        Ok(Path::new(get("rubylibprefix"))
+            .join("gems")
+            .join(get("ruby_version")))
+    }
+
+    /// Returns the default value for `GEM_PATH`, derived from `RbConfig`.
+    ///
+    /// This is [`default_gem_home`](#method.default_gem_home) followed by the
+    /// gem directory within [`vendor_dir`](#method.vendor_dir) if there is
+    /// one.
+    pub fn default_gem_path(&self) -> Result<Vec<PathBuf>, RubyExecError> {
+        let mut paths = vec![self.default_gem_home()?];
+        if let Some(vendor_dir) = self.vendor_dir()? {
+            let ruby_version = self.get_config("ruby_version")?;
+            paths.push(vendor_dir.join("gems").join(ruby_version));
+        }
+        Ok(paths)
+    }
+
+    /// Returns the directory for site-specific libraries (`sitedir`).
+    #[inline]
+    pub fn site_dir(&self) -> Result<PathBuf, RubyExecError> {
+        Ok(self.config_path("sitedir")?.unwrap_or_default())
+    }
+
+    /// Returns the directory for vendor-specific libraries (`vendordir`), if
+    /// Ruby was configured with one.
+    #[inline]
+    pub fn vendor_dir(&self) -> Result<Option<PathBuf>, RubyExecError> {
+        self.config_path("vendordir")
+    }
+
+    /// Returns the directory of Ruby's standard library (`rubylibdir`), which
+    /// is suitable for `RUBYLIB`.
+    #[inline]
+    pub fn rubylibdir(&self) -> Result<PathBuf, RubyExecError> {
+        Ok(self.config_path("rubylibdir")?.unwrap_or_default())
+    }
+
+    /// Returns the directory where gems are installed by default.
+    ///
+    /// Unlike [`default_gem_home`](#method.default_gem_home), this respects
+    /// `GEM_HOME` in the environment of the current process.
+    pub fn gem_dir(&self) -> Result<PathBuf, RubyExecError> {
+        self.run("print Gem.dir").map(PathBuf::from)
+    }
+
+    /// Returns the directories that gems are loaded from.
+    ///
+    /// Unlike [`default_gem_path`](#method.default_gem_path), this respects
+    /// `GEM_PATH` in the environment of the current process.
+    pub fn gem_paths(&self) -> Result<Vec<PathBuf>, RubyExecError> {
+        let paths = self.run("print Gem.path.join(\"\\0\")")?;
+        Ok(split_paths(&paths))
+    }
+
+    /// Returns the default `$LOAD_PATH`, which is where `require` looks for
+    /// libraries.
+    ///
+    /// `RUBYLIB` and `RUBYOPT` are removed from the environment of `ruby`, so
+    /// that the returned paths are those of the installation alone.
+    pub fn load_paths(&self) -> Result<Vec<PathBuf>, RubyExecError> {
+        let paths = self.command()
+            .env_remove("RUBYLIB")
+            .env_remove("RUBYOPT")
+            .script("print $LOAD_PATH.join(\"\\0\")")
+            .exec()?;
+        Ok(split_paths(&paths))
+    }
+
+    /// Returns the environment variables that a process embedding this Ruby,
+    /// such as by calling `ruby_setup()` on a statically linked VM, should
+    /// set for the VM to find its libraries, gems, and executables.
+    ///
+    /// These are:
+    /// - `RUBYLIB`: the default [`$LOAD_PATH`](#method.load_paths)
+    /// - `GEM_HOME`: the default [`GEM_HOME`](#method.default_gem_home)
+    /// - `GEM_PATH`: the default [`GEM_PATH`](#method.default_gem_path)
+    /// - `RUBY_ROOT`: [`out_dir`](#method.out_dir)
+    /// - `PATH`: the `bin` directory followed by the current `PATH`
+    ///
+    /// Use [`apply_embed_env`](#method.apply_embed_env) to set these for a
+    /// `Command`.
+    pub fn embed_env(
+        &self,
+    ) -> Result<Vec<(&'static str, OsString)>, RubyExecError> {
+        let mut path = vec![self.bin_dir().to_owned()];
+        if let Some(current) = env::var_os("PATH") {
+            path.extend(env::split_paths(&current));
+        }
+
+        Ok(vec![
+            ("RUBYLIB", join_paths(self.load_paths()?)?),
+            ("GEM_HOME", self.default_gem_home()?.into()),
+            ("GEM_PATH", join_paths(self.default_gem_path()?)?),
+            ("RUBY_ROOT", self.out_dir.clone().into()),
+            ("PATH", join_paths(path)?),
+        ])
+    }
+
+    /// Sets the variables of [`embed_env`](#method.embed_env) for `command`.
+    pub fn apply_embed_env(
+        &self,
+        command: &mut Command,
+    ) -> Result<(), RubyExecError> {
+        command.envs(self.embed_env()?);
+        Ok(())
+    }
+
+    /// Installs the gems for `gemfile` via Bundler into `out_dir/bundle`,
+    /// returning that directory.
+    ///
+    /// Bundler's configuration is kept within that directory, so neither the
+    /// user's configuration nor the project's `.bundle` directory are used.
+    /// With `deployment`, the `Gemfile.lock` must be up-to-date, as with
+    /// `bundle install --deployment`.
+    pub fn bundle_install(
+        &self,
+        gemfile: impl AsRef<Path>,
+        deployment: bool,
+    ) -> Result<PathBuf, BundleInstallError> {
+        let bundle = self.bin_dir().join("bundle");
+        if !bundle.is_file() {
+            return Err(BundleInstallError::MissingBundler);
+        }
+
+        let bundle_path = self.out_dir.join("bundle");
+        let deployment = if deployment { "true" } else { "false" };
+
+        // Run the script via `ruby` to avoid needing `.bat` shims on Windows
+        let result = self.command()
+            .arg(&bundle)
+            .arg("install")
+            .env("BUNDLE_GEMFILE", gemfile.as_ref())
+            .env("BUNDLE_PATH", &bundle_path)
+            .env("BUNDLE_APP_CONFIG", bundle_path.join("config"))
+            .env("BUNDLE_DEPLOYMENT", deployment)
+            .env("BUNDLE_FROZEN", deployment)
+            .exec_full()?;
+
+        if result.success() {
+            Ok(bundle_path)
+        } else {
+            Err(BundleInstallError::Fail(result))
+        }
+    }
+
     /// Tells `cargo` to link to Ruby and its libraries.
+    ///
+    /// This fails with
+    /// [`RubyLinkError::UnsupportedEngine`](enum.RubyLinkError.html#variant.UnsupportedEngine)
+    /// if `self` is not [`Engine::Mri`](engine/enum.Engine.html#variant.Mri),
+    /// or with
+    /// [`RubyLinkError::MissingStaticLib`](enum.RubyLinkError.html#variant.MissingStaticLib)
+    /// if `static_lib` is `true` but the static library was not installed.
+    ///
+    /// The following environment variables override the options passed in,
+    /// and Cargo is told to rerun the build script when they change:
+    ///
+    /// - `ALOXIDE_STATIC_RUBY` and then `RUBY_STATIC`: whether to link
+    ///   statically, where "0", "false", "no", "off", and empty values are
+    ///   `false`.
+    ///
+    /// - `ALOXIDE_NO_SYMLINK`: if `true`, the shared library is left where it
+    ///   is, as with
+    ///   [`LinkOptions::no_symlink`](struct.LinkOptions.html#method.no_symlink).
     pub fn link(&self, static_lib: bool) -> Result<(), RubyLinkError> {
-        link::link(self, static_lib)
+        self.link_with(&LinkOptions::new().static_lib(static_lib))
+    }
+
+    /// Tells `cargo` to link to Ruby and its libraries as configured by
+    /// `options`.
+    ///
+    /// See [`link`](#method.link) for details.
+    #[inline]
+    pub fn link_with(
+        &self,
+        options: &LinkOptions,
+    ) -> Result<(), RubyLinkError> {
+        self.link_to(options, &mut io::stdout())
+    }
+
+    /// Returns the libraries, search paths, and arguments for linking to Ruby
+    /// as configured by `options`, for use outside of build scripts.
+    ///
+    /// Unlike [`link_with`](#method.link_with), the shared library is only
+    /// symlinked or copied if requested via `options`, since Cargo's `deps`
+    /// directory is only known within build scripts.
+    pub fn link_instructions(
+        &self,
+        options: &LinkOptions,
+    ) -> Result<LinkInstructions, RubyLinkError> {
+        let options = options.clone().outside_build_script();
+        let mut instructions = LinkInstructions::default();
+        link::link(self, &options, &mut instructions)?;
+        Ok(instructions)
+    }
+
+    /// Emits the directives for linking to Ruby as configured by `options`
+    /// into `out` instead of `stdout`.
+    ///
+    /// This allows for inspecting or testing the directives before passing
+    /// them to Cargo. See [`link`](#method.link) for details.
+    #[inline]
+    pub fn link_to(
+        &self,
+        options: &LinkOptions,
+        out: &mut dyn CargoOutput,
+    ) -> Result<(), RubyLinkError> {
+        link::link(self, options, out)
+    }
+
+    /// Writes the installation's [metadata](struct.RubyMetadata.html) as
+    /// JSON to `path`, running `ruby` to retrieve any values that have not
+    /// yet been retrieved.
+    ///
+    /// This allows for build scripts to skip probing the same installation
+    /// again via [`load_metadata`](#method.load_metadata).
+    #[cfg(feature = "json")]
+    pub fn save_metadata(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), MetadataError> {
+        let metadata = RubyMetadata::new(self)?;
+        let json = serde_json::to_vec_pretty(&metadata)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Reads an installation from the metadata written to `path` by
+    /// [`save_metadata`](#method.save_metadata).
+    ///
+    /// The engine and configuration are not retrieved again. Whether the
+    /// metadata is out of date is left up to the caller.
+    #[cfg(feature = "json")]
+    pub fn load_metadata(
+        path: impl AsRef<Path>,
+    ) -> Result<Ruby, MetadataError> {
+        let json = std::fs::read(path)?;
+        let metadata: RubyMetadata = serde_json::from_slice(&json)?;
+        Ok(metadata.into_ruby())
+    }
+
+    /// Checks that the installation is complete, returning every problem
+    /// found.
+    ///
+    /// This confirms that `ruby` runs and reports the expected version, and
+    /// that the Ruby library and its headers exist. An incomplete
+    /// `make install` would otherwise only surface as errors when linking.
+    #[inline]
+    pub fn verify(&self) -> Vec<InstallProblem> {
+        verify::verify(self)
+    }
+
+    /// Returns which of `symbols` are not defined by the Ruby library, such
+    /// as `&["ruby_setup", "rb_eval_string_protect"]`.
+    ///
+    /// The static library is checked if it exists and the shared library
+    /// otherwise. Symbols are read via `dumpbin` for MSVC builds of Ruby and
+    /// via `nm` otherwise, which the `NM` environment variable overrides.
+    ///
+    /// This is useful for cross builds, where a missing symbol would otherwise
+    /// only surface as an obscure linker error. The library is found without
+    /// running `ruby` if it can't run on this machine; see
+    /// [`config`](#method.config).
+    #[inline]
+    pub fn verify_symbols(
+        &self,
+        symbols: &[&str],
+    ) -> Result<Vec<String>, VerifySymbolsError> {
+        verify::missing_symbols(self, symbols)
+    }
+
+    /// Tells `cargo` to rerun the build script when the `ruby` executable,
+    /// library directory, or headers change, or when any of
+    /// [`ENV_VARS`](constant.ENV_VARS.html) change.
+    pub fn emit_cargo_directives(&self) -> Result<(), RubyExecError> {
+        let include_dir = self.include_dir()?;
+        emit_cargo_directives(
+            &self.bin_path,
+            &self.lib_dir,
+            include_dir.as_ref(),
+        );
+        Ok(())
+    }
+
+    /// Writes Rust source code to `path` with `pub const` items describing
+    /// this installation, to be used via `include!`.
+    ///
+    /// This allows for crates to branch on the Ruby they're linked to at
+    /// compile-time. The items are:
+    /// - `RUBY_VERSION: &str`, along with the `u16` values of
+    ///   `RUBY_VERSION_MAJOR`, `RUBY_VERSION_MINOR`, and `RUBY_VERSION_TEENY`
+    /// - `RUBY_ENGINE: &str` and `RUBY_PLATFORM: &str`
+    /// - `RUBY_OUT_DIR`, `RUBY_LIB_DIR`, and `RUBY_BIN_PATH` as `&str`
+    /// - `RUBY_ENABLE_SHARED: bool`
+    /// - `RBCONFIG: &[(&str, &str)]`, the contents of `RbConfig::CONFIG`
+    ///   ordered by key
+    ///
+    /// # Examples
+    ///
+    /// In `build.rs`:
+    ///
+    /// ```no_run
+    /// # let ruby: aloxide::Ruby = unimplemented!();
+    /// let out_dir = std::env::var("OUT_DIR").unwrap();
+    /// let path = std::path::Path::new(&out_dir).join("ruby_config.rs");
+    /// ruby.write_config_rs(path).unwrap();
+    /// ```
+    ///
+    /// In the crate:
+    ///
+    /// ```ignore
+    /// include!(concat!(env!("OUT_DIR"), "/ruby_config.rs"));
+    /// ```
+    pub fn write_config_rs(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let config = self.config()?;
+        let engine = self.engine()?;
+        let version = &self.version;
+
+        let mut out = String::from("// Generated by aloxide\n\n");
+        let mut push = |name: &str, ty: &str, val: &dyn fmt::Debug| {
+            out.push_str(&format!("pub const {}: {} = {:?};\n", name, ty, val));
+        };
+        push("RUBY_VERSION", "&str", &version.to_string());
+        push("RUBY_VERSION_MAJOR", "u16", &version.major);
+        push("RUBY_VERSION_MINOR", "u16", &version.minor);
+        push("RUBY_VERSION_TEENY", "u16", &version.teeny);
+        push("RUBY_ENGINE", "&str", &engine.name());
+        push("RUBY_PLATFORM", "&str", &config.get("arch").unwrap_or_default());
+        push("RUBY_OUT_DIR", "&str", &self.out_dir.to_string_lossy());
+        push("RUBY_LIB_DIR", "&str", &self.lib_dir.to_string_lossy());
+        push("RUBY_BIN_PATH", "&str", &self.bin_path.to_string_lossy());
+        push(
+            "RUBY_ENABLE_SHARED",
+            "bool",
+            &config.get_bool("ENABLE_SHARED").unwrap_or(false),
+        );
+        out.push('\n');
+        config.write_rust_table(&mut out);
+
+        std::fs::write(path, out)
+    }
+
+    /// Returns the disk space used by each entry within
+    /// [`out_dir`](#method.out_dir), such as "bin", "lib", "include", and
+    /// "share".
+    #[inline]
+    pub fn disk_usage(&self) -> io::Result<DiskUsage> {
+        usage::dir_usage(&self.out_dir)
+    }
+
+    /// Removes the installation by deleting [`out_dir`](#method.out_dir).
+    ///
+    /// To avoid deleting anything else, this fails with
+    /// `io::ErrorKind::InvalidInput` unless `out_dir` is an installation
+    /// created by `aloxide`, such as via
+    /// [`RubyBuilder`](src/build/struct.RubyBuilder.html) or
+    /// [`RubyPrebuilt`](prebuilt/struct.RubyPrebuilt.html), that contains
+    /// `ruby`. Installations found elsewhere, such as via
+    /// [`discover`](#method.discover), are never removed.
+    pub fn uninstall(self) -> io::Result<()> {
+        let invalid = |msg: String| {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+        };
+
+        let out_dir = std::fs::canonicalize(&self.out_dir)?;
+        let home_dir = discover::home_dir()
+            .and_then(|dir| std::fs::canonicalize(dir).ok());
+        if out_dir.parent().is_none() || Some(&out_dir) == home_dir.as_ref() {
+            return invalid(format!(
+                "refusing to remove {}",
+                out_dir.display(),
+            ));
+        }
+        if !out_dir.join(INSTALL_MARKER).is_file() {
+            return invalid(format!(
+                "{} is not an installation managed by aloxide",
+                out_dir.display(),
+            ));
+        }
+        let bin_path = std::fs::canonicalize(&self.bin_path)?;
+        if !bin_path.starts_with(&out_dir) {
+            return invalid(format!(
+                "`ruby` at {} is outside of {}",
+                bin_path.display(),
+                out_dir.display(),
+            ));
+        }
+
+        std::fs::remove_dir_all(out_dir)
+    }
+
+    /// Returns whether the development headers needed for building against
+    /// the Ruby library, such as `ruby.h`, are installed.
+    ///
+    /// System installations often lack these unless a separate package is
+    /// installed, such as `ruby-dev` on Debian or `ruby-devel` on Fedora.
+    #[inline]
+    pub fn has_dev_headers(&self) -> Result<bool, RubyExecError> {
+        Ok(Path::new(&self.header_dir()?).join("ruby.h").is_file())
+    }
+
+    // Fails with `MissingDevHeaders` if `has_dev_headers` is false
+    fn require_dev_headers(&self) -> io::Result<()> {
+        if self.has_dev_headers()? {
+            Ok(())
+        } else {
+            let header_dir = PathBuf::from(self.header_dir()?);
+            Err(MissingDevHeaders { header_dir }.into())
+        }
+    }
+
+    /// Writes a [`pkg-config`](https://www.freedesktop.org/wiki/Software/pkg-config)
+    /// file for the installation to `path`, such as "ruby-3.2.pc".
+    ///
+    /// This allows for build systems like CMake and Meson to use the
+    /// installation. The file links to the shared Ruby library if Ruby was
+    /// built with it enabled and to the static library otherwise.
+    pub fn write_pkg_config(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let pc = pkg_config::render(self, self.config()?);
+        std::fs::write(path, pc)
+    }
+
+    /// Tells `cargo` the installation's metadata for crates that depend on
+    /// the current one, if its package has `links = "ruby"`.
+    ///
+    /// Build scripts of those crates can then read the following environment
+    /// variables:
+    /// - `DEP_RUBY_ROOT`: [`out_dir`](#method.out_dir)
+    /// - `DEP_RUBY_INCLUDE`: [`header_dir`](#method.header_dir)
+    /// - `DEP_RUBY_LIBDIR`: [`lib_dir`](#method.lib_dir)
+    /// - `DEP_RUBY_VERSION`: [`version`](#method.version)
+    pub fn emit_link_metadata(&self) -> Result<(), RubyExecError> {
+        let header_dir = self.header_dir()?;
+        println!("cargo:root={}", self.out_dir.display());
+        println!("cargo:include={}", header_dir);
+        println!("cargo:libdir={}", self.lib_dir.display());
+        println!("cargo:version={}", self.version);
+        Ok(())
     }
 
     /// Iterates over the header directory paths for the Ruby library.
+    ///
+    /// If the development headers are not installed, the returned error
+    /// wraps [`MissingDevHeaders`](struct.MissingDevHeaders.html).
     pub fn with_headers<F: FnMut(PathBuf)>(&self, mut f: F) -> io::Result<()> {
+        self.require_dev_headers()?;
         util::walk_files(self.include_dir()?.as_ref(), |path| {
             if path.extension() == Some("h".as_ref()) {
                 f(path);
@@ -391,6 +1462,9 @@ impl Ruby {
     /// This method filters out headers in `arch_header_dir`. If you'd like to
     /// keep those headers, use `wrapper_header_filtered` with a filter that
     /// returns `true`.
+    ///
+    /// If the development headers are not installed, the returned error
+    /// wraps [`MissingDevHeaders`](struct.MissingDevHeaders.html).
     pub fn wrapper_header(&self) -> io::Result<String> {
         let arch_header_dir = self.arch_header_dir()?;
         self.wrapper_header_filtered(|path| {
@@ -415,6 +1489,7 @@ impl Ruby {
         &self,
         f: &mut dyn FnMut(&Path) -> bool,
     ) -> io::Result<String> {
+        self.require_dev_headers()?;
         let header_dir = self.header_dir()?;
         let header_dir = Path::new(&header_dir);
 
@@ -427,7 +1502,7 @@ impl Ruby {
             writeln!(buf, "#include <{}>", header)
         }
 
-        util::walk_files(&header_dir, |path| {
+        util::walk_files(header_dir, |path| {
             if path.extension() != Some("h".as_ref()) || !f(&path) {
                 return Ok(());
             }
@@ -445,7 +1520,7 @@ impl Ruby {
                     Ok(())
                 },
                 Err(error) => {
-                    Err(io::Error::new(io::ErrorKind::Other, error))
+                    Err(io::Error::other(error))
                 },
             }
         })?;
@@ -454,6 +1529,113 @@ impl Ruby {
     }
 }
 
+// The file within `out_dir` that marks an installation as created by `aloxide`
+pub(crate) const INSTALL_MARKER: &str = ".aloxide-install";
+
+// Returns the first of `names` that exists within any of `dirs`
+fn find_file(dirs: &[&Path], names: &[String]) -> Option<PathBuf> {
+    names.iter()
+        .filter(|name| !name.is_empty())
+        .flat_map(|name| dirs.iter().map(move |dir| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+// Joins `paths` for a `PATH`-like environment variable
+fn join_paths(paths: Vec<PathBuf>) -> Result<OsString, RubyExecError> {
+    env::join_paths(paths).map_err(|error| {
+        let error = io::Error::new(io::ErrorKind::InvalidInput, error);
+        RubyExecError::ExecFail(error)
+    })
+}
+
+// Splits paths separated by null bytes
+fn split_paths(paths: &str) -> Vec<PathBuf> {
+    paths.split('\0')
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+pub(crate) fn emit_cargo_directives(
+    bin_path: &Path,
+    lib_dir: &Path,
+    include_dir: &Path,
+) {
+    for path in &[bin_path, lib_dir, include_dir] {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    for var in ENV_VARS {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+}
+
+/// The result of executing `ruby`, as returned by
+/// [`Ruby::exec_full`](struct.Ruby.html#method.exec_full).
+#[derive(Clone, Debug)]
+pub struct ExecResult {
+    /// The data written to `stdout`.
+    pub stdout: String,
+    /// The data written to `stderr`.
+    pub stderr: String,
+    /// The exit status.
+    pub status: ExitStatus,
+}
+
+impl ExecResult {
+    pub(crate) fn from_output(output: Output) -> Result<Self, RubyExecError> {
+        Ok(ExecResult {
+            stdout: String::from_utf8(output.stdout)?,
+            stderr: String::from_utf8(output.stderr)?,
+            status: output.status,
+        })
+    }
+
+    /// Returns whether `ruby` exited successfully.
+    #[inline]
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+}
+
+/// The Ruby library's development headers are not installed.
+///
+/// This is wrapped by the `io::Error` returned from header methods such as
+/// [`Ruby::headers`](struct.Ruby.html#method.headers) and can be retrieved
+/// via `io::Error::get_ref` and `downcast_ref`.
+#[derive(Clone, Debug)]
+pub struct MissingDevHeaders {
+    header_dir: PathBuf,
+}
+
+impl MissingDevHeaders {
+    /// Returns the directory where the headers were expected.
+    #[inline]
+    pub fn header_dir(&self) -> &Path {
+        &self.header_dir
+    }
+}
+
+impl std::error::Error for MissingDevHeaders {}
+
+impl Display for MissingDevHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Ruby's development headers were not found in {}; \
+            install them via your package manager, such as `ruby-dev` on \
+            Debian/Ubuntu or `ruby-devel` on Fedora/RHEL",
+            self.header_dir.display(),
+        )
+    }
+}
+
+impl From<MissingDevHeaders> for io::Error {
+    #[inline]
+    fn from(error: MissingDevHeaders) -> Self {
+        io::Error::new(io::ErrorKind::NotFound, error)
+    }
+}
+
 /// The error returned when running `ruby` fails.
 #[derive(Debug)]
 pub enum RubyExecError {
@@ -463,6 +1645,14 @@ pub enum RubyExecError {
     RunFail(Output),
     /// The output of the config key is not encoded as UTF-8.
     Utf8Error(FromUtf8Error),
+    /// The `ruby` executable was killed after running for longer than the
+    /// given duration.
+    Timeout(Duration),
+    /// The output of
+    /// [`Ruby::run_json`](struct.Ruby.html#method.run_json) could not be
+    /// deserialized.
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
 }
 
 impl std::error::Error for RubyExecError {}
@@ -475,6 +1665,11 @@ impl Display for RubyExecError {
                 write!(f, "Failed to execute `ruby`")
             },
             RubyExecError::Utf8Error(error) => error.fmt(f),
+            RubyExecError::Timeout(timeout) => {
+                write!(f, "`ruby` timed out after {:?}", timeout)
+            },
+            #[cfg(feature = "json")]
+            RubyExecError::Json(error) => error.fmt(f),
         }
     }
 }
@@ -491,7 +1686,7 @@ impl From<RubyExecError> for io::Error {
     fn from(error: RubyExecError) -> Self {
         match error {
             RubyExecError::ExecFail(error) => error,
-            error => io::Error::new(io::ErrorKind::Other, error)
+            error => io::Error::other(error)
         }
     }
 }
@@ -512,4 +1707,89 @@ impl RubyExecError {
             Err(RubyExecError::RunFail(output))
         }
     }
+
+    pub(crate) fn process_timeout(
+        command: &mut Command,
+        timeout: Duration,
+    ) -> Result<String, Self> {
+        use std::io::Read;
+        use std::process::Stdio;
+        use std::thread;
+        use std::time::Instant;
+
+        let start = Instant::now();
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Read on other threads so that `ruby` doesn't block on a full pipe
+        fn read_all(stream: Option<impl Read + Send + 'static>)
+            -> thread::JoinHandle<io::Result<Vec<u8>>>
+        {
+            thread::spawn(move || {
+                let mut buf = Vec::new();
+                if let Some(mut stream) = stream {
+                    stream.read_to_end(&mut buf)?;
+                }
+                Ok(buf)
+            })
+        }
+        let stdout = read_all(child.stdout.take());
+        let stderr = read_all(child.stderr.take());
+
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if start.elapsed() >= timeout {
+                child.kill()?;
+                child.wait()?;
+                return Err(RubyExecError::Timeout(timeout));
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        let join = |reader: thread::JoinHandle<io::Result<Vec<u8>>>| {
+            reader.join().unwrap_or_else(|_| {
+                Err(io::Error::other("failed to read the output of `ruby`"))
+            })
+        };
+        let output = Output {
+            status,
+            stdout: join(stdout)?,
+            stderr: join(stderr)?,
+        };
+
+        if output.status.success() {
+            Ok(String::from_utf8(output.stdout)?)
+        } else {
+            Err(RubyExecError::RunFail(output))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn uninstall() {
+        use std::fs;
+
+        let out_dir = std::env::temp_dir().join("aloxide-uninstall");
+        let _ = fs::remove_dir_all(&out_dir);
+        fs::create_dir_all(out_dir.join("bin")).unwrap();
+        fs::write(out_dir.join("bin").join("ruby"), "").unwrap();
+
+        let ruby = || Ruby::new(Version::new(3, 2, 2), &out_dir);
+        let error = ruby().uninstall().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(out_dir.exists());
+
+        fs::write(out_dir.join(INSTALL_MARKER), "3.2.2").unwrap();
+        ruby().uninstall().unwrap();
+        assert!(!out_dir.exists());
+    }
 }
@@ -1,20 +1,360 @@
 use std::{
     collections::HashSet,
+    env,
+    fs,
     io,
+    path::{Path, PathBuf},
+    process::Command,
 };
-use crate::{Ruby, RubyExecError};
+use crate::{Engine, RbConfig, Ruby, RubyExecError};
 use RubyLinkError::*;
 
-fn link_static(lib: &str) {
-    println!("cargo:rustc-link-lib=static={}", lib);
+/// Options for how to link to Ruby, as used by
+/// [`Ruby::link_with`](struct.Ruby.html#method.link_with).
+#[derive(Clone, Debug, Default)]
+pub struct LinkOptions {
+    static_lib: bool,
+    rpath: bool,
+    placement: Placement,
+    framework: bool,
+    weak_framework: bool,
+    musl: bool,
 }
 
-fn link_dynamic(lib: &str) {
-    println!("cargo:rustc-link-lib=dylib={}", lib);
+// Where to put the shared library for it to be found
+#[derive(Clone, Debug, Default)]
+enum Placement {
+    // Symlink into Cargo's `deps` directory on Linux
+    #[default]
+    Default,
+    Symlink(PathBuf),
+    Copy(PathBuf),
+    None,
 }
 
-fn link_framework(lib: &str) {
-    println!("cargo:rustc-link-lib=framework={}", lib);
+impl LinkOptions {
+    /// Creates a new instance for linking to Ruby's shared library.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to link to Ruby's static library instead of its shared
+    /// library.
+    #[inline]
+    pub fn static_lib(mut self, static_lib: bool) -> Self {
+        self.static_lib = static_lib;
+        self
+    }
+
+    /// Embeds the path of Ruby's library directory in the final binary via
+    /// `-Wl,-rpath` when linking to Ruby's shared library.
+    ///
+    /// This allows for the binary to find the library at runtime, including
+    /// libraries whose install name starts with `@rpath` on macOS. On Linux,
+    /// this replaces symlinking the library into Cargo's `deps` directory,
+    /// which doesn't work with custom target directories.
+    ///
+    /// This does nothing on Windows.
+    #[inline]
+    pub fn rpath(mut self) -> Self {
+        self.rpath = true;
+        self.placement = Placement::None;
+        self
+    }
+
+    /// Symlinks the shared library into `dir` instead of Cargo's `deps`
+    /// directory.
+    ///
+    /// By default, the shared library is only symlinked into `deps` on Linux
+    /// for it to be found when linking and running.
+    #[inline]
+    pub fn symlink_into(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.placement = Placement::Symlink(dir.into());
+        self
+    }
+
+    /// Copies the shared library into `dir` instead of symlinking it.
+    ///
+    /// This is useful for filesystems where symbolic links can't be created,
+    /// such as on Windows without the required privilege or within some
+    /// containers.
+    #[inline]
+    pub fn copy_into(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.placement = Placement::Copy(dir.into());
+        self
+    }
+
+    /// Leaves the shared library where it is.
+    #[inline]
+    pub fn no_symlink(mut self) -> Self {
+        self.placement = Placement::None;
+        self
+    }
+
+    // Skips symlinking into Cargo's `deps` directory, which requires
+    // `OUT_DIR`, unless another directory was chosen
+    pub(crate) fn outside_build_script(mut self) -> Self {
+        if let Placement::Default = self.placement {
+            self.placement = Placement::None;
+        }
+        self
+    }
+
+    /// Links to Ruby and its dependencies statically for a fully static
+    /// `*-linux-musl` binary.
+    ///
+    /// Libraries provided by musl's libc, such as `m`, `dl`, and `crypt`, are
+    /// skipped and the rest, such as `z`, are linked statically. Ruby should
+    /// be built via
+    /// [`RubyBuilder::musl`](src/build/struct.RubyBuilder.html#method.musl).
+    #[inline]
+    pub fn musl(mut self) -> Self {
+        self.static_lib = true;
+        self.musl = true;
+        self
+    }
+
+    /// Links to `Ruby.framework` instead of the shared library if Ruby is
+    /// installed within one, such as the system Ruby on macOS.
+    ///
+    /// This does nothing when linking statically.
+    #[inline]
+    pub fn prefer_framework(mut self) -> Self {
+        self.framework = true;
+        self
+    }
+
+    /// Links to the Ruby framework weakly, allowing for the final binary to
+    /// run where the framework is unavailable.
+    ///
+    /// This applies to both [`prefer_framework`](#method.prefer_framework)
+    /// and frameworks named by `LIBRUBYARG_SHARED`.
+    #[inline]
+    pub fn weak_framework(mut self) -> Self {
+        self.weak_framework = true;
+        self
+    }
+}
+
+/// A destination for the `cargo:` directives emitted when linking to Ruby,
+/// as used by [`Ruby::link_to`](struct.Ruby.html#method.link_to).
+///
+/// Build scripts write directives to `io::Stdout` for Cargo to read. A
+/// `Vec<String>` instead collects each directive as a line, such as
+/// "cargo:rustc-link-lib=dylib=ruby", which is useful for testing.
+pub trait CargoOutput {
+    /// Emits `directive` without its "cargo:" prefix, such as
+    /// "rustc-link-search=native=/usr/lib".
+    fn emit(&mut self, directive: &str);
+}
+
+impl CargoOutput for io::Stdout {
+    #[inline]
+    fn emit(&mut self, directive: &str) {
+        println!("cargo:{}", directive);
+    }
+}
+
+impl CargoOutput for Vec<String> {
+    #[inline]
+    fn emit(&mut self, directive: &str) {
+        self.push(format!("cargo:{}", directive));
+    }
+}
+
+/// The libraries, search paths, and arguments for linking to Ruby, for use
+/// outside of build scripts, as returned by
+/// [`Ruby::link_instructions`](struct.Ruby.html#method.link_instructions).
+///
+/// This allows for tools that invoke `cc` or the linker directly, such as a
+/// standalone embedder or a test harness, to link to Ruby via the same logic
+/// as [`Ruby::link`](struct.Ruby.html#method.link). Arguments use the Unix
+/// syntax accepted by GCC, Clang, and `ld`.
+#[derive(Clone, Debug, Default)]
+pub struct LinkInstructions {
+    // In order of emission, since link order matters for static libraries
+    items: Vec<Instruction>,
+}
+
+#[derive(Clone, Debug)]
+enum Instruction {
+    Search(PathBuf),
+    FrameworkSearch(PathBuf),
+    StaticLib(String),
+    DynamicLib(String),
+    Framework(String),
+    Arg(String),
+}
+
+impl CargoOutput for LinkInstructions {
+    fn emit(&mut self, directive: &str) {
+        let (key, val) = match directive.split_once('=') {
+            Some(pair) => pair,
+            None => return,
+        };
+        let (kind, name) = val.split_once('=').unwrap_or(("", val));
+        let item = match (key, kind) {
+            ("rustc-link-search", "framework") => {
+                Instruction::FrameworkSearch(name.into())
+            },
+            ("rustc-link-search", _) => Instruction::Search(name.into()),
+            ("rustc-link-lib", "static") => {
+                Instruction::StaticLib(name.into())
+            },
+            ("rustc-link-lib", "framework") => {
+                Instruction::Framework(name.into())
+            },
+            ("rustc-link-lib", _) => Instruction::DynamicLib(name.into()),
+            ("rustc-link-arg", _) => Instruction::Arg(val.into()),
+            // e.g. "rerun-if-env-changed"
+            _ => return,
+        };
+        self.items.push(item);
+    }
+}
+
+impl LinkInstructions {
+    // The path of the static library `name` within the search paths
+    fn static_lib_path(&self, name: &str) -> Option<PathBuf> {
+        let file = if cfg!(windows) {
+            format!("{}.lib", name)
+        } else {
+            format!("lib{}.a", name)
+        };
+        self.items.iter().find_map(|item| match item {
+            Instruction::Search(dir) => {
+                Some(dir.join(&file)).filter(|path| path.is_file())
+            },
+            _ => None,
+        })
+    }
+
+    fn to_args(&self, linker: bool) -> Vec<String> {
+        let mut args = Vec::new();
+        for item in &self.items {
+            match item {
+                Instruction::Search(dir) => {
+                    args.push(format!("-L{}", dir.display()));
+                },
+                Instruction::FrameworkSearch(dir) => {
+                    args.push(format!("-F{}", dir.display()));
+                },
+                // Pass the archive itself so that a shared library of the
+                // same name isn't chosen instead
+                Instruction::StaticLib(name) => {
+                    args.push(match self.static_lib_path(name) {
+                        Some(path) => path.display().to_string(),
+                        None => format!("-l{}", name),
+                    });
+                },
+                Instruction::DynamicLib(name) => {
+                    args.push(format!("-l{}", name));
+                },
+                Instruction::Framework(name) => {
+                    args.push("-framework".to_owned());
+                    args.push(name.clone());
+                },
+                Instruction::Arg(arg) => match arg.strip_prefix("-Wl,") {
+                    Some(linker_args) if linker => {
+                        args.extend(linker_args.split(',').map(str::to_owned));
+                    },
+                    _ => args.push(arg.clone()),
+                },
+            }
+        }
+        args
+    }
+
+    /// Returns the arguments to pass to a C compiler driver, such as `cc`
+    /// or `cc::Build`, when linking the final binary.
+    #[inline]
+    pub fn to_cc_args(&self) -> Vec<String> {
+        self.to_args(false)
+    }
+
+    /// Returns the arguments to pass to the linker directly, such as `ld`,
+    /// where `-Wl,` arguments are unwrapped.
+    #[inline]
+    pub fn to_linker_args(&self) -> Vec<String> {
+        self.to_args(true)
+    }
+}
+
+fn link_static(out: &mut dyn CargoOutput, lib: &str) {
+    out.emit(&format!("rustc-link-lib=static={}", lib));
+}
+
+fn link_dynamic(out: &mut dyn CargoOutput, lib: &str) {
+    out.emit(&format!("rustc-link-lib=dylib={}", lib));
+}
+
+fn link_framework(out: &mut dyn CargoOutput, lib: &str) {
+    out.emit(&format!("rustc-link-lib=framework={}", lib));
+}
+
+fn link_ruby_framework(
+    out: &mut dyn CargoOutput,
+    lib: &str,
+    weak: bool,
+) {
+    if weak {
+        out.emit(&format!("rustc-link-arg=-Wl,-weak_framework,{}", lib));
+    } else {
+        link_framework(out, lib);
+    }
+}
+
+// The search directory and name of the framework containing `ruby`, e.g.
+// "/System/Library/Frameworks" and "Ruby" for
+// "/System/Library/Frameworks/Ruby.framework/Versions/2.6/usr"
+fn find_framework(ruby: &Ruby) -> Option<(&Path, &str)> {
+    ruby.out_dir().ancestors().find_map(|dir| {
+        let name = dir.file_name()?.to_str()?.strip_suffix(".framework")?;
+        Some((dir.parent()?, name)).filter(|_| !name.is_empty())
+    })
+}
+
+// Libraries whose symbols are part of musl's `libc.a`, which Rust links to
+const MUSL_LIBC_LIBS: &[&str] = &[
+    "c", "m", "dl", "pthread", "rt", "crypt", "util", "resolv",
+];
+
+// Appends `lib` if not already in `libs`, returning whether it was added
+fn insert_lib<'a>(libs: &mut Vec<&'a str>, lib: &'a str) -> bool {
+    let insert = !libs.contains(&lib);
+    if insert {
+        libs.push(lib);
+    }
+    insert
+}
+
+// Links to a dependency of Ruby, which is done statically for musl except
+// for those part of its libc
+fn link_lib(out: &mut dyn CargoOutput, lib: &str, musl: bool) {
+    if !musl {
+        link_dynamic(out, lib);
+    } else if !MUSL_LIBC_LIBS.contains(&lib) {
+        link_static(out, lib);
+    }
+}
+
+// Reads `var` as a boolean and tells Cargo to rerun if it changes, returning
+// `None` if unset
+pub(crate) fn env_flag(
+    out: &mut dyn CargoOutput,
+    var: &str,
+) -> Option<bool> {
+    out.emit(&format!("rerun-if-env-changed={}", var));
+    env::var_os(var).map(|val| parse_flag(&val))
+}
+
+// Empty, "0", "false", "no", and "off" are false, everything else is true
+fn parse_flag(val: &std::ffi::OsStr) -> bool {
+    let val = val.to_string_lossy();
+    !["", "0", "false", "no", "off"]
+        .iter()
+        .any(|f| val.trim().eq_ignore_ascii_case(f))
 }
 
 // e.g. "-llibruby"
@@ -22,120 +362,451 @@ fn lib_name(lib_flag: &str) -> &str {
     &lib_flag[2..]
 }
 
-// e.g. "user32.lib"
-fn lib_name_msvc(lib_flag: &str) -> &str {
-    &lib_flag[..(lib_flag.len() - 4)]
+// e.g. "-Wl,--export-dynamic", returning `None` for flags that only affect
+// compilation, such as "-Wall", "-Wno-unused" or "-Wa,--noexecstack"
+fn linker_arg(flag: &str) -> Option<&str> {
+    match flag.strip_prefix("-Wl,") {
+        Some(args) if !args.is_empty() => Some(flag),
+        _ => None,
+    }
+}
+
+// e.g. "user32.lib" or "x64-vcruntime140-ruby320.lib", ignoring other flags
+fn lib_name_msvc(lib: &str) -> Option<&str> {
+    let len = lib.len().checked_sub(4)?;
+    match (lib.get(..len), lib.get(len..)) {
+        (Some(name), Some(ext)) if ext.eq_ignore_ascii_case(".lib") => {
+            Some(name).filter(|name| !name.is_empty())
+        },
+        _ => None,
+    }
+}
+
+// e.g. "libx64-ucrt-ruby320.dll.a" or "libx64-ucrt-ruby320-static.a"
+fn lib_name_mingw(lib: &str) -> Option<&str> {
+    let name = lib.strip_prefix("lib")?;
+    name.strip_suffix(".dll.a")
+        .or_else(|| name.strip_suffix(".a"))
+        .filter(|name| !name.is_empty())
 }
 
-#[cfg(target_os = "linux")]
-fn os_helper(ruby: &Ruby, static_lib: bool) -> Result<(), RubyLinkError> {
-    use std::env;
-    use std::os::unix::fs::symlink;
-    use std::path::{Path, PathBuf};
+// Libraries that Rust's `*-windows-gnu` targets already link to, which would
+// otherwise conflict with the C runtime chosen by Rust
+const MINGW_RUNTIME_LIBS: &[&str] = &["msvcrt", "ucrt", "mingw32", "mingwex"];
+
+// Links to Ruby's import library (`LIBRUBY`) or static library (`LIBRUBY_A`)
+// as installed by RubyInstaller, along with the system libraries in `LIBS`,
+// such as "-lws2_32"
+fn link_mingw(
+    out: &mut dyn CargoOutput,
+    ruby: &Ruby,
+    config: &RbConfig,
+    static_lib: bool,
+) -> Result<(), RubyLinkError> {
+    let get = |key: &str| config.get(key).unwrap_or_default();
+
+    let lib = get(if static_lib { "LIBRUBY_A" } else { "LIBRUBY" });
+    let lib_name = match lib_name_mingw(lib) {
+        Some(name) => name,
+        None => return Err(MissingLibs { static_lib }),
+    };
+    if !ruby.lib_dir().join(lib).exists() {
+        if static_lib {
+            return Err(MissingLibs { static_lib });
+        }
+        return Err(MissingImportLib(ruby.lib_dir().join(lib)));
+    }
 
-    // Rust can't find and link to the Ruby's shared object ('.so') library when
-    // linking dynamically and so we need to hold its hand by symlinking it into
-    // the 'deps'
     if static_lib {
-        return Ok(());
-    }
-
-    // Get the 'deps' directory in Cargo's 'target' directory by going to the
-    // parent directory of 'build' and then into 'deps'
-    let mut link_path = match env::var_os("OUT_DIR") {
-        Some(out_dir) => {
-            let mut out_dir = PathBuf::from(out_dir);
-            for _ in 0..3 {
-                if !out_dir.pop() {
-                    let mesg = "Could not find 'deps' directory";
-                    let kind = io::ErrorKind::NotFound;
-                    return Err(io::Error::new(kind, mesg).into());
-                }
+        link_static(out, lib_name);
+    } else {
+        link_dynamic(out, lib_name);
+    }
+
+    let mut seen = HashSet::new();
+    seen.insert(lib_name);
+    seen.extend(MINGW_RUNTIME_LIBS);
+
+    let libs = config.aux_libs(static_lib).unwrap_or_default();
+    for arg in libs.split_ascii_whitespace() {
+        if let Some(lib) = arg.strip_prefix("-l") {
+            // e.g. "-lmsvcrt-ruby240" for older Rubies
+            if lib.starts_with("msvcrt-ruby") || !seen.insert(lib) {
+                continue;
             }
-            out_dir.push("deps");
-            out_dir
-        },
-        None => return Err(RubyLinkError::MissingEnvVar("OUT_DIR")),
+            link_dynamic(out, lib);
+        } else if let Some(dir) = arg.strip_prefix("-L") {
+            out.emit(&format!("rustc-link-search=native={}", dir));
+        }
+        // Other flags, such as "-Wl,..." and "-pthread", are for GCC itself
+    }
+
+    Ok(())
+}
+
+// Returns the value of `$OUT_DIR`
+fn out_dir() -> Result<PathBuf, RubyLinkError> {
+    env::var_os("OUT_DIR")
+        .map(PathBuf::from)
+        .ok_or(RubyLinkError::MissingEnvVar("OUT_DIR"))
+}
+
+// The `/MACHINE` value for `lib.exe` and `-m` value for `dlltool`
+fn msvc_machine(target: &str) -> (&'static str, &'static str) {
+    if target.starts_with("x86_64") || target.starts_with("x64") {
+        ("X64", "i386:x86-64")
+    } else if target.starts_with("aarch64") || target.starts_with("arm64") {
+        ("ARM64", "arm64")
+    } else {
+        ("X86", "i386")
+    }
+}
+
+// Creates the import library `lib` for `dll` within `dir` by generating a
+// module-definition file from its exports and then passing that to `lib.exe`,
+// or to `dlltool` if that is unavailable
+//
+// The exports are listed via the MSVC toolchain's `dumpbin`, falling back to
+// MinGW's `gendef`
+fn generate_import_lib(
+    dll: &Path,
+    lib: &str,
+    dir: &Path,
+    target: &str,
+) -> Result<PathBuf, RubyLinkError> {
+    let run = |command: &mut Command| -> Result<Vec<u8>, RubyLinkError> {
+        let output = command.output()?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(RubyLinkError::ImportLib(output))
+        }
     };
 
-    let version = ruby.version();
-    let so_name = format!("libruby.so.{}.{}", version.major, version.minor);
-    let so_path = ruby.lib_dir().join(&so_name);
+    let rust_target = env::var("TARGET").unwrap_or_default();
+    let dll_name = dll.file_name().unwrap_or(dll.as_os_str());
 
-    fn is_symlink(path: &Path) -> io::Result<bool> {
-        match std::fs::symlink_metadata(path) {
-            Ok(metadata) => Ok(metadata.file_type().is_symlink()),
-            Err(error) => match error.kind() {
-                io::ErrorKind::NotFound => Ok(false),
-                _ => Err(error),
-            },
+    let dumpbin = crate::util::msvc_dumpbin(&rust_target).and_then(|mut cmd| {
+        let exports = run(cmd.arg("/NOLOGO").arg("/EXPORTS").arg(dll)).ok()?;
+        let exports = String::from_utf8_lossy(&exports);
+        Some(def_from_dumpbin(&dll_name.to_string_lossy(), &exports))
+    });
+    let def = match dumpbin {
+        Some(def) => def.into_bytes(),
+        None => run(Command::new("gendef").arg("-").arg(dll))?,
+    };
+
+    let def_path = dir.join(lib).with_extension("def");
+    fs::write(&def_path, def)?;
+
+    let lib_path = dir.join(lib);
+    let (machine, dlltool_machine) = msvc_machine(target);
+
+    match crate::util::msvc_lib(&rust_target) {
+        Some(mut lib_exe) => run(lib_exe
+            .arg("/NOLOGO")
+            .arg(format!("/MACHINE:{}", machine))
+            .arg(format!("/DEF:{}", def_path.display()))
+            .arg(format!("/OUT:{}", lib_path.display())))?,
+        None => run(Command::new("dlltool")
+            .args(["-m", dlltool_machine, "-d"])
+            .arg(&def_path)
+            .arg("-D")
+            .arg(dll_name)
+            .arg("-l")
+            .arg(&lib_path))?,
+    };
+
+    Ok(lib_path)
+}
+
+// Returns a module-definition file for `dll` from the output of
+// `dumpbin /EXPORTS`
+fn def_from_dumpbin(dll: &str, exports: &str) -> String {
+    let mut def = format!("LIBRARY \"{}\"\nEXPORTS\n", dll);
+    for name in dumpbin_exports(exports) {
+        def.push_str("    ");
+        def.push_str(name);
+        def.push('\n');
+    }
+    def
+}
+
+// Returns the names exported in the output of `dumpbin /EXPORTS`, whose table
+// of exports looks like:
+//
+//     ordinal hint RVA      name
+//
+//           1    0 00001000 rb_ary_new
+//           2    1          rb_old (forwarded to other.rb_new)
+pub(crate) fn dumpbin_exports(exports: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+
+    let table = exports.lines()
+        .skip_while(|line| !line.trim_start().starts_with("ordinal"))
+        .skip(1);
+    for line in table {
+        let mut parts = line.split_whitespace();
+        let ordinal = match parts.next() {
+            Some(ordinal) => ordinal,
+            // Blank lines surround the table
+            None => continue,
+        };
+        if !ordinal.bytes().all(|b| b.is_ascii_digit()) {
+            break;
+        }
+
+        // The RVA is omitted for forwarded exports
+        let rest: Vec<&str> = parts.skip(1).collect();
+        let is_rva = |part: &str| part.bytes().all(|b| b.is_ascii_hexdigit());
+        let name = match rest.as_slice() {
+            [rva, name, ..] if is_rva(rva) => name,
+            [name, ..] => name,
+            [] => continue,
+        };
+        if *name != "[NONAME]" {
+            names.push(*name);
+        }
+    }
+    names
+}
+
+// Links to Ruby's import library (`LIBRUBY`) or static library (`LIBRUBY_A`)
+// along with the system libraries in `LIBS`, such as "user32.lib"
+fn link_msvc(
+    out: &mut dyn CargoOutput,
+    ruby: &Ruby,
+    config: &RbConfig,
+    static_lib: bool,
+) -> Result<(), RubyLinkError> {
+    let get = |key: &str| config.get(key).unwrap_or_default();
+
+    let lib = get(if static_lib { "LIBRUBY_A" } else { "LIBRUBY" });
+    let lib_name = match lib_name_msvc(lib) {
+        Some(name) => name,
+        None => return Err(MissingLibs { static_lib }),
+    };
+
+    if !ruby.lib_dir().join(lib).exists() {
+        if static_lib {
+            return Err(MissingLibs { static_lib });
+        }
+
+        // Some distributions only provide the DLL, which is installed
+        // alongside `ruby.exe`
+        let dll = ruby.bin_dir().join(get("LIBRUBY_SO"));
+        if !dll.is_file() {
+            return Err(MissingImportLib(ruby.lib_dir().join(lib)));
         }
+        let out_dir = out_dir()?;
+        generate_import_lib(&dll, lib, &out_dir, get("target"))?;
+        out.emit(&format!("rustc-link-search=native={}", out_dir.display()));
     }
 
-    link_path.push(&so_name);
-    if !is_symlink(&link_path)? {
-        symlink(&so_path, link_path)?;
+    if static_lib {
+        link_static(out, lib_name);
+    } else {
+        link_dynamic(out, lib_name);
+    }
+
+    let mut seen = HashSet::new();
+    for libs in [get("LIBS"), get("MAINLIBS")].iter() {
+        for lib in libs.split_ascii_whitespace().filter_map(lib_name_msvc) {
+            if seen.insert(lib) {
+                link_dynamic(out, lib);
+            }
+        }
     }
 
     Ok(())
 }
 
-#[cfg(not(target_os = "linux"))]
-fn os_helper(_ruby: &Ruby, _static_lib: bool) -> Result<(), RubyLinkError> {
+// Returns Cargo's `deps` directory by going to the parent directory of
+// `build` from `$OUT_DIR` and then into `deps`
+fn deps_dir() -> Result<PathBuf, RubyLinkError> {
+    let mut dir = out_dir()?;
+    for _ in 0..3 {
+        if !dir.pop() {
+            let mesg = "Could not find 'deps' directory";
+            let kind = io::ErrorKind::NotFound;
+            return Err(io::Error::new(kind, mesg).into());
+        }
+    }
+    dir.push("deps");
+    Ok(dir)
+}
+
+#[cfg(unix)]
+fn symlink_file(src: &Path, dst: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(src, dst)
+}
+
+#[cfg(windows)]
+fn symlink_file(src: &Path, dst: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(src, dst)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink_file(_src: &Path, _dst: &Path) -> io::Result<()> {
+    let mesg = "Symbolic links are not supported on this platform";
+    Err(io::Error::new(io::ErrorKind::Other, mesg))
+}
+
+// Symlinks or copies the shared library (`LIBRUBY_SO`) and its aliases, such
+// as "libruby.so.3.2", into a directory where they can be found when linking
+// and running
+fn place_shared_lib(
+    ruby: &Ruby,
+    config: &RbConfig,
+    placement: &Placement,
+) -> Result<(), RubyLinkError> {
+    let (dir, copy) = match placement {
+        // Rust can't find and link to the Ruby's shared object ('.so')
+        // library when linking dynamically and so we need to hold its hand
+        // by symlinking it into the 'deps'
+        Placement::Default if cfg!(target_os = "linux") => (deps_dir()?, false),
+        Placement::Default | Placement::None => return Ok(()),
+        Placement::Symlink(dir) => (dir.clone(), false),
+        Placement::Copy(dir) => (dir.clone(), true),
+    };
+
+    let mut names = vec![config.get("LIBRUBY_SO").unwrap_or_default()];
+    if let Some(aliases) = config.get("LIBRUBY_ALIASES") {
+        names.extend(aliases.split_ascii_whitespace());
+    }
+
+    for name in names.into_iter().filter(|name| !name.is_empty()) {
+        // DLLs are installed alongside `ruby.exe` on Windows
+        let src = [ruby.lib_dir(), ruby.bin_dir()].iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.exists());
+        let src = match src {
+            Some(src) => src,
+            None => continue,
+        };
+        let dst = dir.join(name);
+
+        if copy {
+            fs::copy(&src, &dst)?;
+            continue;
+        }
+        if fs::read_link(&dst).ok().as_ref() == Some(&src) {
+            continue;
+        }
+        match fs::remove_file(&dst) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                return Err(error.into());
+            },
+            _ => {},
+        }
+        symlink_file(&src, &dst)?;
+    }
+
     Ok(())
 }
 
-pub(crate) fn link(ruby: &Ruby, static_lib: bool) -> Result<(), RubyLinkError> {
-    os_helper(ruby, static_lib)?;
+pub(crate) fn link(
+    ruby: &Ruby,
+    options: &LinkOptions,
+    out: &mut dyn CargoOutput,
+) -> Result<(), RubyLinkError> {
+    // Allow for end users to flip linkage without patching `-sys` crates
+    let aloxide_static = env_flag(out, "ALOXIDE_STATIC_RUBY");
+    let ruby_static = env_flag(out, "RUBY_STATIC");
+    let static_lib = aloxide_static.or(ruby_static)
+        .unwrap_or(options.static_lib);
+    let placement = if env_flag(out, "ALOXIDE_NO_SYMLINK") == Some(true) {
+        &Placement::None
+    } else {
+        &options.placement
+    };
+
+    // Other implementations don't provide a C library to link to
+    let engine = ruby.engine()?;
+    if !engine.is_mri() {
+        return Err(UnsupportedEngine(engine.clone()));
+    }
 
-    println!("cargo:rustc-link-search=native={}", ruby.lib_dir().display());
+    // Without this, a missing archive shows up as many unresolved symbols
+    if static_lib && ruby.static_lib_path()?.is_none() {
+        return Err(MissingStaticLib {
+            lib_dir: ruby.lib_dir().to_owned(),
+            configure_flag: "--enable-install-static-library",
+        });
+    }
 
-    let target = ruby.get_config("target")?;
-    let target_msvc = target.contains("msvc") || target.contains("mswin");
-    let lib_name = if target_msvc { lib_name_msvc } else { lib_name };
+    out.emit(&format!("rustc-link-search=native={}", ruby.lib_dir().display()));
+
+    let config = ruby.config()?;
+    let get = |key: &str| config.get(key).unwrap_or_default();
+
+    if !static_lib {
+        place_shared_lib(ruby, config, placement)?;
+    }
+
+    let target = get("target");
+    if target.contains("msvc") || target.contains("mswin") {
+        return link_msvc(out, ruby, config, static_lib);
+    }
+    if target.contains("mingw") {
+        return link_mingw(out, ruby, config, static_lib);
+    }
+
+    if !static_lib && options.rpath {
+        let lib_dir = ruby.lib_dir().display();
+        out.emit(&format!("rustc-link-arg=-Wl,-rpath,{}", lib_dir));
+    }
 
     let key = if static_lib {
         "LIBRUBYARG_STATIC"
     } else {
         "LIBRUBYARG_SHARED"
     };
-    let args = ruby.get_config(key)?;
+    let args = get(key).to_owned();
 
-    if args.trim().is_empty() {
-        return Err(RubyLinkError::MissingLibs { static_lib });
-    }
-
-    let libs = ruby.libs()?;
-    let main_libs = ruby.main_libs()?;
-    let so_libs = ruby.so_libs()?;
+    let ruby_lib = match config.lib_name(static_lib) {
+        Some(ruby_lib) if !args.trim().is_empty() => ruby_lib,
+        _ => return Err(RubyLinkError::MissingLibs { static_lib }),
+    };
 
-    let mut dy_libs = HashSet::new();
-    for libs in [&libs, &main_libs, &so_libs].iter() {
+    // Kept in order of appearance for the same output on every run
+    let mut dy_libs = Vec::new();
+    for libs in [get("LIBS"), get("MAINLIBS"), get("SOLIBS")].iter() {
         if *libs != "nil" {
-            dy_libs.extend(libs.split_ascii_whitespace().map(lib_name));
+            for lib in libs.split_ascii_whitespace().map(lib_name) {
+                insert_lib(&mut dy_libs, lib);
+            }
         }
     }
 
-    let ruby_lib = ruby.lib_name(static_lib)?;
-    if static_lib {
-        link_static(&ruby_lib);
-    } else {
-        link_dynamic(&ruby_lib);
-    }
+    let is_ruby_framework = |name: &str| name.eq_ignore_ascii_case(&ruby_lib);
+    let args_framework = args.split_ascii_whitespace()
+        .skip_while(|&arg| arg != "-framework")
+        .nth(1)
+        .is_some_and(is_ruby_framework);
 
-    let seen_lib = |lib: &str| {
-        lib == ruby_lib || dy_libs.contains(lib)
+    let found_framework = if options.framework && !static_lib {
+        find_framework(ruby)
+    } else {
+        None
     };
 
-    for lib in &dy_libs {
-        link_dynamic(lib);
+    if let Some((dir, name)) = found_framework {
+        out.emit(&format!("rustc-link-search=framework={}", dir.display()));
+        link_ruby_framework(out, name, options.weak_framework);
+    } else if static_lib {
+        link_static(out, &ruby_lib);
+    } else if !args_framework {
+        link_dynamic(out, &ruby_lib);
     }
 
-    // TODO: Figure out whether `args` should be evaluated for MSVC
-    if target_msvc {
-        return Ok(());
+    for lib in &dy_libs {
+        link_lib(out, lib, options.musl);
     }
 
+    let mut seen_libs = dy_libs;
+    seen_libs.push(&ruby_lib);
+
     // Need to call `next()` in "-framework" case
     let mut args_iter = args.split_ascii_whitespace();
 
@@ -145,24 +816,28 @@ pub(crate) fn link(ruby: &Ruby, static_lib: bool) -> Result<(), RubyLinkError> {
         }
         let (opt, val) = arg.split_at(2);
         match opt {
-            "-l" => if !seen_lib(val) {
-                link_dynamic(val);
+            "-l" => if insert_lib(&mut seen_libs, val) {
+                link_lib(out, val, options.musl);
             },
             "-L" => {
-                println!("cargo:rustc-link-search=native={}", val);
+                out.emit(&format!("rustc-link-search=native={}", val));
             },
             "-F" => {
-                println!("cargo:rustc-link-search=framework={}", val);
+                out.emit(&format!("rustc-link-search=framework={}", val));
             },
-            "-W" => {
-                continue;
+            "-W" => if let Some(arg) = linker_arg(arg) {
+                out.emit(&format!("rustc-link-arg={}", arg));
             },
             _ => if arg == "-framework" {
                 let framework = match args_iter.next() {
                     Some(arg) => arg,
                     None => return Err(MissingFramework(args)),
                 };
-                link_framework(framework);
+                if !is_ruby_framework(framework) {
+                    link_framework(out, framework);
+                } else if found_framework.is_none() {
+                    link_ruby_framework(out, framework, options.weak_framework);
+                }
             } else {
                 return Err(UnknownFlags(args));
             }
@@ -187,8 +862,25 @@ pub enum RubyLinkError {
         /// Whether linking to Ruby statically.
         static_lib: bool
     },
+    /// The static Ruby library does not exist, which happens when Ruby was
+    /// configured without `--enable-install-static-library`.
+    MissingStaticLib {
+        /// The directory where the library was expected.
+        lib_dir: PathBuf,
+        /// The flag to pass to Ruby's `configure` script to install the
+        /// static library.
+        configure_flag: &'static str,
+    },
+    /// The Ruby implementation has no library to link to.
+    UnsupportedEngine(Engine),
     /// An environment variable required for linking is missing.
     MissingEnvVar(&'static str),
+    /// The import library at the given path does not exist. For MSVC, the
+    /// DLL to generate it from does not exist either.
+    MissingImportLib(PathBuf),
+    /// Generating an MSVC import library via `gendef` and either `lib.exe`
+    /// or `dlltool` failed.
+    ImportLib(std::process::Output),
     /// An I/O error occurred.
     Io(io::Error),
 }
@@ -206,3 +898,266 @@ impl From<io::Error> for RubyLinkError {
         RubyLinkError::Io(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn frameworks() {
+        use crate::Version;
+
+        let prefix = "/System/Library/Frameworks/Ruby.framework/Versions/2.6";
+        let prefix = Path::new(prefix).join("usr");
+        let ruby = Ruby::new(Version::new(2, 6, 10), prefix);
+        let (dir, name) = find_framework(&ruby).unwrap();
+        assert_eq!(dir, Path::new("/System/Library/Frameworks"));
+        assert_eq!(name, "Ruby");
+
+        let ruby = Ruby::new(Version::new(3, 2, 2), "/usr/local");
+        assert!(find_framework(&ruby).is_none());
+    }
+
+    #[test]
+    fn flags() {
+        for val in &["1", "yes", "true", "ON"] {
+            assert!(parse_flag(val.as_ref()), "{}", val);
+        }
+        for val in &["", "0", "false", "No", " off "] {
+            assert!(!parse_flag(val.as_ref()), "{:?}", val);
+        }
+    }
+
+    #[test]
+    fn linker_args() {
+        let rpath = "-Wl,-rpath,/usr/lib";
+        assert_eq!(linker_arg(rpath), Some(rpath));
+        let export = "-Wl,--export-dynamic";
+        assert_eq!(linker_arg(export), Some(export));
+        assert_eq!(linker_arg("-Wl,"), None);
+        assert_eq!(linker_arg("-Wall"), None);
+        assert_eq!(linker_arg("-Wno-unused-parameter"), None);
+        assert_eq!(linker_arg("-Wa,--noexecstack"), None);
+    }
+
+    // A `Ruby` with `config` already retrieved
+    fn synthetic_ruby(dump: &str) -> Ruby {
+        let ruby = Ruby::new(crate::Version::new(3, 2, 2), "/r");
+        ruby.config.get_or_init(|| RbConfig::parse(dump));
+        ruby.engine.get_or_init(|| Engine::Mri);
+        ruby
+    }
+
+    #[test]
+    fn link_directives() {
+        let ruby = synthetic_ruby("target\0x86_64-pc-linux-gnu\0\
+            RUBY_SO_NAME\0ruby\0\
+            LIBRUBYARG_SHARED\0-Wl,-rpath,/r/lib -L/r/lib -lruby\0\
+            LIBS\0-lm -lpthread\0");
+        let options = LinkOptions::new().no_symlink();
+
+        let mut out = Vec::new();
+        ruby.link_to(&options, &mut out).unwrap();
+
+        for directive in &[
+            "cargo:rustc-link-search=native=/r/lib",
+            "cargo:rustc-link-lib=dylib=ruby",
+            "cargo:rustc-link-lib=dylib=m",
+            "cargo:rustc-link-lib=dylib=pthread",
+            "cargo:rustc-link-arg=-Wl,-rpath,/r/lib",
+            "cargo:rerun-if-env-changed=ALOXIDE_STATIC_RUBY",
+        ] {
+            assert!(out.iter().any(|d| d == directive), "{}", directive);
+        }
+        assert!(!out.iter().any(|d| d.contains("ruby-static")));
+    }
+
+    // The `rustc-link-*` directives emitted for `dump`
+    fn link_libs(dump: &str, options: &LinkOptions) -> Vec<String> {
+        let mut out = Vec::new();
+        synthetic_ruby(dump).link_to(options, &mut out).unwrap();
+        out.retain(|d| d.starts_with("cargo:rustc-link-"));
+        out
+    }
+
+    #[test]
+    fn link_order() {
+        let dump = "target\0x86_64-pc-linux-gnu\0RUBY_SO_NAME\0ruby\0\
+            LIBRUBYARG_SHARED\0-L/r/lib -lruby -lz -lm\0\
+            LIBS\0-lz -lrt -lrt -lgmp -ldl -lcrypt -lm\0\
+            MAINLIBS\0-lpthread -lz\0SOLIBS\0nil\0";
+        let options = LinkOptions::new().no_symlink();
+
+        for _ in 0..3 {
+            assert_eq!(link_libs(dump, &options), [
+                "cargo:rustc-link-search=native=/r/lib",
+                "cargo:rustc-link-lib=dylib=ruby",
+                "cargo:rustc-link-lib=dylib=z",
+                "cargo:rustc-link-lib=dylib=rt",
+                "cargo:rustc-link-lib=dylib=gmp",
+                "cargo:rustc-link-lib=dylib=dl",
+                "cargo:rustc-link-lib=dylib=crypt",
+                "cargo:rustc-link-lib=dylib=m",
+                "cargo:rustc-link-lib=dylib=pthread",
+                "cargo:rustc-link-search=native=/r/lib",
+            ]);
+        }
+    }
+
+    #[test]
+    fn link_order_musl() {
+        let dir = std::env::temp_dir().join("aloxide-link-order-musl");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("lib").join("libruby-static.a"), "").unwrap();
+
+        let ruby = Ruby::new(crate::Version::new(3, 2, 2), &dir);
+        ruby.engine.get_or_init(|| Engine::Mri);
+        ruby.config.get_or_init(|| RbConfig::parse(
+            "target\0x86_64-unknown-linux-musl\0RUBY_SO_NAME\0ruby\0\
+            LIBRUBYARG_STATIC\0-lruby-static -lz -lm\0\
+            LIBS\0-lz -lrt -ldl -lcrypt -lm -lgmp\0"
+        ));
+
+        let options = LinkOptions::new().musl();
+        let mut out = Vec::new();
+        let result = ruby.link_to(&options, &mut out);
+        fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+
+        out.retain(|d| d.starts_with("cargo:rustc-link-lib="));
+        assert_eq!(out, [
+            "cargo:rustc-link-lib=static=ruby-static",
+            "cargo:rustc-link-lib=static=z",
+            "cargo:rustc-link-lib=static=gmp",
+        ]);
+    }
+
+    #[test]
+    fn instructions() {
+        let mut instructions = LinkInstructions::default();
+        for directive in &[
+            "rerun-if-env-changed=RUBY_STATIC",
+            "rustc-link-search=native=/r/lib",
+            "rustc-link-lib=dylib=ruby",
+            "rustc-link-lib=dylib=m",
+            "rustc-link-search=framework=/Library/Frameworks",
+            "rustc-link-lib=framework=CoreFoundation",
+            "rustc-link-lib=static=aloxide-missing",
+            "rustc-link-arg=-Wl,-rpath,/r/lib",
+        ] {
+            instructions.emit(directive);
+        }
+
+        assert_eq!(instructions.to_cc_args(), [
+            "-L/r/lib",
+            "-lruby",
+            "-lm",
+            "-F/Library/Frameworks",
+            "-framework",
+            "CoreFoundation",
+            "-laloxide-missing",
+            "-Wl,-rpath,/r/lib",
+        ]);
+        let linker_args = instructions.to_linker_args();
+        assert_eq!(linker_args[linker_args.len() - 2..], ["-rpath", "/r/lib"]);
+    }
+
+    #[test]
+    fn msvc_lib_names() {
+        let lib = "x64-vcruntime140-ruby320.lib";
+        assert_eq!(lib_name_msvc(lib), Some("x64-vcruntime140-ruby320"));
+        assert_eq!(lib_name_msvc("USER32.LIB"), Some("USER32"));
+        assert_eq!(lib_name_msvc(".lib"), None);
+        assert_eq!(lib_name_msvc("lib"), None);
+        assert_eq!(lib_name_msvc("-link"), None);
+        assert_eq!(lib_name_msvc("é.lib"), Some("é"));
+    }
+
+    #[test]
+    fn msvc_machines() {
+        assert_eq!(msvc_machine("x64-mswin64_140"), ("X64", "i386:x86-64"));
+        assert_eq!(msvc_machine("x86_64-w64-mingw32"), ("X64", "i386:x86-64"));
+        assert_eq!(msvc_machine("aarch64-mingw-ucrt"), ("ARM64", "arm64"));
+        assert_eq!(msvc_machine("arm64-mswin64_140"), ("ARM64", "arm64"));
+        assert_eq!(msvc_machine("i386-mswin32_140"), ("X86", "i386"));
+    }
+
+    #[test]
+    fn dumpbin_def() {
+        let exports = "\
+Dump of file x64-vcruntime140-ruby320.dll
+
+File Type: DLL
+
+  Section contains the following exports for x64-vcruntime140-ruby320.dll
+
+    00000000 characteristics
+         600 number of functions
+         600 number of names
+
+    ordinal hint RVA      name
+
+          1    0 00001000 rb_ary_new
+          2    1 0000A2F0 ruby_setup
+          3    2          rb_old (forwarded to other.rb_new)
+
+  Summary
+
+        1000 .data
+";
+        let def = def_from_dumpbin("x64-vcruntime140-ruby320.dll", exports);
+        assert_eq!(def, "\
+LIBRARY \"x64-vcruntime140-ruby320.dll\"
+EXPORTS
+    rb_ary_new
+    ruby_setup
+    rb_old
+");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn shared_lib_placement() {
+        use crate::Version;
+
+        let dir = std::env::temp_dir().join("aloxide-shared-lib-placement");
+        let _ = fs::remove_dir_all(&dir);
+        let ruby = Ruby::new(Version::new(3, 2, 2), dir.join("ruby"));
+        let dst = dir.join("dst");
+        fs::create_dir_all(ruby.lib_dir()).unwrap();
+        fs::create_dir_all(&dst).unwrap();
+        fs::write(ruby.lib_dir().join("libruby.so.3.2.2"), "so").unwrap();
+
+        let dump = "LIBRUBY_SO\0libruby.so.3.2.2\0\
+            LIBRUBY_ALIASES\0libruby.so.3.2 libruby.so\0";
+        let config = RbConfig::parse(dump);
+
+        let placement = Placement::Symlink(dst.clone());
+        place_shared_lib(&ruby, &config, &placement).unwrap();
+        place_shared_lib(&ruby, &config, &placement).unwrap();
+        let link = fs::read_link(dst.join("libruby.so.3.2.2")).unwrap();
+        assert_eq!(link, ruby.lib_dir().join("libruby.so.3.2.2"));
+        assert!(!dst.join("libruby.so.3.2").exists());
+
+        let placement = Placement::Copy(dst.clone());
+        fs::remove_file(dst.join("libruby.so.3.2.2")).unwrap();
+        place_shared_lib(&ruby, &config, &placement).unwrap();
+        let copy = dst.join("libruby.so.3.2.2");
+        assert!(!fs::symlink_metadata(&copy).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(copy).unwrap(), "so");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mingw_lib_names() {
+        let lib = "libx64-ucrt-ruby320.dll.a";
+        assert_eq!(lib_name_mingw(lib), Some("x64-ucrt-ruby320"));
+        let lib = "libx64-ucrt-ruby320-static.a";
+        assert_eq!(lib_name_mingw(lib), Some("x64-ucrt-ruby320-static"));
+        assert_eq!(lib_name_mingw("lib.a"), None);
+        assert_eq!(lib_name_mingw("ruby.dll"), None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Engine, RbConfig, Ruby, RubyExecError};
+
+#[cfg(feature = "json")]
+use std::{fmt, io};
+
+/// A snapshot of a Ruby installation, including its engine and build
+/// configuration.
+///
+/// This allows for retrieving a previously probed installation without
+/// running `ruby` again. See
+/// [`Ruby::save_metadata`](struct.Ruby.html#method.save_metadata) and
+/// [`Ruby::load_metadata`](struct.Ruby.html#method.load_metadata).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RubyMetadata {
+    #[serde(flatten)]
+    ruby: Ruby,
+    engine: Engine,
+    config: RbConfig,
+}
+
+impl RubyMetadata {
+    /// Creates a snapshot of `ruby`, running it to retrieve any values that
+    /// have not yet been retrieved.
+    pub fn new(ruby: &Ruby) -> Result<Self, RubyExecError> {
+        Ok(RubyMetadata {
+            ruby: Ruby {
+                version: ruby.version.clone(),
+                out_dir: ruby.out_dir.clone(),
+                lib_dir: ruby.lib_dir.clone(),
+                bin_path: ruby.bin_path.clone(),
+                config: OnceLock::new(),
+                engine: OnceLock::new(),
+            },
+            engine: ruby.engine()?.clone(),
+            config: ruby.config()?.clone(),
+        })
+    }
+
+    /// Returns the installation without its engine and configuration.
+    #[inline]
+    pub fn ruby(&self) -> &Ruby {
+        &self.ruby
+    }
+
+    /// Returns the Ruby implementation.
+    #[inline]
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Returns the contents of `RbConfig::CONFIG`.
+    #[inline]
+    pub fn config(&self) -> &RbConfig {
+        &self.config
+    }
+
+    /// Converts `self` into a `Ruby` whose engine and configuration are
+    /// already retrieved.
+    pub fn into_ruby(self) -> Ruby {
+        let RubyMetadata { ruby, engine, config } = self;
+        ruby.engine.get_or_init(|| engine);
+        ruby.config.get_or_init(|| config);
+        ruby
+    }
+}
+
+/// The error returned when saving or loading a
+/// [`RubyMetadata`](struct.RubyMetadata.html).
+#[cfg(feature = "json")]
+#[derive(Debug)]
+pub enum MetadataError {
+    /// Failed to run `ruby` to retrieve the metadata.
+    Exec(RubyExecError),
+    /// Failed to read or write the metadata file.
+    Io(io::Error),
+    /// Failed to convert the metadata to or from JSON.
+    Json(serde_json::Error),
+}
+
+#[cfg(feature = "json")]
+impl std::error::Error for MetadataError {}
+
+#[cfg(feature = "json")]
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetadataError::Exec(error) => error.fmt(f),
+            MetadataError::Io(error) => error.fmt(f),
+            MetadataError::Json(error) => error.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<RubyExecError> for MetadataError {
+    #[inline]
+    fn from(error: RubyExecError) -> Self {
+        MetadataError::Exec(error)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<io::Error> for MetadataError {
+    #[inline]
+    fn from(error: io::Error) -> Self {
+        MetadataError::Io(error)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for MetadataError {
+    #[inline]
+    fn from(error: serde_json::Error) -> Self {
+        MetadataError::Json(error)
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+    use crate::Version;
+
+    #[test]
+    fn round_trip() {
+        let ruby = Ruby::new(Version::new(3, 2, 2), "/opt/rubies/ruby-3.2.2");
+        let metadata = RubyMetadata {
+            ruby,
+            engine: Engine::Mri,
+            config: RbConfig::parse("RUBY_SO_NAME\0ruby\0"),
+        };
+
+        let json = serde_json::to_string(&metadata).unwrap();
+        let metadata: RubyMetadata = serde_json::from_str(&json).unwrap();
+        assert_eq!(metadata.engine(), &Engine::Mri);
+        assert_eq!(metadata.config().get("RUBY_SO_NAME"), Some("ruby"));
+
+        let ruby = metadata.into_ruby();
+        assert_eq!(ruby.version(), &Version::new(3, 2, 2));
+        assert_eq!(ruby.engine().unwrap(), &Engine::Mri);
+        let config = ruby.config().unwrap();
+        assert_eq!(config.lib_name(true).unwrap(), "ruby-static");
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::{RbConfig, Ruby, RubyExecError, Version};
+use crate::version::RubyVersionError;
+
+// The values read from a Ruby `.pc` file
+pub struct Package {
+    pub version: Version,
+    pub vars: BTreeMap<String, String>,
+}
+
+impl Package {
+    pub fn var_path(&self, key: &str) -> Option<PathBuf> {
+        self.vars.get(key).filter(|val| !val.is_empty()).map(PathBuf::from)
+    }
+}
+
+fn pkg_config(args: &[&str]) -> Result<String, RubyExecError> {
+    let mut command = Command::new("pkg-config");
+    command.args(args);
+    Ok(RubyExecError::process(&mut command)?.trim().to_owned())
+}
+
+// The package names to try for `query`, which is either a package name or a
+// version to find a package for
+fn candidates(query: &str) -> Vec<String> {
+    match query.parse::<Version>() {
+        Ok(version) => vec![
+            // e.g. Debian and Homebrew
+            format!("ruby-{}.{}", version.major, version.minor),
+            // e.g. Fedora
+            "ruby".to_owned(),
+        ],
+        Err(_) => vec![query.to_owned()],
+    }
+}
+
+// Returns whether `found` is the version requested by `query`, where "3.2"
+// matches any 3.2.x
+fn matches(query: &str, found: &Version) -> bool {
+    match query.parse::<Version>() {
+        Ok(version) if query.matches('.').count() >= 2 => version == *found,
+        Ok(version) => {
+            (version.major, version.minor) == (found.major, found.minor)
+        },
+        Err(_) => true,
+    }
+}
+
+pub fn probe(query: &str) -> Result<Package, RubyVersionError> {
+    let mut last_error = None;
+
+    for name in candidates(query) {
+        let version = match pkg_config(&["--modversion", &name]) {
+            Ok(version) => version.parse::<Version>()?,
+            Err(error) => {
+                last_error = Some(error);
+                continue;
+            },
+        };
+        if !matches(query, &version) {
+            continue;
+        }
+
+        let mut vars = BTreeMap::new();
+        let names = pkg_config(&["--print-variables", &name])?;
+        for key in names.lines().map(str::trim).filter(|k| !k.is_empty()) {
+            let var = format!("--variable={}", key);
+            vars.insert(key.to_owned(), pkg_config(&[&var, &name])?);
+        }
+        return Ok(Package { version, vars });
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        let msg = format!("no pkg-config package found for Ruby {}", query);
+        RubyExecError::ExecFail(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            msg,
+        ))
+    }).into())
+}
+
+// Renders a `.pc` file for `ruby`, linking to the shared library if enabled
+pub fn render(ruby: &Ruby, config: &RbConfig) -> String {
+    let get = |key| config.get(key).unwrap_or_default();
+    let shared = config.get_bool("ENABLE_SHARED").unwrap_or(false);
+    let lib_name = config.lib_name(!shared).unwrap_or_default();
+    let private_libs = config.aux_libs(!shared).unwrap_or_default();
+
+    let mut out = String::new();
+    let mut var = |key: &str, val: &dyn std::fmt::Display| {
+        out.push_str(&format!("{}={}\n", key, val));
+    };
+    var("prefix", &ruby.out_dir().display());
+    var("bindir", &ruby.bin_dir().display());
+    var("libdir", &ruby.lib_dir().display());
+    var("rubyhdrdir", &get("rubyhdrdir"));
+    var("rubyarchhdrdir", &get("rubyarchhdrdir"));
+    var("ruby_version", &get("ruby_version"));
+    var("RUBY_SO_NAME", &get("RUBY_SO_NAME"));
+    var("ruby", &ruby.bin_path().display());
+
+    out.push_str(&format!(
+        "\n\
+        Name: Ruby\n\
+        Description: Object Oriented Script Language\n\
+        Version: {version}\n\
+        URL: https://www.ruby-lang.org\n\
+        Cflags: -I${{rubyarchhdrdir}} -I${{rubyhdrdir}}\n\
+        Libs: -L${{libdir}} -l{lib_name}\n\
+        Libs.private: {private_libs}\n",
+        version = ruby.version(),
+        lib_name = lib_name,
+        private_libs = private_libs.trim(),
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn render_pc() {
+        let dump = "ENABLE_SHARED\0no\0RUBY_SO_NAME\0ruby\0\
+            MAINLIBS\0-lz -lm\0rubyhdrdir\0/r/include/ruby-3.2.0\0";
+        let ruby = Ruby::new(Version::new(3, 2, 2), "/r");
+        let pc = render(&ruby, &RbConfig::parse(dump));
+
+        assert!(pc.starts_with("prefix=/r\nbindir=/r/bin\nlibdir=/r/lib\n"));
+        assert!(pc.contains("\nrubyhdrdir=/r/include/ruby-3.2.0\n"));
+        assert!(pc.contains("\nVersion: 3.2.2\n"));
+        assert!(pc.contains("\nLibs: -L${libdir} -lruby-static\n"));
+        assert!(pc.ends_with("\nLibs.private: -lz -lm\n"));
+    }
+
+    #[test]
+    fn query() {
+        assert_eq!(candidates("3.2"), ["ruby-3.2", "ruby"]);
+        assert_eq!(candidates("3.2.2"), ["ruby-3.2", "ruby"]);
+        assert_eq!(candidates("ruby-3.1"), ["ruby-3.1"]);
+
+        let version = Version::new(3, 2, 2);
+        assert!(matches("3.2", &version));
+        assert!(matches("3.2.2", &version));
+        assert!(!matches("3.2.1", &version));
+        assert!(!matches("3.1", &version));
+        assert!(matches("ruby", &version));
+    }
+}
@@ -0,0 +1,67 @@
+// Normalizes the CPU architecture names used by Ruby and Rust
+fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "arm64" => "aarch64",
+        "amd64" | "x64" => "x86_64",
+        "i386" | "i486" | "i586" | "i686" | "x86" => "x86",
+        "ppc64le" => "powerpc64le",
+        "ppc64" => "powerpc64",
+        arch => arch,
+    }
+}
+
+// Normalizes Ruby's `target_os` (e.g. "darwin21", "mingw32") and the OS within
+// a Rust target triple (e.g. "darwin", "windows")
+fn normalize_os(os: &str) -> &str {
+    if os.starts_with("darwin") {
+        "darwin"
+    } else if os.starts_with("mingw")
+        || os.starts_with("mswin")
+        || os.starts_with("cygwin")
+    {
+        "windows"
+    } else if os.starts_with("linux") {
+        "linux"
+    } else {
+        os.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.')
+    }
+}
+
+// Whether Ruby's `target_cpu` and `target_os` match the Rust target triple
+pub fn matches_rust_target(arch: &str, os: &str, target: &str) -> bool {
+    // The OS is the third component, or the last in triples such as
+    // "wasm32-wasi"
+    let parts: Vec<&str> = target.split('-').collect();
+    let target_arch = parts[0];
+    let target_os = parts.get(2).or(parts.last()).unwrap_or(&"");
+
+    normalize_arch(arch) == normalize_arch(target_arch)
+        && normalize_os(os) == normalize_os(target_os)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rust_target() {
+        let good = [
+            ("x86_64", "linux", "x86_64-unknown-linux-gnu"),
+            ("arm64", "darwin21", "aarch64-apple-darwin"),
+            ("x64", "mingw32", "x86_64-pc-windows-gnu"),
+            ("i686", "linux-gnu", "i686-unknown-linux-gnu"),
+            ("x86_64", "freebsd13.1", "x86_64-unknown-freebsd"),
+        ];
+        for &(arch, os, target) in &good {
+            assert!(matches_rust_target(arch, os, target), "{}", target);
+        }
+
+        let bad = [
+            ("x86_64", "linux", "aarch64-unknown-linux-gnu"),
+            ("x86_64", "darwin21", "x86_64-unknown-linux-gnu"),
+        ];
+        for &(arch, os, target) in &bad {
+            assert!(!matches_rust_target(arch, os, target), "{}", target);
+        }
+    }
+}
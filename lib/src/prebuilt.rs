@@ -0,0 +1,364 @@
+//! Utilities for downloading prebuilt Ruby binaries.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{util, Archive, Ruby, Version};
+use crate::src::download::{self, Progress, RemoveFileHandle, RequestOptions};
+use crate::version::RubyVersionError;
+
+#[doc(inline)]
+pub use crate::src::download::RubySrcDownloadError;
+
+/// Downloads and unpacks a prebuilt Ruby, skipping `configure` and `make`
+/// entirely.
+///
+/// By default, binaries are fetched from
+/// [ruby-builder](https://github.com/ruby/ruby-builder), which requires setting
+/// a [`platform`](#method.platform). On Windows, binaries are fetched from
+/// [RubyInstaller](https://rubyinstaller.org) instead. Any other archive can be
+/// used by setting its [`url`](#method.url).
+///
+/// Concurrent downloads of the same Ruby into `dst_dir` wait for each other,
+/// and an installation is only reused if it was completely unpacked.
+///
+/// **Note:** requires the `download` feature (the default).
+pub struct RubyPrebuilt<'a> {
+    version: &'a Version,
+    dst_dir: &'a Path,
+    platform: Option<&'a str>,
+    url: Option<&'a str>,
+    ignore_existing_dir: bool,
+    request: RequestOptions<'a>,
+    progress: Option<Box<Progress<'a>>>,
+}
+
+impl<'a> RubyPrebuilt<'a> {
+    /// Creates a new instance that unpacks Ruby `version` into `dst_dir`.
+    #[inline]
+    pub fn new<P: AsRef<Path> + ?Sized>(
+        version: &'a Version,
+        dst_dir: &'a P,
+    ) -> Self {
+        RubyPrebuilt {
+            version,
+            dst_dir: dst_dir.as_ref(),
+            platform: None,
+            url: None,
+            ignore_existing_dir: false,
+            request: RequestOptions::default(),
+            progress: None,
+        }
+    }
+
+    /// Sets the ruby-builder platform to download for (e.g. `ubuntu-22.04`,
+    /// `macos-latest`).
+    ///
+    /// The `windows-x64` and `windows-x86` platforms download from
+    /// RubyInstaller. Unpacking its `.7z` archives requires a `tar` that
+    /// supports them, such as the one bundled with Windows 10 and later. If no
+    /// platform is set on Windows, `windows-x64` or `windows-x86` is chosen
+    /// based on the target architecture.
+    #[inline]
+    pub fn platform(mut self, platform: &'a str) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    /// Sets the URL of the archive to download, overriding `platform`.
+    ///
+    /// The archive may be in any format supported by
+    /// [`Archive`](../trait.Archive.html) and must contain a Ruby installation
+    /// at its root or in a top-level directory.
+    #[inline]
+    pub fn url(mut self, url: &'a str) -> Self {
+        self.url = Some(url);
+        self
+    }
+
+    /// Overwrite the installation directory in `dst_dir` if it already exists.
+    ///
+    /// **Warning:** This will remove the existing installation. Use carefully!
+    #[inline]
+    pub fn ignore_existing_dir(mut self) -> Self {
+        self.ignore_existing_dir = true;
+        self
+    }
+
+    /// Sets the proxy server to download through.
+    ///
+    /// See
+    /// [`RubySrcDownloader::proxy`](../src/download/struct.RubySrcDownloader.html#method.proxy)
+    /// for details.
+    #[inline]
+    pub fn proxy(mut self, url: &'a str) -> Self {
+        self.request.proxy = Some(url);
+        self
+    }
+
+    /// Sets a callback for reporting download progress.
+    ///
+    /// The callback is passed the number of bytes downloaded so far and the
+    /// total number of bytes, if the server reported a `Content-Length`.
+    #[inline]
+    pub fn progress<F>(mut self, f: F) -> Self
+        where F: FnMut(u64, Option<u64>) + 'a
+    {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    /// Returns the URL of the archive that will be downloaded, if known.
+    pub fn archive_url(&self) -> Option<String> {
+        if let Some(url) = self.url {
+            return Some(url.to_owned());
+        }
+        let platform = match self.platform {
+            Some(platform) => platform,
+            None if cfg!(target_os = "windows") => {
+                if cfg!(target_arch = "x86") {
+                    "windows-x86"
+                } else {
+                    "windows-x64"
+                }
+            },
+            None => return None,
+        };
+        if let Some(arch) = platform.strip_prefix("windows-") {
+            return Some(format!(
+                "https://github.com/oneclick/rubyinstaller2/releases/download/RubyInstaller-{version}-1/rubyinstaller-{version}-1-{arch}.7z",
+                version = self.version.release(),
+                arch = arch,
+            ));
+        }
+        Some(format!(
+            "https://github.com/ruby/ruby-builder/releases/download/toolcache/ruby-{version}-{platform}.tar.gz",
+            version = self.version.release(),
+            platform = platform,
+        ))
+    }
+
+    /// Returns the directory that Ruby is unpacked into.
+    #[inline]
+    pub fn install_dir(&self) -> PathBuf {
+        self.dst_dir.join(format!("ruby-{}", self.version.release()))
+    }
+
+    /// Downloads and unpacks Ruby, returning the installation.
+    pub fn download(mut self) -> Result<Ruby, RubyPrebuiltError> {
+        use RubyPrebuiltError::*;
+
+        let install_dir = self.install_dir();
+
+        fs::create_dir_all(self.dst_dir).map_err(CreateDir)?;
+
+        // Wait for other processes installing the same Ruby
+        let _install_lock = util::lock(&install_dir).map_err(Lock)?;
+
+        if install_dir.exists() {
+            if !self.ignore_existing_dir {
+                // Reuse the existing installation if it was fully unpacked
+                if let Some(prefix) = installed_prefix(&install_dir) {
+                    return self.ruby(prefix);
+                }
+            }
+            fs::remove_dir_all(&install_dir).map_err(CreateDir)?;
+        }
+
+        let url = self.archive_url().ok_or(MissingPlatform)?;
+        let archive_name = match url.rsplit('/').next() {
+            Some(name) if !name.is_empty() => name,
+            _ => "ruby-prebuilt",
+        };
+
+        let mut archive_dir = env::temp_dir();
+        archive_dir.push("aloxide");
+        fs::create_dir_all(&archive_dir).map_err(CreateDir)?;
+
+        let archive_path = archive_dir.join(archive_name);
+
+        // Wait for other processes downloading the same archive
+        let _archive_lock = util::lock(&archive_path).map_err(Lock)?;
+        let _remove_archive = RemoveFileHandle { file: &archive_path };
+
+        let progress = self.progress.as_mut().map(|f| &mut **f as _);
+        let mut file = download::fetch(&url, &archive_path, &self.request, progress)?;
+
+        fs::create_dir_all(&install_dir).map_err(CreateDir)?;
+        if archive_name.ends_with(".7z") {
+            drop(file);
+            unpack_7z(&archive_path, &install_dir).map_err(UnpackArchive)?;
+        } else {
+            file.unpack(&install_dir).map_err(UnpackArchive)?;
+        }
+
+        let prefix = find_prefix(&install_dir).map_err(CreateDir)?;
+        let ruby = self.ruby(prefix.ok_or(MissingRuby)?)?;
+
+        // Marks the installation as complete and allows for `Ruby::uninstall`
+        // to remove it
+        let marker = ruby.out_dir().join(crate::INSTALL_MARKER);
+        fs::write(marker, self.version.to_string()).map_err(CreateDir)?;
+
+        Ok(ruby)
+    }
+
+    fn ruby(&self, prefix: PathBuf) -> Result<Ruby, RubyPrebuiltError> {
+        let ruby = Ruby::from_path(prefix)?;
+
+        // `ruby -v` may report a patch level that wasn't requested
+        if ruby.version().release() != self.version.release() {
+            let version = ruby.version().clone();
+            return Err(RubyPrebuiltError::VersionMismatch(version));
+        }
+        Ok(ruby)
+    }
+}
+
+// Returns the prefix of the installation in `dir` if it was fully unpacked
+fn installed_prefix(dir: &Path) -> Option<PathBuf> {
+    find_prefix(dir).ok()?
+        .filter(|prefix| prefix.join(crate::INSTALL_MARKER).is_file())
+}
+
+// Unpacks the `.7z` archive at `path` into `dst` via `tar`, since `Archive`
+// doesn't support the format
+fn unpack_7z(path: &Path, dst: &Path) -> io::Result<()> {
+    let status = Command::new("tar")
+        .arg("-xf")
+        .arg(path)
+        .arg("-C")
+        .arg(dst)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "failed to unpack {} with `tar` ({})",
+            path.display(),
+            status,
+        )))
+    }
+}
+
+// Finds the directory containing `bin/ruby` at or directly below `dir`
+fn find_prefix(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let has_ruby = |dir: &Path| dir.join("bin").join(Ruby::bin_name()).exists();
+
+    if has_ruby(dir) {
+        return Ok(Some(dir.to_path_buf()));
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() && has_ruby(&path) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// The error returned when
+/// [`RubyPrebuilt::download`](struct.RubyPrebuilt.html#method.download) fails.
+#[derive(Debug)]
+pub enum RubyPrebuiltError {
+    /// Neither a platform nor a URL was provided.
+    MissingPlatform,
+    /// Failed to create or replace a directory.
+    CreateDir(io::Error),
+    /// Failed to lock the installation directory or the archive.
+    Lock(io::Error),
+    /// Failed to download the archive.
+    Download(RubySrcDownloadError),
+    /// Failed to unpack the archive.
+    UnpackArchive(io::Error),
+    /// The archive did not contain a `ruby` executable.
+    MissingRuby,
+    /// Failed to get the version for `ruby`.
+    Version(RubyVersionError),
+    /// The downloaded Ruby is a different version than requested.
+    VersionMismatch(Version),
+}
+
+impl From<RubySrcDownloadError> for RubyPrebuiltError {
+    #[inline]
+    fn from(error: RubySrcDownloadError) -> Self {
+        RubyPrebuiltError::Download(error)
+    }
+}
+
+impl From<RubyVersionError> for RubyPrebuiltError {
+    #[inline]
+    fn from(error: RubyVersionError) -> Self {
+        RubyPrebuiltError::Version(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rubyinstaller_url() {
+        let version = Version::new(3, 2, 2);
+        let url = RubyPrebuilt::new(&version, "")
+            .platform("windows-x86")
+            .archive_url()
+            .unwrap();
+        assert_eq!(
+            url,
+            "https://github.com/oneclick/rubyinstaller2/releases/download/RubyInstaller-3.2.2-1/rubyinstaller-3.2.2-1-x86.7z",
+        );
+    }
+
+    #[test]
+    fn ignore_patchlevel() {
+        let version: Version = "3.2.2p53".parse().unwrap();
+        let prebuilt = RubyPrebuilt::new(&version, "rubies")
+            .platform("ubuntu-22.04-x64");
+        assert_eq!(prebuilt.install_dir(), Path::new("rubies/ruby-3.2.2"));
+        assert!(prebuilt.archive_url().unwrap().ends_with(
+            "/ruby-3.2.2-ubuntu-22.04-x64.tar.gz",
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reuse_only_complete_install() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dst_dir = env::temp_dir().join("aloxide-prebuilt-reuse");
+        let _ = fs::remove_dir_all(&dst_dir);
+
+        let version = Version::new(3, 2, 2);
+        let prebuilt = || {
+            // Nothing listens on port 1, so downloading always fails
+            RubyPrebuilt::new(&version, &dst_dir)
+                .url("http://127.0.0.1:1/ruby-3.2.2.tar.gz")
+        };
+
+        let prefix = prebuilt().install_dir().join("ruby-3.2.2");
+        let bin_dir = prefix.join("bin");
+        let ruby = bin_dir.join("ruby");
+        fs::create_dir_all(&bin_dir).unwrap();
+        fs::write(&ruby, "#!/bin/sh\nprintf 3.2.2\n").unwrap();
+        fs::set_permissions(&ruby, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // A complete installation is reused without downloading
+        fs::write(prefix.join(crate::INSTALL_MARKER), "3.2.2").unwrap();
+        let installed = prebuilt().download().unwrap();
+        assert_eq!(installed.out_dir(), prefix);
+
+        // An incomplete installation is removed and downloaded again
+        fs::remove_file(prefix.join(crate::INSTALL_MARKER)).unwrap();
+        match prebuilt().download() {
+            Err(RubyPrebuiltError::Download(_)) => {},
+            _ => panic!("expected a download error"),
+        }
+        assert!(!prefix.exists());
+
+        fs::remove_dir_all(&dst_dir).unwrap();
+    }
+}
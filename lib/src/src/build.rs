@@ -1,178 +1,1865 @@
 //! Utilities for building Ruby.
 
 use std::ffi::{OsStr, OsString};
-use std::fmt::Display;
-use std::borrow::Borrow;
+use std::fmt::{self, Display};
+use std::borrow::{Borrow, Cow};
+use std::fs;
 use std::io;
-use std::path::PathBuf;
-use std::process::{Command, Output, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::{Ruby, RubySrc, version::RubyVersionError};
+use crate::config::RbConfig;
+use crate::engine::Engine;
+
+type PhaseStart<'a> = dyn FnMut(Phase) + 'a;
+type PhaseEnd<'a> = dyn FnMut(Phase, Result<(), &RubyBuildError>) + 'a;
+type OnLine<'a> = dyn FnMut(&str) + 'a;
+type PhaseParts<'b, 'a> = (
+    &'b mut Command,
+    &'b mut LineHandlers<'a>,
+    fn(Output) -> RubyBuildError,
+    fn(io::Error) -> RubyBuildError,
+);
+
+// Callbacks for each line written by a phase
+#[derive(Default)]
+struct LineHandlers<'a> {
+    stdout: Option<Box<OnLine<'a>>>,
+    stderr: Option<Box<OnLine<'a>>>,
+}
 
 /// Configures and builds Ruby.
 pub struct RubyBuilder<'a> {
     src: &'a RubySrc,
     out_dir: PathBuf,
     autoconf: Command,
+    autoconf_lines: LineHandlers<'a>,
     force_autoconf: bool,
     configure: Command,
+    configure_lines: LineHandlers<'a>,
     configure_path: PathBuf,
     force_configure: bool,
+    validate_flags: bool,
     make: Command,
+    make_lines: LineHandlers<'a>,
     force_make: bool,
+    resume: bool,
+    until: Option<Phase>,
+    clean_env: bool,
+    reproducible: bool,
+    make_jobs: Option<usize>,
+    make_targets: Vec<OsString>,
+    build_dir: Option<PathBuf>,
+    write_logs: bool,
+    strip: bool,
+    cargo_directives: bool,
+    universal_macos: bool,
+    target: String,
+    target_apple: bool,
+    // The triple to build a `baseruby` for if needed
+    baseruby_build: Option<Option<String>>,
+    // The image and program for `in_container`
+    container: Option<String>,
+    container_runtime: OsString,
+    // The phases run so far, for `build_with_report`
+    report: Vec<PhaseReport>,
+    on_phase_start: Option<Box<PhaseStart<'a>>>,
+    on_phase_end: Option<Box<PhaseEnd<'a>>>,
+
+    #[cfg(windows)]
+    target_msvc: bool,
+}
+
+impl<'a> RubyBuilder<'a> {
+    // Process `target` to make it usable with building for Windows
+    fn convert_to_ruby(target: &str) -> &str {
+        match target {
+            "x86_64-pc-windows-msvc" => "x84_64-mswin64",
+            "x86_64-pc-windows-gnu"  => "x86_64-pc-mingw32",
+            "i686-pc-windows-msvc"   => "x86-mswin32",
+            "i686-pc-windows-gnu"    => "x86-pc-mingw32",
+            other => other
+        }
+    }
+
+    // Process `target` to make it usable with `cc::windows_registry::find`
+    fn convert_to_rust(target: &str) -> &str {
+        if target.contains("mswin") {
+            if target.contains("64") {
+                "x86_64-pc-windows-msvc"
+            } else {
+                "i686-pc-windows-msvc"
+            }
+        } else {
+            target
+        }
+    }
+
+    pub(crate) fn new(
+        src: &'a RubySrc,
+        out_dir: PathBuf,
+        target: &str,
+    ) -> Self {
+        // Use an absolute path so that `configure` can run from any directory
+        let src_dir = std::path::absolute(src.as_path())
+            .unwrap_or_else(|_| src.as_path().to_path_buf());
+        let ruby_target = RubyBuilder::convert_to_ruby(target);
+        let rust_target = RubyBuilder::convert_to_rust(target);
+
+        let nmake = crate::util::nmake(rust_target);
+        let target_msvc = cfg!(target_os = "windows") && nmake.is_some();
+
+        let (mut make, configure_path) = match nmake {
+            Some(nmake) => {
+                let mut path = src_dir.join("win32");
+                path.push("configure.bat");
+                (nmake, path)
+            },
+            None => (Command::new("make"), src_dir.join("configure"))
+        };
+
+        make.env("PREFIX", &out_dir);
+
+        let mut configure = configure_command(&configure_path, target_msvc);
+
+        configure.arg(format!("--prefix={}", out_dir.display()));
+        configure.arg(format!("--target={}", ruby_target));
+
+        RubyBuilder {
+            src,
+            out_dir,
+            autoconf: Command::new("autoconf"),
+            autoconf_lines: LineHandlers::default(),
+            force_autoconf: false,
+            configure,
+            configure_lines: LineHandlers::default(),
+            configure_path,
+            force_configure: false,
+            validate_flags: false,
+            make,
+            make_lines: LineHandlers::default(),
+            force_make: false,
+            resume: false,
+            until: None,
+            clean_env: false,
+            reproducible: false,
+            make_jobs: None,
+            make_targets: vec!["install".into()],
+            build_dir: None,
+            write_logs: false,
+            strip: false,
+            cargo_directives: false,
+            universal_macos: false,
+            target: target.to_owned(),
+            target_apple: target.contains("apple"),
+            baseruby_build: None,
+            container: None,
+            container_runtime: OsString::from("docker"),
+            report: Vec::new(),
+            on_phase_start: None,
+            on_phase_end: None,
+
+            #[cfg(windows)]
+            target_msvc,
+        }
+    }
+
+    /// Runs `configure` and `make` in `dir` instead of in the sources.
+    ///
+    /// This keeps the sources clean and allows for building multiple
+    /// configurations from the same sources. Builds in different directories
+    /// don't wait for each other.
+    #[inline]
+    pub fn build_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.build_dir = Some(dir.into());
+        self
+    }
+
+    /// Writes the output of each phase to `out_dir/logs/{phase}.log`, such as
+    /// `logs/make.log`.
+    #[inline]
+    pub fn write_logs(mut self) -> Self {
+        self.write_logs = true;
+        self
+    }
+
+    /// Runs each phase with only `PATH`, `HOME`, temporary directory
+    /// variables, and environment variables set via this builder.
+    ///
+    /// This prevents variables such as `RUBYOPT`, `GEM_HOME`, `CFLAGS`, and
+    /// `MAKEFLAGS` from leaking into the build and making it irreproducible.
+    #[inline]
+    pub fn clean_env(mut self) -> Self {
+        self.clean_env = true;
+        self
+    }
+
+    /// Makes each phase produce the same output for the same sources,
+    /// regardless of when or where Ruby is built.
+    ///
+    /// This sets `SOURCE_DATE_EPOCH` (inherited if set, otherwise the
+    /// modification time of `version.h`), pins `LC_ALL` and `TZ`, and maps the
+    /// source and build directories to `.` in debug info and macros via
+    /// `-ffile-prefix-map` in `CPPFLAGS`. Pair with
+    /// [`clean_env`](#method.clean_env) to also prevent the environment from
+    /// affecting the build.
+    ///
+    /// Note that `CPPFLAGS` set via
+    /// [`ConfigurePhase::set_val`](struct.ConfigurePhase.html#method.set_val)
+    /// overrides the file prefix maps.
+    #[inline]
+    pub fn reproducible(mut self) -> Self {
+        self.reproducible = true;
+        self
+    }
+
+    /// Resumes from the last phase that failed, rather than redoing phases
+    /// that were forced but have already completed.
+    ///
+    /// Each phase records its completion in the
+    /// [build directory](#method.build_dir), or in `out_dir` for builds within
+    /// the sources. Phases still rerun when their inputs change, such as
+    /// `configure` arguments.
+    #[inline]
+    pub fn resume(mut self) -> Self {
+        self.resume = true;
+        self
+    }
+
+    /// Strips symbols from the installed executables and shared libraries
+    /// after building, which greatly reduces their size.
+    ///
+    /// The `STRIP` environment variable overrides the `strip` program used.
+    /// This has no effect on the MSVC target platform, where debug info is
+    /// already separate.
+    #[inline]
+    pub fn strip(mut self) -> Self {
+        self.strip = true;
+        self
+    }
+
+    /// Tells `cargo` to rerun the build script when the installed Ruby
+    /// changes, once the build succeeds.
+    ///
+    /// See [`Ruby::emit_cargo_directives`](../../struct.Ruby.html#method.emit_cargo_directives).
+    #[inline]
+    pub fn emit_cargo_directives(mut self) -> Self {
+        self.cargo_directives = true;
+        self
+    }
+
+    /// Runs each phase within a container created from `image`, with the
+    /// source, build, and output directories mounted at the same paths.
+    ///
+    /// This allows for building for Linux on other platforms or with a
+    /// hermetic toolchain. The container is run via `docker` by default; use
+    /// [`container_runtime`](#method.container_runtime) to change this.
+    ///
+    /// Only environment variables set via this builder are passed into the
+    /// container. Handles set via `stdout` and `stderr` are not kept; use
+    /// `on_stdout_line` and `on_stderr_line` instead.
+    #[inline]
+    pub fn in_container(mut self, image: impl Into<String>) -> Self {
+        self.container = Some(image.into());
+        self
+    }
+
+    /// Sets the program used to run containers for
+    /// [`in_container`](#method.in_container), such as `podman`.
+    #[inline]
+    pub fn container_runtime(mut self, program: impl Into<OsString>) -> Self {
+        self.container_runtime = program.into();
+        self
+    }
+
+    /// Cross-compiles Ruby for the target from another platform.
+    ///
+    /// This passes the target into `--host`, the machine that Ruby will run
+    /// on, along with a `baseruby` for running scripts during the build. If
+    /// `cross` has no `baseruby`, one is built from the same sources for the
+    /// build machine first.
+    ///
+    /// The version of a cross-compiled Ruby is read from its installed
+    /// `rbconfig.rb` since `ruby` can't run on the build machine.
+    pub fn cross(mut self, cross: CrossBuild) -> Self {
+        let ruby_target = RubyBuilder::convert_to_ruby(&self.target);
+        let mut args = vec![format!("--host={}", ruby_target)];
+        if let Some(build) = &cross.build {
+            let build = RubyBuilder::convert_to_ruby(build);
+            args.push(format!("--build={}", build));
+        }
+        match &cross.baseruby {
+            Some(baseruby) => {
+                args.push(format!("--with-baseruby={}", baseruby.display()));
+            },
+            None => self.baseruby_build = Some(cross.build.clone()),
+        }
+        self.configure.args(args);
+
+        let mut envs = cross.envs;
+        if cross.inherit_tools {
+            envs.extend(target_tools(&self.target));
+        }
+        for (key, val) in envs {
+            self.configure.env(&key, &val);
+            self.make.env(&key, &val);
+        }
+        self
+    }
+
+    /// Builds a universal binary for both `arm64` and `x86_64` when targeting
+    /// macOS.
+    ///
+    /// Each architecture is configured and built separately within the build
+    /// directory, and then their executables and libraries are merged into
+    /// the output directory via `lipo`. This has no effect for other targets.
+    ///
+    /// [`dry_run`](#method.dry_run) does not reflect the separate builds.
+    #[inline]
+    pub fn universal_macos(mut self) -> Self {
+        self.universal_macos = true;
+        self
+    }
+
+    /// Builds a static library suited for fully static binaries when
+    /// targeting musl, such as `x86_64-unknown-linux-musl`.
+    ///
+    /// This applies [`Preset::Musl`](enum.Preset.html#variant.Musl) to
+    /// `configure` and has no effect for other targets. Flags passed
+    /// afterwards take precedence.
+    #[inline]
+    pub fn musl(self) -> Self {
+        if self.target.contains("musl") {
+            self.configure().preset(Preset::Musl).0
+        } else {
+            self
+        }
+    }
+
+    /// Sets a callback for when a phase is about to run.
+    ///
+    /// Phases that don't need to run are skipped without calling this.
+    #[inline]
+    pub fn on_phase_start<F>(mut self, f: F) -> Self
+        where F: FnMut(Phase) + 'a
+    {
+        self.on_phase_start = Some(Box::new(f));
+        self
+    }
+
+    /// Sets a callback for when a phase has finished running, along with
+    /// whether it succeeded.
+    #[inline]
+    pub fn on_phase_end<F>(mut self, f: F) -> Self
+        where F: FnMut(Phase, Result<(), &RubyBuildError>) + 'a
+    {
+        self.on_phase_end = Some(Box::new(f));
+        self
+    }
+
+    /// Adjust what happens when running `autoconf`.
+    #[inline]
+    pub fn autoconf(self) -> AutoconfPhase<'a> {
+        AutoconfPhase(self)
+    }
+
+    /// Adjust what happens when running `configure`.
+    #[inline]
+    pub fn configure(self) -> ConfigurePhase<'a> {
+        ConfigurePhase(self)
+    }
+
+    /// Adjust what happens when running `make`.
+    #[inline]
+    pub fn make(self) -> MakePhase<'a> {
+        MakePhase(self)
+    }
+
+    // The directory that `configure` and `make` run in
+    fn work_dir(&self) -> &Path {
+        self.build_dir.as_deref().unwrap_or_else(|| self.src.as_path())
+    }
+
+    fn bin_path(&self) -> PathBuf {
+        self.out_dir.join("bin").join(Ruby::bin_name())
+    }
+
+    fn target_msvc(&self) -> bool {
+        #[cfg(target_os = "windows")]
+        return self.target_msvc;
+
+        #[cfg(not(target_os = "windows"))]
+        return false;
+    }
+
+    // The extra argument passed into `make` for parallel jobs
+    fn make_jobs_arg(&self) -> Option<String> {
+        if self.target_msvc() {
+            // `nmake` doesn't support parallel jobs
+            None
+        } else {
+            let jobs = self.make_jobs.unwrap_or_else(auto_jobs);
+            Some(format!("-j{}", jobs))
+        }
+    }
+
+    /// Returns the commands that [`build`](#method.build) would run given the
+    /// current state of the sources, without running anything.
+    ///
+    /// This is useful for testing build scripts and for reproducing build
+    /// failures by hand.
+    pub fn dry_run(&self) -> BuildPlan {
+        let work_dir = self.work_dir();
+
+        // With `resume`, forcing doesn't redo phases that already completed
+        let force = |force: bool, phase: Phase| {
+            force && !(self.resume && self.completed(phase) == Some(true))
+        };
+
+        let run_autoconf = !self.target_msvc()
+            && (force(self.force_autoconf, Phase::Autoconf)
+                || !self.configure_path.exists());
+
+        let src_dir = self.src.as_path();
+        let mut make = BuildStep::new(Phase::Make, &self.make, work_dir);
+        make.args.extend(self.make_jobs_arg().map(OsString::from));
+        make.args.extend(self.make_targets.iter().cloned());
+
+        let mut steps = vec![
+            BuildStep::new(Phase::Autoconf, &self.autoconf, src_dir),
+            BuildStep::new(Phase::Configure, &self.configure, work_dir),
+            make,
+        ];
+        for step in &mut steps {
+            if self.reproducible {
+                self.make_reproducible(step);
+            }
+            if let Some(image) = &self.container {
+                // The container's environment is already isolated
+                self.containerize(step, image);
+            } else if self.clean_env {
+                isolate_env(step);
+            }
+        }
+
+        // Reconfigure if the arguments changed since the last configuration,
+        // which also marks that `configure` completed
+        let fingerprint = fingerprint(&steps[1]);
+        let fingerprint_path = self.marker_path(Phase::Configure);
+        // Without a fingerprint, whether `configure` is stale is unknown, so
+        // only the `Makefile` is checked
+        let stale = fs::read_to_string(&fingerprint_path)
+            .is_ok_and(|old| old.trim() != fingerprint)
+            || is_newer(&work_dir.join("config.status"), &fingerprint_path);
+
+        let run_configure = run_autoconf
+            || force(self.force_configure, Phase::Configure)
+            || stale
+            || !work_dir.join("Makefile").exists();
+
+        // Other targets are left to `make` to decide what's outdated
+        let run_make = run_configure
+            || force(self.force_make, Phase::Make)
+            || self.completed(Phase::Make) == Some(false)
+            || !self.installs()
+            || !self.bin_path().exists();
+
+        steps.retain(|step| match step.phase {
+            Phase::Autoconf => run_autoconf,
+            Phase::Configure => run_configure,
+            Phase::Make => run_make,
+        });
+
+        if let Some(until) = self.until {
+            steps.retain(|step| step.phase <= until);
+        }
+
+        BuildPlan { steps }
+    }
+
+    // Sets the environment of `step` for `reproducible`
+    fn make_reproducible(&self, step: &mut BuildStep) {
+        let src_dir = self.src.as_path();
+        let mut envs = vec![
+            ("SOURCE_DATE_EPOCH", source_date_epoch(src_dir)),
+            ("LC_ALL", OsString::from("C")),
+            ("TZ", OsString::from("UTC")),
+        ];
+
+        if step.phase == Phase::Configure && !self.target_msvc() {
+            let explicit = step.envs.iter()
+                .find(|(key, _)| key == "CPPFLAGS")
+                .map(|(_, val)| val.clone());
+            let inherited = if self.clean_env || self.container.is_some() {
+                None
+            } else {
+                std::env::var_os("CPPFLAGS")
+            };
+
+            let mut flags = explicit.unwrap_or(inherited).unwrap_or_default();
+            let work_dir = self.work_dir();
+            for dir in &[src_dir, work_dir] {
+                let dir = std::path::absolute(dir)
+                    .unwrap_or_else(|_| dir.to_path_buf());
+                if !flags.is_empty() {
+                    flags.push(" ");
+                }
+                flags.push("-ffile-prefix-map=");
+                flags.push(dir);
+                flags.push("=.");
+                if src_dir == work_dir {
+                    break;
+                }
+            }
+            envs.push(("CPPFLAGS", flags));
+        }
+
+        for (key, val) in envs {
+            // Values set explicitly take precedence, except for `CPPFLAGS`
+            // which has been extended
+            match step.envs.iter_mut().find(|(k, _)| k == key) {
+                Some((_, old)) if key == "CPPFLAGS" => *old = Some(val),
+                Some(_) => {},
+                None => step.envs.push((OsString::from(key), Some(val))),
+            }
+        }
+    }
+
+    // Makes `step` run its command within the container from `image`
+    fn containerize(&self, step: &mut BuildStep, image: &str) {
+        let absolute = |dir: &Path| {
+            std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf())
+        };
+
+        let mut args = vec![OsString::from("run"), OsString::from("--rm")];
+
+        // Create files as the owner of the sources rather than as root
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if let Ok(meta) = fs::metadata(self.src.as_path()) {
+                args.push(OsString::from("--user"));
+                args.push(format!("{}:{}", meta.uid(), meta.gid()).into());
+            }
+        }
+
+        let mut mounts = vec![self.src.as_path(), self.work_dir()];
+        mounts.push(&self.out_dir);
+        mounts.dedup();
+        for dir in mounts {
+            let dir = absolute(dir);
+            let mut mount = dir.clone().into_os_string();
+            mount.push(":");
+            mount.push(dir);
+            args.push(OsString::from("-v"));
+            args.push(mount);
+        }
+
+        args.push(OsString::from("-w"));
+        args.push(absolute(&step.dir).into_os_string());
+
+        for (key, val) in step.envs.drain(..) {
+            if let Some(val) = val {
+                let mut env = key;
+                env.push("=");
+                env.push(val);
+                args.push(OsString::from("-e"));
+                args.push(env);
+            }
+        }
+
+        args.push(OsString::from(image));
+        args.push(std::mem::replace(
+            &mut step.program,
+            self.container_runtime.clone(),
+        ));
+        args.append(&mut step.args);
+
+        step.args = args;
+        step.clear_env = false;
+    }
+
+    // Fails if `configure` doesn't recognize any of the queued flags
+    fn check_configure_flags(&self) -> Result<(), RubyBuildError> {
+        use RubyBuildError::*;
+
+        // `configure.bat` has a different help format
+        if self.target_msvc() {
+            return Ok(());
+        }
+
+        let output = configure_command(&self.configure_path, false)
+            .arg("--help")
+            .current_dir(self.work_dir())
+            .output()
+            .map_err(ConfigureSpawnFail)?;
+        if !output.status.success() {
+            return Err(ConfigureFail(output));
+        }
+
+        let help = String::from_utf8_lossy(&output.stdout);
+        let unknown = unknown_flags(&help, self.configure.get_args());
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(UnknownFlags(unknown))
+        }
+    }
+
+    // Runs the command for `step`
+    fn run_step(&mut self, step: &BuildStep) -> Result<(), RubyBuildError> {
+        let (mut log, marker) = self.start_step(step)?;
+        let (cmd, lines, _, spawn_fail) = self.phase_parts(step.phase);
+        let output = output_lines(cmd, lines, log.as_mut())
+            .map_err(spawn_fail)?;
+        self.finish_step(step.phase, output, marker)
+    }
+
+    // Prepares the command for `step`, returning the log file if enabled and
+    // the contents of the marker to write once it completes
+    fn start_step(
+        &mut self,
+        step: &BuildStep,
+    ) -> Result<(Option<fs::File>, String), RubyBuildError> {
+        use RubyBuildError::*;
+
+        // Later phases must rerun after this one, even if it fails
+        fs::create_dir_all(self.marker_dir()).map_err(WriteMarker)?;
+        for &phase in &[Phase::Autoconf, Phase::Configure, Phase::Make] {
+            if phase >= step.phase {
+                fs::write(self.marker_path(phase), PENDING_MARKER)
+                    .map_err(WriteMarker)?;
+            }
+        }
+
+        if step.phase == Phase::Configure && self.validate_flags {
+            self.check_configure_flags()?;
+        }
+
+        let marker = match step.phase {
+            Phase::Configure => fingerprint(step),
+            _ => String::new(),
+        };
+
+        let log = if self.write_logs {
+            let dir = self.out_dir.join("logs");
+            fs::create_dir_all(&dir).map_err(CreateLog)?;
+
+            let path = dir.join(format!("{}.log", step.phase));
+            Some(fs::File::create(path).map_err(CreateLog)?)
+        } else {
+            None
+        };
+
+        if step.phase == Phase::Make {
+            if let Some(jobs) = self.make_jobs_arg() {
+                self.make.arg(jobs);
+            }
+            self.make.args(&self.make_targets);
+        }
+
+        let in_container = self.container.is_some();
+        let (cmd, ..) = self.phase_parts(step.phase);
+        if in_container {
+            // The program and arguments were replaced in `containerize`
+            *cmd = Command::new(&step.program);
+            cmd.args(&step.args);
+        }
+        if step.clear_env {
+            cmd.env_clear();
+        }
+        for (key, val) in &step.envs {
+            match val {
+                Some(val) => cmd.env(key, val),
+                None => cmd.env_remove(key),
+            };
+        }
+        cmd.current_dir(&step.dir);
+
+        Ok((log, marker))
+    }
+
+    // Returns the command and line handlers for `phase` along with its errors
+    fn phase_parts(&mut self, phase: Phase) -> PhaseParts<'_, 'a> {
+        use RubyBuildError::*;
+        match phase {
+            Phase::Autoconf => (
+                &mut self.autoconf,
+                &mut self.autoconf_lines,
+                AutoconfFail,
+                AutoconfSpawnFail,
+            ),
+            Phase::Configure => (
+                &mut self.configure,
+                &mut self.configure_lines,
+                ConfigureFail,
+                ConfigureSpawnFail,
+            ),
+            Phase::Make => (
+                &mut self.make,
+                &mut self.make_lines,
+                MakeFail,
+                MakeSpawnFail,
+            ),
+        }
+    }
+
+    // Checks the output of `phase` and marks it as completed
+    fn finish_step(
+        &mut self,
+        phase: Phase,
+        output: Output,
+        marker: String,
+    ) -> Result<(), RubyBuildError> {
+        let (_, _, fail, _) = self.phase_parts(phase);
+        if !output.status.success() {
+            return Err(fail(output));
+        }
+        fs::write(self.marker_path(phase), marker)
+            .map_err(RubyBuildError::WriteMarker)
+    }
+
+    // Whether `--host` was passed via `cross`
+    fn is_cross(&self) -> bool {
+        self.configure.get_args().any(|arg| {
+            arg.to_str().is_some_and(|arg| arg.starts_with("--host="))
+        })
+    }
+
+    // Whether the `make` targets install Ruby into `out_dir`
+    fn installs(&self) -> bool {
+        self.make_targets.iter().any(|target| {
+            target.to_str().is_some_and(|t| t.starts_with("install"))
+        })
+    }
+
+    // Builds a `baseruby` for `cross` if needed
+    fn prepare_baseruby(&mut self) -> Result<(), RubyBuildError> {
+        if let Some(build) = self.baseruby_build.take() {
+            let build = build.ok_or(RubyBuildError::UnknownBuildTriple)?;
+            let baseruby = self.build_baseruby(&build)?;
+            self.configure.arg(format!(
+                "--with-baseruby={}",
+                baseruby.bin_path().display(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn run_phases(&mut self) -> Result<(), RubyBuildError> {
+        self.prepare_baseruby()?;
+
+        // Wait for other processes building in the same directory
+        let _lock = self.lock_work_dir()?;
+
+        if self.universal_macos && self.target_apple {
+            self.run_universal()
+        } else {
+            self.run_plan()
+        }
+    }
+
+    fn run_plan(&mut self) -> Result<(), RubyBuildError> {
+        self.create_dirs()?;
+
+        let plan = self.dry_run();
+        for &phase in &[Phase::Autoconf, Phase::Configure, Phase::Make] {
+            if self.until.is_some_and(|until| phase > until) {
+                break;
+            }
+            let step = match plan.step(phase) {
+                Some(step) => step,
+                None => {
+                    self.report.push(PhaseReport::skipped(phase));
+                    continue;
+                },
+            };
+
+            if let Some(on_start) = &mut self.on_phase_start {
+                on_start(phase);
+            }
+            let start = Instant::now();
+            let result = self.run_step(step);
+            self.report.push(PhaseReport {
+                phase,
+                step: Some(step.clone()),
+                duration: start.elapsed(),
+            });
+            if let Some(on_end) = &mut self.on_phase_end {
+                on_end(phase, result.as_ref().map(|_| ()));
+            }
+            result?;
+        }
+
+        Ok(())
+    }
+
+    // Locks the directory that `configure` and `make` run in
+    fn lock_work_dir(&self) -> Result<crate::util::Lock, RubyBuildError> {
+        use RubyBuildError::*;
+
+        // The lock file is created next to the build directory
+        let parent = self.build_dir.as_deref().and_then(Path::parent);
+        if let Some(parent) = parent {
+            fs::create_dir_all(parent).map_err(CreateBuildDir)?;
+        }
+        crate::util::lock(self.work_dir()).map_err(Lock)
+    }
+
+    // Creates the directories that must exist before running any phase
+    fn create_dirs(&self) -> Result<(), RubyBuildError> {
+        use RubyBuildError::CreateBuildDir;
+
+        if let Some(build_dir) = &self.build_dir {
+            fs::create_dir_all(build_dir).map_err(CreateBuildDir)?;
+        }
+        // Mounted into the container, so it must exist beforehand
+        if self.container.is_some() {
+            fs::create_dir_all(&self.out_dir).map_err(CreateBuildDir)?;
+        }
+        Ok(())
+    }
+
+    // The directory of the files that mark phases as completed, which is never
+    // the sources so that they stay clean
+    fn marker_dir(&self) -> &Path {
+        self.build_dir.as_deref().unwrap_or(&self.out_dir)
+    }
+
+    // Whether `phase` last completed, or `None` if it was never recorded, such
+    // as for sources built by an older version
+    fn completed(&self, phase: Phase) -> Option<bool> {
+        let marker = fs::read_to_string(self.marker_path(phase)).ok()?;
+        Some(marker != PENDING_MARKER)
+    }
+
+    // The file within the marker directory that marks `phase` as completed
+    fn marker_path(&self, phase: Phase) -> PathBuf {
+        let name = match phase {
+            Phase::Configure => FINGERPRINT_FILE,
+            Phase::Autoconf => ".aloxide-autoconf",
+            Phase::Make => ".aloxide-make",
+        };
+        self.marker_dir().join(name)
+    }
+
+    // Builds each architecture out-of-tree, installs each into a staging
+    // directory via `DESTDIR`, and then merges them into `out_dir`
+    fn run_universal(&mut self) -> Result<(), RubyBuildError> {
+        use std::mem;
+        use std::path::Component;
+        use RubyBuildError::*;
+
+        let universal_dir = self.work_dir().join("universal");
+        let out_dir = std::path::absolute(&self.out_dir)
+            .map_err(MergeSlices)?;
+
+        // The path of `out_dir` within a `DESTDIR`
+        let out_rel: PathBuf = out_dir.components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+
+        let mut slices = Vec::new();
+        for arch in UNIVERSAL_ARCHS {
+            let arch_dir = universal_dir.join(arch);
+            let dest_dir = arch_dir.join("dest");
+            let host = format!("{}-apple-darwin", arch);
+
+            let mut configure = clone_command(&self.configure);
+            configure.arg(format!("--host={}", host));
+            configure.arg(format!("--target={}", host));
+            configure.arg(format!("--with-arch={}", arch));
+
+            let mut make = clone_command(&self.make);
+            make.env("DESTDIR", &dest_dir);
+
+            // Always install the slice since `out_dir` is checked otherwise
+            let configure = mem::replace(&mut self.configure, configure);
+            let make = mem::replace(&mut self.make, make);
+            let build_dir = self.build_dir.replace(arch_dir.join("build"));
+            let force_make = mem::replace(&mut self.force_make, true);
+
+            let result = self.run_plan();
+
+            self.configure = configure;
+            self.make = make;
+            self.build_dir = build_dir;
+            self.force_make = force_make;
+
+            result?;
+            slices.push(dest_dir.join(&out_rel));
+        }
+
+        merge_slices(&slices, &out_dir).map_err(MergeSlices)
+    }
+
+    // Runs `strip` on the native binaries installed in `out_dir`
+    fn strip_installed(&self) -> Result<(), RubyBuildError> {
+        use RubyBuildError::*;
+
+        let mut binaries = Vec::new();
+        for dir in &[self.out_dir.join("bin"), self.out_dir.join("lib")] {
+            if !dir.exists() {
+                continue;
+            }
+            crate::util::walk_files(dir, |path| {
+                if is_native_binary(&path)? {
+                    binaries.push(path);
+                }
+                Ok(())
+            }).map_err(Strip)?;
+        }
+        if binaries.is_empty() {
+            return Ok(());
+        }
+
+        let program = std::env::var_os("STRIP")
+            .unwrap_or_else(|| OsString::from("strip"));
+        let mut strip = Command::new(program);
+        if self.target_apple {
+            // Remove local symbols, which keeps dylibs linkable
+            strip.arg("-x");
+        } else {
+            strip.arg("--strip-unneeded");
+        }
+
+        let output = strip.args(&binaries).output().map_err(Strip)?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(StripFail(output))
+        }
+    }
+
+    // Builds Ruby for the build machine from the same sources
+    fn build_baseruby(&self, build: &str) -> Result<Ruby, RubyBuildError> {
+        let dir = self.work_dir().join("baseruby");
+        RubyBuilder::new(self.src, dir.join("out"), build)
+            .build_dir(dir.join("build"))
+            .configure()
+            .disable_install_doc()
+            .build()
+            .map_err(|error| RubyBuildError::BaseRuby(Box::new(error)))
+    }
+
+    /// Performs the required build steps up to and including `phase`.
+    ///
+    /// For example, `run_until(Phase::Configure)` generates the `Makefile`
+    /// without compiling anything.
+    #[inline]
+    pub fn run_until(mut self, phase: Phase) -> Result<(), RubyBuildError> {
+        self.until = Some(phase);
+        self.run_phases()
+    }
+
+    /// Performs the required build steps without expecting Ruby to be
+    /// installed afterwards.
+    ///
+    /// This is useful with `make` targets other than `install`, such as
+    /// `miniruby`.
+    #[inline]
+    pub fn run(mut self) -> Result<(), RubyBuildError> {
+        self.run_phases()
+    }
+
+    /// Performs the required build steps for Ruby in one go.
+    ///
+    /// Builds in the same directory wait for each other via a lock file next
+    /// to it, such as `ruby-3.2.2.lock`, which is removed once done.
+    pub fn build(mut self) -> Result<Ruby, RubyBuildError> {
+        self.run_phases()?;
+        self.into_ruby()
+    }
+
+    /// Performs the required build steps for Ruby in one go, returning a
+    /// report of how long each phase took alongside the result.
+    ///
+    /// The report covers the phases that were run or skipped before the
+    /// build finished or failed.
+    pub fn build_with_report(
+        mut self,
+    ) -> (Result<Ruby, RubyBuildError>, BuildReport) {
+        let result = self.run_phases();
+        let report = BuildReport {
+            phases: std::mem::take(&mut self.report),
+        };
+        (result.and_then(|_| self.into_ruby()), report)
+    }
+
+    /// Starts performing the build steps in the background and returns a
+    /// handle for waiting on or canceling the build.
+    ///
+    /// Each phase runs as a child process that is advanced by calls to
+    /// [`BuildHandle::try_wait`](struct.BuildHandle.html#method.try_wait) and
+    /// [`BuildHandle::wait`](struct.BuildHandle.html#method.wait). Its output
+    /// is captured, so handles set via `stdout` and `stderr` are ignored.
+    ///
+    /// Builds for [`universal_macos`](#method.universal_macos) and builds that
+    /// need a `baseruby` for [`cross`](#method.cross) perform that extra work
+    /// before this returns.
+    pub fn build_spawned(mut self) -> Result<BuildHandle<'a>, RubyBuildError> {
+        if self.universal_macos && self.target_apple {
+            self.run_phases()?;
+            return Ok(BuildHandle::new(self, Vec::new(), None));
+        }
+
+        self.prepare_baseruby()?;
+
+        // Held by the handle until the build finishes
+        let lock = self.lock_work_dir()?;
+
+        self.create_dirs()?;
+
+        let steps = self.dry_run().steps;
+        Ok(BuildHandle::new(self, steps, Some(lock)))
+    }
+
+    // Performs the post-install steps and returns the installed Ruby
+    fn into_ruby(self) -> Result<Ruby, RubyBuildError> {
+        use crate::Version;
+
+        if self.strip && !self.target_msvc() {
+            self.strip_installed()?;
+        }
+
+        let bin_path = self.bin_path();
+        // The installed `ruby` may not run on this machine, so its
+        // configuration is read from the installed `rbconfig.rb` instead
+        let config = if self.is_cross() || self.container.is_some() {
+            let config = RbConfig::read_installed(&self.out_dir)
+                .ok_or(RubyBuildError::NoRbConfig)?;
+            Some(config)
+        } else {
+            None
+        };
+        let version = match &config {
+            Some(config) => {
+                rbconfig_version(config).ok_or(RubyBuildError::NoRbConfig)?
+            },
+            None => Version::from_bin(&bin_path)?,
+        };
+
+        // Allows for `Ruby::uninstall` to remove `out_dir`
+        let marker = self.out_dir.join(crate::INSTALL_MARKER);
+        fs::write(marker, version.to_string())
+            .map_err(RubyBuildError::WriteMarker)?;
+
+        let lib_dir = self.out_dir.join("lib");
+        if self.cargo_directives {
+            // Avoid running `ruby`, which may not be runnable when cross
+            // compiling
+            let include_dir = self.out_dir.join("include");
+            crate::emit_cargo_directives(&bin_path, &lib_dir, &include_dir);
+        }
+
+        let ruby = Ruby::new(version, self.out_dir);
+        if let Some(config) = config {
+            // Keeps `ruby` from being run for either of these later on, such
+            // as by `Ruby::link`
+            ruby.config.get_or_init(|| config);
+            ruby.engine.get_or_init(|| Engine::Mri);
+        }
+        Ok(ruby)
+    }
+}
+
+/// A build running in the background, as returned by
+/// [`RubyBuilder::build_spawned`](struct.RubyBuilder.html#method.build_spawned).
+///
+/// Dropping the handle before the build finishes leaves the current phase's
+/// process running; use [`kill`](#method.kill) to stop it.
+pub struct BuildHandle<'a> {
+    builder: RubyBuilder<'a>,
+    steps: std::vec::IntoIter<BuildStep>,
+    running: Option<RunningStep>,
+    result: Option<Result<(), RubyBuildError>>,
+    _lock: Option<crate::util::Lock>,
+}
+
+// A phase whose process is running
+struct RunningStep {
+    phase: Phase,
+    reader: LineReader,
+    log: Option<fs::File>,
+    marker: String,
+}
+
+impl<'a> BuildHandle<'a> {
+    fn new(
+        builder: RubyBuilder<'a>,
+        steps: Vec<BuildStep>,
+        lock: Option<crate::util::Lock>,
+    ) -> Self {
+        BuildHandle {
+            builder,
+            steps: steps.into_iter(),
+            running: None,
+            result: None,
+            _lock: lock,
+        }
+    }
+
+    /// Returns the phase that is currently running, if any.
+    #[inline]
+    pub fn phase(&self) -> Option<Phase> {
+        self.running.as_ref().map(|running| running.phase)
+    }
+
+    /// Advances the build without blocking and returns whether it finished.
+    ///
+    /// This passes output to line callbacks and starts the next phase once
+    /// the current one exits.
+    pub fn try_wait(&mut self) -> bool {
+        self.advance(false);
+        self.result.is_some()
+    }
+
+    /// Blocks until the build finishes and returns the result.
+    pub fn wait(mut self) -> Result<Ruby, RubyBuildError> {
+        self.advance(true);
+        match self.result.take() {
+            Some(Ok(())) => {
+                drop(self._lock.take());
+                self.builder.into_ruby()
+            },
+            Some(Err(error)) => Err(error),
+            None => unreachable!("build did not finish"),
+        }
+    }
+
+    /// Kills the process of the current phase and cancels the build.
+    ///
+    /// Afterwards, [`wait`](#method.wait) returns
+    /// [`RubyBuildError::Killed`](enum.RubyBuildError.html#variant.Killed).
+    pub fn kill(&mut self) -> io::Result<()> {
+        if self.result.is_some() {
+            return Ok(());
+        }
+        if let Some(mut running) = self.running.take() {
+            running.reader.child.kill()?;
+            running.reader.child.wait()?;
+            self.end_phase(running.phase, Err(RubyBuildError::Killed));
+        } else {
+            self.result = Some(Err(RubyBuildError::Killed));
+        }
+        Ok(())
+    }
+
+    // Records the result of `phase`, which finishes the build on failure
+    fn end_phase(&mut self, phase: Phase, result: Result<(), RubyBuildError>) {
+        if let Some(on_end) = &mut self.builder.on_phase_end {
+            on_end(phase, result.as_ref().map(|_| ()));
+        }
+        if let Err(error) = result {
+            self.result = Some(Err(error));
+        }
+    }
+
+    // Runs phases until the build finishes or, unless `block` is set, the
+    // current phase is still running
+    fn advance(&mut self, block: bool) {
+        while self.result.is_none() {
+            let mut running = match self.running.take() {
+                Some(running) => running,
+                None => match self.steps.next() {
+                    Some(step) => match self.start(&step) {
+                        Ok(running) => running,
+                        Err(error) => {
+                            self.end_phase(step.phase, Err(error));
+                            continue;
+                        },
+                    },
+                    None => {
+                        self.result = Some(Ok(()));
+                        return;
+                    },
+                },
+            };
+
+            let phase = running.phase;
+            let (_, lines, _, spawn_fail) = self.builder.phase_parts(phase);
+            running.reader.pump(lines, running.log.as_mut(), false);
+
+            if !block {
+                match running.reader.child.try_wait() {
+                    Ok(None) => {
+                        self.running = Some(running);
+                        return;
+                    },
+                    Ok(Some(_)) => {},
+                    Err(error) => {
+                        self.end_phase(phase, Err(spawn_fail(error)));
+                        continue;
+                    },
+                }
+            }
+
+            let RunningStep { reader, mut log, marker, .. } = running;
+            let (_, lines, _, _) = self.builder.phase_parts(phase);
+            let result = reader.finish(lines, log.as_mut())
+                .map_err(spawn_fail)
+                .and_then(|output| {
+                    self.builder.finish_step(phase, output, marker)
+                });
+            self.end_phase(phase, result);
+        }
+    }
+
+    fn start(
+        &mut self,
+        step: &BuildStep,
+    ) -> Result<RunningStep, RubyBuildError> {
+        if let Some(on_start) = &mut self.builder.on_phase_start {
+            on_start(step.phase);
+        }
+        let (log, marker) = self.builder.start_step(step)?;
+        let (cmd, _, _, spawn_fail) = self.builder.phase_parts(step.phase);
+        let reader = LineReader::spawn(cmd).map_err(spawn_fail)?;
+        Ok(RunningStep { phase: step.phase, reader, log, marker })
+    }
+}
+
+/// Configures cross-compiling Ruby via
+/// [`RubyBuilder::cross`](struct.RubyBuilder.html#method.cross).
+#[derive(Clone, Debug, Default)]
+pub struct CrossBuild {
+    build: Option<String>,
+    baseruby: Option<PathBuf>,
+    envs: Vec<(OsString, OsString)>,
+    inherit_tools: bool,
+}
+
+impl CrossBuild {
+    /// Creates a new configuration for building on the current machine.
+    ///
+    /// The build machine is taken from the `HOST` environment variable, which
+    /// Cargo sets for build scripts.
+    #[inline]
+    pub fn new() -> Self {
+        CrossBuild {
+            build: std::env::var("HOST").ok(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the target triple of the machine performing the build.
+    #[inline]
+    pub fn build_triple(mut self, triple: impl Into<String>) -> Self {
+        self.build = Some(triple.into());
+        self
+    }
+
+    /// Uses `ruby` of the build machine to run scripts during the build.
+    ///
+    /// Its version should match the version being built.
+    #[inline]
+    pub fn baseruby(self, ruby: &Ruby) -> Self {
+        self.baseruby_path(ruby.bin_path())
+    }
+
+    /// Uses the `ruby` executable at `path` to run scripts during the build.
+    #[inline]
+    pub fn baseruby_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.baseruby = Some(path.into());
+        self
+    }
+
+    /// Sets the tool used for `name`, such as `CC`, `AR`, or `RANLIB`.
+    #[inline]
+    pub fn tool(
+        mut self,
+        name: impl Into<OsString>,
+        path: impl Into<OsString>,
+    ) -> Self {
+        self.envs.push((name.into(), path.into()));
+        self
+    }
+
+    /// Inherits the tools for the target from the environment in the same
+    /// way as the [`cc`](https://docs.rs/cc) crate.
+    ///
+    /// For example, `CC` is taken from `CC_aarch64-unknown-linux-gnu`,
+    /// `CC_aarch64_unknown_linux_gnu`, or `TARGET_CC`, in that order.
+    #[inline]
+    pub fn inherit_tools(mut self) -> Self {
+        self.inherit_tools = true;
+        self
+    }
+}
+
+// Returns the cross toolchain set in the environment for `target`
+fn target_tools(target: &str) -> Vec<(OsString, OsString)> {
+    let underscored = target.replace('-', "_");
+    ["CC", "CXX", "AR", "RANLIB", "STRIP", "CFLAGS", "LDFLAGS"]
+        .iter()
+        .filter_map(|tool| {
+            let val = std::env::var_os(format!("{}_{}", tool, target))
+                .or_else(|| {
+                    std::env::var_os(format!("{}_{}", tool, underscored))
+                })
+                .or_else(|| std::env::var_os(format!("TARGET_{}", tool)))?;
+            Some((OsString::from(tool), val))
+        })
+        .collect()
+}
+
+// Reads the version from the configuration of an installed `rbconfig.rb`
+fn rbconfig_version(config: &RbConfig) -> Option<crate::Version> {
+    let value = |key: &str| -> Option<u16> { config.get(key)?.parse().ok() };
+    Some(crate::Version::new(value("MAJOR")?, value("MINOR")?, value("TEENY")?))
+}
+
+/// A step of building Ruby.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Phase {
+    /// Generating `configure` via `autoconf`.
+    Autoconf,
+    /// Generating `Makefile` via `configure`.
+    Configure,
+    /// Compiling and installing via `make install`.
+    Make,
+}
+
+impl Phase {
+    /// Returns the name of the program run by this phase.
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Autoconf => "autoconf",
+            Phase::Configure => "configure",
+            Phase::Make => "make",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The commands that
+/// [`RubyBuilder::build`](struct.RubyBuilder.html#method.build) would run, as
+/// returned by
+/// [`RubyBuilder::dry_run`](struct.RubyBuilder.html#method.dry_run).
+///
+/// The `Display` implementation writes each step as a shell command on its own
+/// line.
+#[derive(Clone, Debug)]
+pub struct BuildPlan {
+    /// The steps to run, in order.
+    pub steps: Vec<BuildStep>,
+}
+
+impl fmt::Display for BuildPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+impl BuildPlan {
+    /// Returns whether `phase` would run.
+    #[inline]
+    pub fn runs(&self, phase: Phase) -> bool {
+        self.step(phase).is_some()
+    }
+
+    /// Returns the step for `phase` if it would run.
+    #[inline]
+    pub fn step(&self, phase: Phase) -> Option<&BuildStep> {
+        self.steps.iter().find(|step| step.phase == phase)
+    }
+}
+
+/// The phases performed by a build, as returned by
+/// [`RubyBuilder::build_with_report`](struct.RubyBuilder.html#method.build_with_report).
+///
+/// The `Display` implementation writes the duration of each phase on its own
+/// line.
+#[derive(Clone, Debug)]
+pub struct BuildReport {
+    /// The phases that were run or skipped, in order.
+    ///
+    /// Builds for
+    /// [`RubyBuilder::universal_macos`](struct.RubyBuilder.html#method.universal_macos)
+    /// include the phases for each architecture.
+    pub phases: Vec<PhaseReport>,
+}
+
+impl fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for phase in &self.phases {
+            writeln!(f, "{}", phase)?;
+        }
+        Ok(())
+    }
+}
+
+impl BuildReport {
+    /// Returns the total time spent running phases.
+    #[inline]
+    pub fn duration(&self) -> Duration {
+        self.phases.iter().map(|phase| phase.duration).sum()
+    }
+}
+
+/// A phase within a [`BuildReport`](struct.BuildReport.html).
+#[derive(Clone, Debug)]
+pub struct PhaseReport {
+    /// The phase that was run or skipped.
+    pub phase: Phase,
+    /// The exact command that was run, or `None` if the phase was skipped
+    /// because it was already up-to-date.
+    pub step: Option<BuildStep>,
+    /// The wall-clock time spent running the phase.
+    pub duration: Duration,
+}
+
+impl fmt::Display for PhaseReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.step {
+            Some(step) => write!(
+                f,
+                "{}: {:.1}s: {}",
+                self.phase,
+                self.duration.as_secs_f64(),
+                step,
+            ),
+            None => write!(f, "{}: skipped", self.phase),
+        }
+    }
+}
 
-    #[cfg(windows)]
-    target_msvc: bool,
+impl PhaseReport {
+    #[inline]
+    fn skipped(phase: Phase) -> Self {
+        PhaseReport { phase, step: None, duration: Duration::default() }
+    }
+
+    /// Returns whether the phase was skipped.
+    #[inline]
+    pub fn is_skipped(&self) -> bool {
+        self.step.is_none()
+    }
 }
 
-impl<'a> RubyBuilder<'a> {
-    // Process `target` to make it usable with building for Windows
-    fn convert_to_ruby(target: &str) -> &str {
-        match target {
-            "x86_64-pc-windows-msvc" => "x84_64-mswin64",
-            "x86_64-pc-windows-gnu"  => "x86_64-pc-mingw32",
-            "i686-pc-windows-msvc"   => "x86-mswin32",
-            "i686-pc-windows-gnu"    => "x86-pc-mingw32",
-            other => other
+/// A command within a [`BuildPlan`](struct.BuildPlan.html).
+#[derive(Clone, Debug)]
+pub struct BuildStep {
+    /// The phase that this step performs.
+    pub phase: Phase,
+    /// The program to run.
+    pub program: OsString,
+    /// The arguments passed into `program`.
+    pub args: Vec<OsString>,
+    /// The environment variables explicitly set, or removed if `None`.
+    pub envs: Vec<(OsString, Option<OsString>)>,
+    /// Whether `envs` are the only environment variables, rather than
+    /// modifying the inherited environment.
+    pub clear_env: bool,
+    /// The working directory.
+    pub dir: PathBuf,
+}
+
+impl fmt::Display for BuildStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cd {} &&", quote(self.dir.as_os_str()))?;
+
+        if self.clear_env {
+            f.write_str(" env -i")?;
+        } else {
+            let removed = self.envs.iter().filter(|(_, val)| val.is_none());
+            for (i, (key, _)) in removed.enumerate() {
+                if i == 0 {
+                    f.write_str(" env")?;
+                }
+                write!(f, " -u {}", quote(key))?;
+            }
+        }
+        for (key, val) in &self.envs {
+            if let Some(val) = val {
+                write!(f, " {}={}", key.to_string_lossy(), quote(val))?;
+            }
+        }
+
+        write!(f, " {}", quote(&self.program))?;
+        for arg in &self.args {
+            write!(f, " {}", quote(arg))?;
         }
+        Ok(())
     }
+}
 
-    // Process `target` to make it usable with `cc::windows_registry::find`
-    fn convert_to_rust(target: &str) -> &str {
-        if target.contains("mswin") {
-            if target.contains("64") {
-                "x86_64-pc-windows-msvc"
-            } else {
-                "i686-pc-windows-msvc"
-            }
-        } else {
-            target
+impl BuildStep {
+    fn new(phase: Phase, cmd: &Command, dir: &Path) -> Self {
+        BuildStep {
+            phase,
+            program: cmd.get_program().to_owned(),
+            args: cmd.get_args().map(ToOwned::to_owned).collect(),
+            envs: cmd.get_envs()
+                .map(|(key, val)| (key.to_owned(), val.map(ToOwned::to_owned)))
+                .collect(),
+            clear_env: false,
+            dir: dir.to_path_buf(),
         }
     }
+}
 
-    pub(crate) fn new(
-        src: &'a RubySrc,
-        out_dir: PathBuf,
-        target: &str,
-    ) -> Self {
-        let src_dir = src.as_path();
-        let ruby_target = RubyBuilder::convert_to_ruby(target);
-        let rust_target = RubyBuilder::convert_to_rust(target);
+// The environment variables inherited by `RubyBuilder::clean_env`
+const ENV_WHITELIST: &[&str] = &[
+    "PATH",
+    "HOME",
+    "TMPDIR",
+    // Required by Windows programs
+    "SYSTEMROOT",
+    "TEMP",
+    "TMP",
+    "USERPROFILE",
+    "COMSPEC",
+];
+
+// Makes `step` run with only its explicit and whitelisted environment
+fn isolate_env(step: &mut BuildStep) {
+    step.envs.retain(|(_, val)| val.is_some());
+
+    let inherited: Vec<_> = ENV_WHITELIST.iter()
+        .filter(|key| step.envs.iter().all(|(k, _)| k != **key))
+        .filter_map(|key| {
+            let val = std::env::var_os(key)?;
+            Some((OsString::from(key), Some(val)))
+        })
+        .collect();
 
-        let nmake = crate::util::nmake(rust_target);
-        let target_msvc = cfg!(target_os = "windows") && nmake.is_some();
+    step.envs.splice(0..0, inherited);
+    step.clear_env = true;
+}
 
-        let (mut make, configure_path) = match nmake {
-            Some(nmake) => {
-                let mut path = src_dir.join("win32");
-                path.push("configure.bat");
-                (nmake, path)
-            },
-            None => (Command::new("make"), src_dir.join("configure"))
-        };
+// Quotes `s` for use in a POSIX shell if needed
+fn quote(s: &OsStr) -> Cow<'_, str> {
+    let s = s.to_string_lossy();
+    let is_safe = |c: char| {
+        c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c)
+    };
+    if !s.is_empty() && s.chars().all(is_safe) {
+        s
+    } else {
+        Cow::Owned(format!("'{}'", s.replace('\'', "'\\''")))
+    }
+}
 
-        make.arg("install");
-        make.env("PREFIX", &out_dir);
+// Runs `cmd` to completion, passing each line of output to `handlers` and
+// `log` as it's written
+//
+// Like `Command::output`, both streams are captured and returned
+fn output_lines(
+    cmd: &mut Command,
+    handlers: &mut LineHandlers,
+    log: Option<&mut fs::File>,
+) -> io::Result<Output> {
+    let no_handlers = handlers.stdout.is_none() && handlers.stderr.is_none();
+    if no_handlers && log.is_none() {
+        return cmd.output();
+    }
+    LineReader::spawn(cmd)?.finish(handlers, log)
+}
 
-        let mut configure = if cfg!(target_os = "windows") && !target_msvc {
-            // HACK: Spawn `configure` via `sh` since `Command::new` requires a
-            // Win32 application to work
-            let mut sh = Command::new("sh.exe");
-            sh.arg("configure");
-            sh
-        } else {
-            Command::new(&configure_path)
+// A child process whose output is read line by line on other threads
+struct LineReader {
+    child: Child,
+    lines: mpsc::Receiver<(bool, Vec<u8>)>,
+    readers: Vec<thread::JoinHandle<io::Result<()>>>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl LineReader {
+    fn spawn(cmd: &mut Command) -> io::Result<Self> {
+        use std::io::{BufRead, BufReader, Read};
+
+        let mut child = cmd
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Lines are sent back since the handlers can't be sent to other
+        // threads
+        let (tx, rx) = mpsc::channel::<(bool, Vec<u8>)>();
+        let read = |stream: Box<dyn Read + Send>, is_err: bool| {
+            let tx = tx.clone();
+            thread::spawn(move || -> io::Result<()> {
+                let mut reader = BufReader::new(stream);
+                loop {
+                    let mut line = Vec::new();
+                    if reader.read_until(b'\n', &mut line)? == 0 {
+                        return Ok(());
+                    }
+                    if tx.send((is_err, line)).is_err() {
+                        return Ok(());
+                    }
+                }
+            })
         };
 
-        configure.arg(format!("--prefix={}", out_dir.display()));
-        configure.arg(format!("--target={}", ruby_target));
+        let readers = vec![
+            child.stdout.take().map(|out| read(Box::new(out), false)),
+            child.stderr.take().map(|err| read(Box::new(err), true)),
+        ];
+
+        Ok(LineReader {
+            child,
+            lines: rx,
+            readers: readers.into_iter().flatten().collect(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        })
+    }
 
-        RubyBuilder {
-            src,
-            out_dir,
-            autoconf: Command::new("autoconf"),
-            force_autoconf: false,
-            configure,
-            configure_path,
-            force_configure: false,
-            make,
-            force_make: false,
+    // Passes the lines written so far to `handlers` and `log`, blocking until
+    // the streams are closed if `block` is set
+    fn pump(
+        &mut self,
+        handlers: &mut LineHandlers,
+        mut log: Option<&mut fs::File>,
+        block: bool,
+    ) {
+        use std::io::Write;
+
+        loop {
+            let (is_err, line) = if block {
+                match self.lines.recv() {
+                    Ok(line) => line,
+                    Err(_) => return,
+                }
+            } else {
+                match self.lines.try_recv() {
+                    Ok(line) => line,
+                    Err(_) => return,
+                }
+            };
 
-            #[cfg(windows)]
-            target_msvc,
+            let (buf, handler) = if is_err {
+                (&mut self.stderr, &mut handlers.stderr)
+            } else {
+                (&mut self.stdout, &mut handlers.stdout)
+            };
+            if let Some(handler) = handler {
+                let text = String::from_utf8_lossy(&line);
+                handler(text.trim_end_matches(&['\n', '\r'][..]));
+            }
+            if let Some(log) = &mut log {
+                // Logging shouldn't fail the build
+                let _ = log.write_all(&line);
+            }
+            buf.extend_from_slice(&line);
         }
     }
 
-    /// Adjust what happens when running `autoconf`.
-    #[inline]
-    pub fn autoconf(self) -> AutoconfPhase<'a> {
-        AutoconfPhase(self)
+    // Waits for the process to exit after passing along all of its output
+    fn finish(
+        mut self,
+        handlers: &mut LineHandlers,
+        log: Option<&mut fs::File>,
+    ) -> io::Result<Output> {
+        self.pump(handlers, log, true);
+        for reader in self.readers {
+            reader.join().expect("output reader panicked")?;
+        }
+        Ok(Output {
+            status: self.child.wait()?,
+            stdout: self.stdout,
+            stderr: self.stderr,
+        })
     }
+}
 
-    /// Adjust what happens when running `configure`.
-    #[inline]
-    pub fn configure(self) -> ConfigurePhase<'a> {
-        ConfigurePhase(self)
+// The architectures built by `RubyBuilder::universal_macos`
+const UNIVERSAL_ARCHS: &[&str] = &["arm64", "x86_64"];
+
+// Returns a copy of `cmd` without its standard I/O handles
+fn clone_command(cmd: &Command) -> Command {
+    let mut clone = Command::new(cmd.get_program());
+    clone.args(cmd.get_args());
+    for (key, val) in cmd.get_envs() {
+        match val {
+            Some(val) => clone.env(key, val),
+            None => clone.env_remove(key),
+        };
     }
-
-    /// Adjust what happens when running `make`.
-    #[inline]
-    pub fn make(self) -> MakePhase<'a> {
-        MakePhase(self)
+    if let Some(dir) = cmd.get_current_dir() {
+        clone.current_dir(dir);
     }
+    clone
+}
 
-    /// Performs the required build steps for Ruby in one go.
-    pub fn build(mut self) -> Result<Ruby, RubyBuildError> {
-        use RubyBuildError::*;
-        use crate::Version;
+// Copies the first of `slices` into `out_dir`, combining each native binary
+// with those at the same path in the other slices via `lipo`
+fn merge_slices(slices: &[PathBuf], out_dir: &Path) -> io::Result<()> {
+    let (first, rest) = match slices.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    merge_dir(first, rest, Path::new(""), out_dir)
+}
 
-        #[cfg(target_os = "windows")]
-        let target_msvc = self.target_msvc;
+fn merge_dir(
+    first: &Path,
+    rest: &[PathBuf],
+    rel: &Path,
+    out_dir: &Path,
+) -> io::Result<()> {
+    let dst_dir = out_dir.join(rel);
+    fs::create_dir_all(&dst_dir)?;
+
+    for entry in fs::read_dir(first.join(rel))? {
+        let entry = entry?;
+        let rel = rel.join(entry.file_name());
+        let src = entry.path();
+        let dst = out_dir.join(&rel);
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            merge_dir(first, rest, &rel, out_dir)?;
+            continue;
+        }
 
-        #[cfg(not(target_os = "windows"))]
-        let target_msvc = false;
-
-        macro_rules! phase {
-            ($cmd:ident, $cond:expr, $fail:ident, $spawn_fail:ident) => (
-                if $cond {
-                    let output = self.$cmd
-                        .current_dir(&self.src)
-                        .output()
-                        .map_err($spawn_fail)?;
-
-                    if !output.status.success() {
-                        return Err($fail(output));
-                    }
-                }
-            )
+        match fs::remove_file(&dst) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                return Err(error);
+            },
+            _ => {},
+        }
+
+        if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(&src)?, &dst)?;
+
+            #[cfg(not(unix))]
+            fs::copy(&src, &dst)?;
+
+            continue;
         }
 
-        let run_autoconf = if target_msvc {
-            false
+        let others: Vec<PathBuf> = rest.iter()
+            .map(|slice| slice.join(&rel))
+            .filter(|path| path.is_file())
+            .collect();
+
+        if !others.is_empty() && is_native_binary(&src)? {
+            let output = Command::new("lipo")
+                .arg("-create")
+                .arg(&src)
+                .args(&others)
+                .arg("-output")
+                .arg(&dst)
+                .output()?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(io::Error::other(format!(
+                    "`lipo` failed for {}: {}",
+                    rel.display(),
+                    stderr.trim(),
+                )));
+            }
         } else {
-            let run_autoconf = self.force_autoconf || !self.configure_path.exists();
-            phase!(autoconf, run_autoconf, AutoconfFail, AutoconfSpawnFail);
-            run_autoconf
-        };
+            fs::copy(&src, &dst)?;
+        }
+    }
+    Ok(())
+}
 
-        let src_dir = self.src.as_path();
+// Whether `path` is an ELF or Mach-O executable or shared library, as opposed
+// to a script or static library
+fn is_native_binary(path: &Path) -> io::Result<bool> {
+    use std::io::Read;
 
-        let run_configure = run_autoconf || self.force_configure || !src_dir.join("Makefile").exists();
-        phase!(configure, run_configure, ConfigureFail, ConfigureSpawnFail);
+    let mut magic = [0u8; 4];
+    let mut file = fs::File::open(path)?;
+    if file.read_exact(&mut magic).is_err() {
+        return Ok(false);
+    }
+    Ok(matches!(
+        magic,
+        [0x7F, b'E', b'L', b'F']
+            | [0xFE, 0xED, 0xFA, 0xCE..=0xCF]
+            | [0xCE..=0xCF, 0xFA, 0xED, 0xFE]
+            | [0xCA, 0xFE, 0xBA, 0xBE]
+    ))
+}
 
-        let bin_path = self.out_dir.join("bin").join(Ruby::bin_name());
+// The file within the build or output directory that stores the fingerprint of
+// the last successful `configure` run
+const FINGERPRINT_FILE: &str = ".aloxide-fingerprint";
 
-        let run_make = run_configure || self.force_make || !bin_path.exists();
-        phase!(make, run_make, MakeFail, MakeSpawnFail);
+// The contents of a phase's marker while the phase or an earlier one is running
+const PENDING_MARKER: &str = "pending";
 
-        let lib_dir = self.out_dir.join("lib");
-        Ok(Ruby {
-            version: Version::from_bin(&bin_path)?,
-            out_dir: self.out_dir,
-            lib_dir,
-            bin_path,
-        })
+// Whether the file at `path` was modified after the one at `than`, such as when
+// an in-tree build for another `out_dir` reconfigured the same sources
+fn is_newer(path: &Path, than: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path)?.modified();
+    match (modified(path), modified(than)) {
+        (Ok(modified), Ok(than)) => modified > than,
+        _ => false,
+    }
+}
+
+// Hashes the program, arguments, and environment of `cmd` via 64-bit FNV-1a
+fn fingerprint(step: &BuildStep) -> String {
+    fn write(hash: &mut u64, bytes: &[u8]) {
+        // Terminate each part to make the hash unambiguous
+        for &byte in bytes.iter().chain(&[0xFF]) {
+            *hash ^= byte as u64;
+            *hash = hash.wrapping_mul(0x100_0000_01B3);
+        }
+    }
+
+    let mut hash = 0xCBF2_9CE4_8422_2325;
+    write(&mut hash, step.program.as_encoded_bytes());
+    for arg in &step.args {
+        write(&mut hash, arg.as_encoded_bytes());
+    }
+    for (key, val) in &step.envs {
+        write(&mut hash, key.as_encoded_bytes());
+        match val {
+            Some(val) => write(&mut hash, val.as_encoded_bytes()),
+            None => write(&mut hash, &[]),
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+// The value of `SOURCE_DATE_EPOCH` for `RubyBuilder::reproducible`
+fn source_date_epoch(src_dir: &Path) -> OsString {
+    use std::time::UNIX_EPOCH;
+
+    if let Some(epoch) = std::env::var_os("SOURCE_DATE_EPOCH") {
+        return epoch;
     }
+    let secs = fs::metadata(src_dir.join("version.h"))
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_secs());
+    OsString::from(secs.to_string())
+}
+
+// The number of jobs to run `make` with by default
+fn auto_jobs() -> usize {
+    // Set by Cargo when running build scripts
+    let num_jobs = std::env::var("NUM_JOBS").ok()
+        .and_then(|jobs| jobs.parse().ok());
+
+    num_jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    })
 }
 
 /// Adjusts what happens when running `autoconf`.
@@ -190,7 +1877,7 @@ impl<'a> AutoconfPhase<'a> {
 
     /// Perform custom operations on the `Command` instance used.
     #[inline]
-    pub fn with_command<F: FnOnce(&mut Command) -> ()>(mut self, f: F) -> Self {
+    pub fn with_command<F: FnOnce(&mut Command)>(mut self, f: F) -> Self {
         f(&mut self.0.autoconf);
         self
     }
@@ -250,6 +1937,28 @@ impl<'a> AutoconfPhase<'a> {
         self
     }
 
+    /// Sets a callback for each line that `autoconf` writes to `stdout`.
+    ///
+    /// This captures both `stdout` and `stderr`, overriding their handles.
+    #[inline]
+    pub fn on_stdout_line<F>(mut self, f: F) -> Self
+        where F: FnMut(&str) + 'a
+    {
+        self.0.autoconf_lines.stdout = Some(Box::new(f));
+        self
+    }
+
+    /// Sets a callback for each line that `autoconf` writes to `stderr`.
+    ///
+    /// This captures both `stdout` and `stderr`, overriding their handles.
+    #[inline]
+    pub fn on_stderr_line<F>(mut self, f: F) -> Self
+        where F: FnMut(&str) + 'a
+    {
+        self.0.autoconf_lines.stderr = Some(Box::new(f));
+        self
+    }
+
     /// Adjust what happens when running `configure`.
     #[inline]
     pub fn configure(self) -> ConfigurePhase<'a> {
@@ -267,6 +1976,32 @@ impl<'a> AutoconfPhase<'a> {
     pub fn build(self) -> Result<Ruby, RubyBuildError> {
         self.0.build()
     }
+
+    /// Perform the build without expecting Ruby to be installed afterwards.
+    #[inline]
+    pub fn run(self) -> Result<(), RubyBuildError> {
+        self.0.run()
+    }
+
+    /// Perform the build and report how long each phase took.
+    #[inline]
+    pub fn build_with_report(
+        self,
+    ) -> (Result<Ruby, RubyBuildError>, BuildReport) {
+        self.0.build_with_report()
+    }
+
+    /// Start the build in the background.
+    #[inline]
+    pub fn build_spawned(self) -> Result<BuildHandle<'a>, RubyBuildError> {
+        self.0.build_spawned()
+    }
+
+    /// Returns the commands that would run, without running anything.
+    #[inline]
+    pub fn dry_run(&self) -> BuildPlan {
+        self.0.dry_run()
+    }
 }
 
 /// Adjusts what happens when running `configure`.
@@ -390,48 +2125,227 @@ impl<'a> ConfigurePhase<'a> {
         self
     }
 
-    /// Build an Apple/NeXT Multi Architecture Binary (MAB). If this option is
-    /// disabled or omitted entirely, then the package will be built only for
-    /// the target platform.
+    /// Build an Apple/NeXT Multi Architecture Binary (MAB). If this option is
+    /// disabled or omitted entirely, then the package will be built only for
+    /// the target platform.
+    ///
+    /// Passes `archs` as comma-separated values into `--with-arch=`.
+    #[inline]
+    pub fn arch(mut self, archs: &[impl Borrow<str>]) -> Self {
+        self.0.configure.arg(format!("--with-arch={}", archs.join(",")));
+        self
+    }
+
+    /// Build only the bundled extensions in `exts`, such as `openssl` and
+    /// `zlib`.
+    ///
+    /// Passes `exts` as comma-separated values into `--with-ext=`.
+    #[inline]
+    pub fn with_ext(mut self, exts: &[impl Borrow<str>]) -> Self {
+        self.0.configure.arg(format!("--with-ext={}", exts.join(",")));
+        self
+    }
+
+    /// Skip building the bundled extensions in `exts`, such as `tk` and
+    /// `fiddle`.
+    ///
+    /// Passes `exts` as comma-separated values into `--without-ext=`.
+    #[inline]
+    pub fn without_ext(mut self, exts: &[impl Borrow<str>]) -> Self {
+        self.0.configure.arg(format!("--without-ext={}", exts.join(",")));
+        self
+    }
+
+    /// Do not install neither rdoc indexes nor C API documents during install.
+    #[inline]
+    pub fn disable_install_doc(mut self) -> Self {
+        self.0.configure.arg("--disable-install-doc");
+        self
+    }
+
+    /// Disable dynamic link feature.
+    #[inline]
+    pub fn disable_dy_link(mut self) -> Self {
+        self.0.configure.arg("--disable-dln");
+        self
+    }
+
+    /// Resolve load paths at run time.
+    #[inline]
+    pub fn enable_load_relative(mut self) -> Self {
+        self.0.configure.arg("--enable-load-relative");
+        self
+    }
+
+    /// Disable rubygems by default.
+    #[inline]
+    pub fn disable_rubygems(mut self) -> Self {
+        self.0.configure.arg("--disable-rubygems");
+        self
+    }
+
+    // Passes `--with-{package}-dir={dir}`
+    fn with_dir(mut self, package: &str, dir: &Path) -> Self {
+        let mut arg = OsString::from(format!("--with-{}-dir=", package));
+        arg.push(dir);
+        self.0.configure.arg(arg);
+        self
+    }
+
+    /// Search `dirs` for the headers and libraries of all dependencies.
+    ///
+    /// Each directory is expected to have `include` and `lib` subdirectories.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of `dirs` contains the path separator character.
+    #[inline]
+    pub fn with_opt_dirs<I>(self, dirs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<OsStr>,
+    {
+        let dirs = std::env::join_paths(dirs).expect("invalid opt dir");
+        self.with_dir("opt", dirs.as_ref())
+    }
+
+    /// Use the OpenSSL installation at `dir`.
+    #[inline]
+    pub fn with_openssl_dir(self, dir: impl AsRef<Path>) -> Self {
+        self.with_dir("openssl", dir.as_ref())
+    }
+
+    /// Use the libyaml installation at `dir`, which is required by `psych`.
+    #[inline]
+    pub fn with_libyaml_dir(self, dir: impl AsRef<Path>) -> Self {
+        self.with_dir("libyaml", dir.as_ref())
+    }
+
+    /// Use the zlib installation at `dir`.
+    #[inline]
+    pub fn with_zlib_dir(self, dir: impl AsRef<Path>) -> Self {
+        self.with_dir("zlib", dir.as_ref())
+    }
+
+    /// Use the readline installation at `dir`.
+    #[inline]
+    pub fn with_readline_dir(self, dir: impl AsRef<Path>) -> Self {
+        self.with_dir("readline", dir.as_ref())
+    }
+
+    /// Use the GMP installation at `dir` for faster bignum operations.
+    #[inline]
+    pub fn with_gmp_dir(self, dir: impl AsRef<Path>) -> Self {
+        self.with_dir("gmp", dir.as_ref())
+    }
+
+    /// Searches for an OpenSSL installation and uses it if found.
+    ///
+    /// The following are checked in order:
+    ///
+    /// 1. The `OPENSSL_DIR` environment variable
+    /// 2. `brew --prefix openssl@3` and `brew --prefix openssl@1.1`
+    /// 3. `pkg-config --variable=prefix openssl`
+    /// 4. Common system prefixes, such as `/usr/local/ssl`
+    ///
+    /// If none are found, `configure` is left to find OpenSSL on its own.
+    pub fn auto_openssl(self) -> Self {
+        match find_openssl_dir() {
+            Some(dir) => self.with_openssl_dir(dir),
+            None => self,
+        }
+    }
+
+    /// Optimizes Ruby for speed and size by compiling with `-O3`, enabling
+    /// link-time optimization, omitting debug info, and
+    /// [stripping](struct.RubyBuilder.html#method.strip) the installed
+    /// binaries.
     ///
-    /// Passes `archs` as comma-separated values into `--with-arch=`.
-    #[inline]
-    pub fn arch(mut self, archs: &[impl Borrow<str>]) -> Self {
-        self.0.configure.arg(format!("--with-arch={}", archs.join(",")));
-        self
+    /// This overrides `optflags` and `debugflags`, and appends to any
+    /// `LDFLAGS` already passed into `configure` or set in the environment.
+    /// Requires a compiler with LTO support, such as GCC or Clang.
+    pub fn optimized(mut self) -> Self {
+        self.0.strip = true;
+        self.set_val("optflags", "-O3 -flto")
+            .set_val("debugflags", "")
+            .append_val("LDFLAGS", "-flto")
     }
 
-    /// Do not install neither rdoc indexes nor C API documents during install.
+    // Sets `key` to its current value followed by `val`, where the current
+    // value is the last one passed as an argument or else the environment's
+    fn append_val(self, key: &str, val: &str) -> Self {
+        let prefix = format!("{}=", key);
+        let arg = self.0.configure.get_args()
+            .filter_map(|arg| arg.to_str()?.strip_prefix(&prefix))
+            .last()
+            .map(OsString::from);
+        let env = || {
+            let cmd_env = self.0.configure.get_envs()
+                .find(|&(name, _)| name == key)
+                .map(|(_, val)| val.map(OsStr::to_os_string));
+            match cmd_env {
+                Some(val) => val,
+                None => std::env::var_os(key),
+            }
+        };
+
+        let mut new = arg.or_else(env).unwrap_or_default();
+        if !new.is_empty() {
+            new.push(" ");
+        }
+        new.push(val);
+        self.set_val(key, new)
+    }
+
+    /// Makes the installation relocatable, so that the output directory can
+    /// be moved or bundled within an application and still find its Ruby
+    /// libraries.
+    ///
+    /// This resolves load paths relative to `ruby` at run time. The search
+    /// path for a shared `libruby` is left unchanged, so this is best paired
+    /// with a static library.
     #[inline]
-    pub fn disable_install_doc(mut self) -> Self {
-        self.0.configure.arg("--disable-install-doc");
-        self
+    pub fn relocatable(self) -> Self {
+        self.enable_load_relative()
     }
 
-    /// Disable dynamic link feature.
+    /// Links the bundled extensions into the static library and disables
+    /// loading extensions at run time.
+    ///
+    /// Paired with a static library, this allows for
+    /// [`Ruby::link(true)`](../../struct.Ruby.html#method.link) to produce a
+    /// binary that loads no shared libraries for Ruby.
     #[inline]
-    pub fn disable_dy_link(mut self) -> Self {
-        self.0.configure.arg("--disable-dln");
-        self
+    pub fn static_extensions(self) -> Self {
+        self.static_lib(true)
+            .shared_lib(false)
+            .with("static-linked-ext")
+            .disable_dy_link()
     }
 
-    /// Resolve load paths at run time.
+    /// Checks the flags passed into `configure` against the output of
+    /// `configure --help` before running it.
+    ///
+    /// Otherwise, `configure` silently ignores unknown flags, such as
+    /// `--disable-install-docs`. Flags passed through to extensions, such as
+    /// `--with-openssl-dir`, are not checked.
     #[inline]
-    pub fn enable_load_relative(mut self) -> Self {
-        self.0.configure.arg("--enable-load-relative");
+    pub fn validate_flags(mut self) -> Self {
+        self.0.validate_flags = true;
         self
     }
 
-    /// Disable rubygems by default.
+    /// Applies the flags for `preset`.
+    ///
+    /// Flags passed afterwards take precedence over those of `preset`.
     #[inline]
-    pub fn disable_rubygems(mut self) -> Self {
-        self.0.configure.arg("--disable-rubygems");
-        self
+    pub fn preset(self, preset: Preset) -> Self {
+        self.args(preset.args())
     }
 
     /// Perform custom operations on the `Command` instance used.
     #[inline]
-    pub fn with_command<F: FnOnce(&mut Command) -> ()>(mut self, f: F) -> Self {
+    pub fn with_command<F: FnOnce(&mut Command)>(mut self, f: F) -> Self {
         f(&mut self.0.configure);
         self
     }
@@ -491,6 +2405,28 @@ impl<'a> ConfigurePhase<'a> {
         self
     }
 
+    /// Sets a callback for each line that `configure` writes to `stdout`.
+    ///
+    /// This captures both `stdout` and `stderr`, overriding their handles.
+    #[inline]
+    pub fn on_stdout_line<F>(mut self, f: F) -> Self
+        where F: FnMut(&str) + 'a
+    {
+        self.0.configure_lines.stdout = Some(Box::new(f));
+        self
+    }
+
+    /// Sets a callback for each line that `configure` writes to `stderr`.
+    ///
+    /// This captures both `stdout` and `stderr`, overriding their handles.
+    #[inline]
+    pub fn on_stderr_line<F>(mut self, f: F) -> Self
+        where F: FnMut(&str) + 'a
+    {
+        self.0.configure_lines.stderr = Some(Box::new(f));
+        self
+    }
+
     /// Adjust what happens when running `make`.
     #[inline]
     pub fn make(self) -> MakePhase<'a> {
@@ -502,6 +2438,198 @@ impl<'a> ConfigurePhase<'a> {
     pub fn build(self) -> Result<Ruby, RubyBuildError> {
         self.0.build()
     }
+
+    /// Perform the build without expecting Ruby to be installed afterwards.
+    #[inline]
+    pub fn run(self) -> Result<(), RubyBuildError> {
+        self.0.run()
+    }
+
+    /// Perform the build and report how long each phase took.
+    #[inline]
+    pub fn build_with_report(
+        self,
+    ) -> (Result<Ruby, RubyBuildError>, BuildReport) {
+        self.0.build_with_report()
+    }
+
+    /// Start the build in the background.
+    #[inline]
+    pub fn build_spawned(self) -> Result<BuildHandle<'a>, RubyBuildError> {
+        self.0.build_spawned()
+    }
+
+    /// Returns the commands that would run, without running anything.
+    #[inline]
+    pub fn dry_run(&self) -> BuildPlan {
+        self.0.dry_run()
+    }
+}
+
+// Returns a command that runs the `configure` script at `path`
+fn configure_command(path: &Path, target_msvc: bool) -> Command {
+    if cfg!(target_os = "windows") && !target_msvc {
+        // HACK: Spawn `configure` via `sh` since `Command::new` requires a
+        // Win32 application to work
+        let mut sh = Command::new("sh.exe");
+        sh.arg(path);
+        sh
+    } else {
+        Command::new(path)
+    }
+}
+
+// Returns the `--enable`, `--disable`, `--with`, and `--without` flags within
+// `args` that aren't listed in `help`, the output of `configure --help`
+fn unknown_flags<'a, I>(help: &str, args: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a OsStr>,
+{
+    use std::collections::HashSet;
+
+    // Maps `--disable-x` to `--enable-x` and `--without-x` to `--with-x`
+    fn normalize(flag: &str) -> Option<String> {
+        let name = flag.split(['=', '[']).next()?;
+        if let Some(feature) = name.strip_prefix("--enable-") {
+            Some(format!("--enable-{}", feature))
+        } else if let Some(feature) = name.strip_prefix("--disable-") {
+            Some(format!("--enable-{}", feature))
+        } else if let Some(package) = name.strip_prefix("--without-") {
+            Some(format!("--with-{}", package))
+        } else if name.starts_with("--with-") {
+            Some(name.to_owned())
+        } else {
+            None
+        }
+    }
+
+    let known: HashSet<String> = help
+        .split_whitespace()
+        .map(|word| word.trim_end_matches(','))
+        .filter_map(normalize)
+        .collect();
+
+    args.into_iter()
+        .filter_map(|arg| arg.to_str())
+        .filter(|arg| {
+            let name = arg.split('=').next().unwrap_or(arg);
+            // Passed through to the `extconf.rb` of extensions
+            let is_ext_dir = name.starts_with("--with")
+                && ["-dir", "-include", "-lib"]
+                    .iter()
+                    .any(|suffix| name.ends_with(suffix));
+            !is_ext_dir
+        })
+        .filter(|arg| {
+            normalize(arg).is_some_and(|flag| !known.contains(&flag))
+        })
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+// Whether `dir` is the prefix of an OpenSSL installation
+fn is_openssl_dir(dir: &Path) -> bool {
+    dir.join("include").join("openssl").join("ssl.h").is_file()
+}
+
+// Runs `cmd` and returns its trimmed output as a path if it succeeds
+fn probe_dir(cmd: &mut Command) -> Option<PathBuf> {
+    let output = cmd.stderr(Stdio::null()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let dir = stdout.trim();
+    if dir.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(dir))
+    }
+}
+
+fn find_openssl_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("OPENSSL_DIR") {
+        return Some(dir.into());
+    }
+
+    let brew = ["openssl@3", "openssl@1.1", "openssl"].iter().filter_map(|f| {
+        probe_dir(Command::new("brew").args(["--prefix", f]))
+    });
+    let pkg_config = probe_dir(
+        Command::new("pkg-config").args(["--variable=prefix", "openssl"]),
+    );
+    let common = [
+        "/opt/homebrew/opt/openssl@3",
+        "/usr/local/opt/openssl@3",
+        "/opt/local",
+        "/usr/local/ssl",
+        "/usr/local",
+        "/usr",
+    ].iter().map(PathBuf::from);
+
+    brew.chain(pkg_config)
+        .chain(common)
+        .find(|dir| is_openssl_dir(dir))
+}
+
+/// A set of `configure` flags suited for a common use case.
+///
+/// The exact flags of each preset are returned by [`args`](#method.args).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Preset {
+    /// An optimized shared library without documentation.
+    Production,
+    /// An unoptimized shared library with full debug info.
+    Development,
+    /// An optimized static library with as few dependencies as possible.
+    Minimal,
+    /// A static library built with `musl-gcc` for linking into fully static
+    /// `*-linux-musl` binaries.
+    ///
+    /// Pair with [`LinkOptions::musl`](../../struct.LinkOptions.html#method.musl).
+    Musl,
+}
+
+impl Preset {
+    /// Returns the flags passed into `configure`.
+    pub fn args(self) -> &'static [&'static str] {
+        match self {
+            Preset::Production => &[
+                "--enable-shared",
+                "--disable-install-doc",
+                "optflags=-O3",
+            ],
+            Preset::Development => &[
+                "--enable-shared",
+                "--disable-install-doc",
+                "optflags=-O0",
+                "debugflags=-g3",
+            ],
+            Preset::Minimal => &[
+                "--disable-shared",
+                "--enable-install-static-library",
+                "--disable-install-doc",
+                "--disable-install-rdoc",
+                "--with-static-linked-ext",
+                "--without-gmp",
+                "optflags=-O2",
+            ],
+            Preset::Musl => &[
+                "CC=musl-gcc",
+                // Rust's musl targets produce static position-independent
+                // executables by default
+                "CFLAGS=-fPIC",
+                "LDFLAGS=-static",
+                "--disable-shared",
+                "--enable-install-static-library",
+                "--disable-install-doc",
+                "--with-static-linked-ext",
+                "--disable-dln",
+                "--without-gmp",
+                "optflags=-O2",
+            ],
+        }
+    }
 }
 
 /// Adjusts what happens when running `make install`.
@@ -509,7 +2637,7 @@ impl<'a> ConfigurePhase<'a> {
 /// **Note:** On the MSVC target platform, `nmake` is used instead of `make`.
 pub struct MakePhase<'a>(RubyBuilder<'a>);
 
-impl MakePhase<'_> {
+impl<'a> MakePhase<'a> {
     /// Force `make install` to run.
     #[inline]
     pub fn force(mut self) -> Self {
@@ -517,9 +2645,60 @@ impl MakePhase<'_> {
         self
     }
 
+    /// Run `make` with `n` parallel jobs.
+    ///
+    /// The default is [`jobs_auto`](#method.jobs_auto). This has no effect
+    /// with `nmake`.
+    #[inline]
+    pub fn jobs(mut self, n: usize) -> Self {
+        self.0.make_jobs = Some(n.max(1));
+        self
+    }
+
+    /// Run `make` with as many parallel jobs as there are logical CPUs, or as
+    /// many as Cargo allows via `NUM_JOBS` when in a build script.
+    ///
+    /// This is the default.
+    #[inline]
+    pub fn jobs_auto(mut self) -> Self {
+        self.0.make_jobs = None;
+        self
+    }
+
+    /// Selects how Ruby is installed.
+    ///
+    /// The default is [`InstallTarget::Install`](enum.InstallTarget.html).
+    #[inline]
+    pub fn install_target(self, target: InstallTarget) -> Self {
+        self.target(target.name())
+    }
+
+    /// Run `make target` instead of `make install`.
+    ///
+    /// If `target` doesn't install Ruby into the output directory, use
+    /// [`run`](struct.RubyBuilder.html#method.run) instead of `build`.
+    #[inline]
+    pub fn target(self, target: impl Into<OsString>) -> Self {
+        self.targets(Some(target))
+    }
+
+    /// Run `make` with `targets` instead of `install`.
+    ///
+    /// If `targets` don't install Ruby into the output directory, use
+    /// [`run`](struct.RubyBuilder.html#method.run) instead of `build`.
+    #[inline]
+    pub fn targets<I>(mut self, targets: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString>,
+    {
+        self.0.make_targets = targets.into_iter().map(Into::into).collect();
+        self
+    }
+
     /// Perform custom operations on the `Command` instance used.
     #[inline]
-    pub fn with_command<F: FnOnce(&mut Command) -> ()>(mut self, f: F) -> Self {
+    pub fn with_command<F: FnOnce(&mut Command)>(mut self, f: F) -> Self {
         f(&mut self.0.make);
         self
     }
@@ -579,17 +2758,108 @@ impl MakePhase<'_> {
         self
     }
 
+    /// Sets a callback for each line that `make install` writes to `stdout`.
+    ///
+    /// This captures both `stdout` and `stderr`, overriding their handles.
+    #[inline]
+    pub fn on_stdout_line<F>(mut self, f: F) -> Self
+        where F: FnMut(&str) + 'a
+    {
+        self.0.make_lines.stdout = Some(Box::new(f));
+        self
+    }
+
+    /// Sets a callback for each line that `make install` writes to `stderr`.
+    ///
+    /// This captures both `stdout` and `stderr`, overriding their handles.
+    #[inline]
+    pub fn on_stderr_line<F>(mut self, f: F) -> Self
+        where F: FnMut(&str) + 'a
+    {
+        self.0.make_lines.stderr = Some(Box::new(f));
+        self
+    }
+
     /// Perform the build.
     #[inline]
     pub fn build(self) -> Result<Ruby, RubyBuildError> {
         self.0.build()
     }
+
+    /// Perform the build without expecting Ruby to be installed afterwards.
+    #[inline]
+    pub fn run(self) -> Result<(), RubyBuildError> {
+        self.0.run()
+    }
+
+    /// Perform the build and report how long each phase took.
+    #[inline]
+    pub fn build_with_report(
+        self,
+    ) -> (Result<Ruby, RubyBuildError>, BuildReport) {
+        self.0.build_with_report()
+    }
+
+    /// Start the build in the background.
+    #[inline]
+    pub fn build_spawned(self) -> Result<BuildHandle<'a>, RubyBuildError> {
+        self.0.build_spawned()
+    }
+
+    /// Returns the commands that would run, without running anything.
+    #[inline]
+    pub fn dry_run(&self) -> BuildPlan {
+        self.0.dry_run()
+    }
+}
+
+/// The `make` target that installs Ruby, as passed into
+/// [`MakePhase::install_target`](struct.MakePhase.html#method.install_target).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InstallTarget {
+    /// `make install`, which installs Ruby along with its ri and rdoc
+    /// documentation unless disabled via `configure`.
+    Install,
+    /// `make install-nodoc`, which skips generating documentation and saves a
+    /// lot of time.
+    InstallNodoc,
+    /// `make install-all`, which installs Ruby along with all documentation,
+    /// including the C API.
+    InstallAll,
+}
+
+impl Default for InstallTarget {
+    #[inline]
+    fn default() -> Self {
+        InstallTarget::Install
+    }
+}
+
+impl InstallTarget {
+    /// Returns the name of the `make` target.
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            InstallTarget::Install => "install",
+            InstallTarget::InstallNodoc => "install-nodoc",
+            InstallTarget::InstallAll => "install-all",
+        }
+    }
 }
 
 /// The error returned when
 /// [`RubyBuilder::build`](struct.RubyBuilder.html#method.build) fails.
 #[derive(Debug)]
 pub enum RubyBuildError {
+    /// Failed to lock the sources or build directory.
+    Lock(io::Error),
+    /// Failed to create a log file for a phase.
+    CreateLog(io::Error),
+    /// Failed to create the directory passed into
+    /// [`RubyBuilder::build_dir`](struct.RubyBuilder.html#method.build_dir),
+    /// or the output directory for
+    /// [`RubyBuilder::in_container`](struct.RubyBuilder.html#method.in_container).
+    CreateBuildDir(io::Error),
     /// Failed to spawn a process for `autoconf`.
     AutoconfSpawnFail(io::Error),
     /// `autoconf` exited unsuccessfully.
@@ -598,17 +2868,665 @@ pub enum RubyBuildError {
     ConfigureSpawnFail(io::Error),
     /// `configure` exited unsuccessfully.
     ConfigureFail(Output),
+    /// `configure` doesn't recognize these flags, which was checked because of
+    /// [`ConfigurePhase::validate_flags`](struct.ConfigurePhase.html#method.validate_flags).
+    UnknownFlags(Vec<String>),
+    /// Failed to write the file marking a phase as completed, which includes
+    /// the fingerprint of the `configure` arguments, or the file marking the
+    /// installation as managed by `aloxide`.
+    WriteMarker(io::Error),
     /// Failed to spawn a process for `make`.
     MakeSpawnFail(io::Error),
     /// `make` exited unsuccessfully.
     MakeFail(Output),
+    /// Failed to merge the architectures of
+    /// [`RubyBuilder::universal_macos`](struct.RubyBuilder.html#method.universal_macos).
+    MergeSlices(io::Error),
+    /// Failed to find or spawn a process for `strip`.
+    Strip(io::Error),
+    /// `strip` exited unsuccessfully.
+    StripFail(Output),
+    /// Failed to build a `baseruby` for
+    /// [`RubyBuilder::cross`](struct.RubyBuilder.html#method.cross).
+    BaseRuby(Box<RubyBuildError>),
+    /// A `baseruby` must be built, but the build machine is unknown.
+    UnknownBuildTriple,
+    /// The installed `rbconfig.rb` of a cross-compiled Ruby was not found.
+    NoRbConfig,
+    /// Failed to download the sources for
+    /// [`MultiBuilder`](../multi/struct.MultiBuilder.html).
+    #[cfg(feature = "download")]
+    Download(Box<super::RubySrcDownloadError>),
+    /// The build was canceled via
+    /// [`BuildHandle::kill`](struct.BuildHandle.html#method.kill).
+    Killed,
     /// Failed to get the version for `ruby`.
     Version(RubyVersionError),
 }
 
+impl std::error::Error for RubyBuildError {}
+
+impl Display for RubyBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use RubyBuildError::*;
+        match self {
+            Lock(error) => {
+                write!(f, "Failed to lock the build directory: {}", error)
+            },
+            CreateLog(error) => {
+                write!(f, "Failed to create a log file: {}", error)
+            },
+            CreateBuildDir(error) => {
+                write!(f, "Failed to create the build directory: {}", error)
+            },
+            AutoconfSpawnFail(error) => {
+                write!(f, "Failed to spawn `autoconf`: {}", error)
+            },
+            AutoconfFail(output) => write_fail(f, Phase::Autoconf, output),
+            ConfigureSpawnFail(error) => {
+                write!(f, "Failed to spawn `configure`: {}", error)
+            },
+            ConfigureFail(output) => write_fail(f, Phase::Configure, output),
+            UnknownFlags(flags) => {
+                write!(f, "Unknown `configure` flags: {}", flags.join(", "))
+            },
+            WriteMarker(error) => {
+                write!(f, "Failed to mark a phase as completed: {}", error)
+            },
+            MakeSpawnFail(error) => {
+                write!(f, "Failed to spawn `make`: {}", error)
+            },
+            MakeFail(output) => write_fail(f, Phase::Make, output),
+            MergeSlices(error) => {
+                write!(f, "Failed to merge universal binaries: {}", error)
+            },
+            Strip(error) => write!(f, "Failed to strip binaries: {}", error),
+            StripFail(output) => write_fail(f, "strip", output),
+            BaseRuby(error) => {
+                write!(f, "Failed to build `baseruby`: {}", error)
+            },
+            UnknownBuildTriple => {
+                write!(f, "Cannot build `baseruby` for an unknown machine")
+            },
+            NoRbConfig => write!(f, "Failed to find the installed rbconfig.rb"),
+            Killed => write!(f, "The build was killed"),
+            #[cfg(feature = "download")]
+            Download(error) => {
+                write!(f, "Failed to download the sources: {:?}", error)
+            },
+            Version(error) => {
+                write!(f, "Failed to get the version of `ruby`: {:?}", error)
+            },
+        }
+    }
+}
+
+// The number of trailing lines of output to include in error messages
+const TAIL_LINES: usize = 50;
+
+// Writes the exit status of `program` and the tail of each output stream
+fn write_fail(
+    f: &mut fmt::Formatter,
+    program: impl Display,
+    output: &Output,
+) -> fmt::Result {
+    write!(f, "`{}` exited unsuccessfully ({})", program, output.status)?;
+
+    let streams = [("stdout", &output.stdout), ("stderr", &output.stderr)];
+    for (name, bytes) in &streams {
+        let text = String::from_utf8_lossy(bytes);
+        let lines: Vec<&str> = text.lines().collect();
+        if lines.is_empty() {
+            continue;
+        }
+
+        let start = lines.len().saturating_sub(TAIL_LINES);
+        let count = lines.len() - start;
+        write!(f, "\n\n--- last {} lines of {} ---", count, name)?;
+        for line in &lines[start..] {
+            write!(f, "\n{}", line)?;
+        }
+    }
+    Ok(())
+}
+
 impl From<RubyVersionError> for RubyBuildError {
     #[inline]
     fn from(error: RubyVersionError) -> Self {
         RubyBuildError::Version(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_changes() {
+        let step = |cmd: &Command| {
+            BuildStep::new(Phase::Configure, cmd, Path::new("."))
+        };
+
+        let mut a = Command::new("configure");
+        a.arg("--enable-shared");
+
+        let mut b = Command::new("configure");
+        b.arg("--enable-shared");
+        assert_eq!(fingerprint(&step(&a)), fingerprint(&step(&b)));
+
+        b.env("CC", "clang");
+        assert_ne!(fingerprint(&step(&a)), fingerprint(&step(&b)));
+
+        // Arguments must not run together
+        let mut c = Command::new("configure");
+        c.args(["--enable-", "shared"]);
+        assert_ne!(fingerprint(&step(&a)), fingerprint(&step(&c)));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn output_lines_streams() {
+        use std::cell::RefCell;
+
+        let stdout = RefCell::new(Vec::new());
+        let stderr = RefCell::new(Vec::new());
+        let mut handlers = LineHandlers {
+            stdout: Some(Box::new(|line| {
+                stdout.borrow_mut().push(line.to_owned())
+            })),
+            stderr: Some(Box::new(|line| {
+                stderr.borrow_mut().push(line.to_owned())
+            })),
+        };
+
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo a; echo b >&2; printf c"]);
+        let output = output_lines(&mut cmd, &mut handlers, None).unwrap();
+        drop(handlers);
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"a\nc");
+        assert_eq!(output.stderr, b"b\n");
+        assert_eq!(stdout.into_inner(), ["a", "c"]);
+        assert_eq!(stderr.into_inner(), ["b"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn fail_display_tail() {
+        let output = Command::new("sh")
+            .args(["-c", "seq 1 100; echo oops >&2; exit 2"])
+            .output()
+            .unwrap();
+
+        let message = RubyBuildError::MakeFail(output).to_string();
+        assert!(message.starts_with("`make` exited unsuccessfully"));
+        assert!(message.contains("last 50 lines of stdout ---\n51\n"));
+        assert!(!message.contains("\n50\n"));
+        assert!(message.ends_with("last 1 lines of stderr ---\noops"));
+    }
+
+    #[test]
+    fn unknown_configure_flags() {
+        let help = "
+  --disable-option-checking  ignore unrecognized --enable/--with options
+  --enable-shared         build a shared library for Ruby
+  --disable-install-doc   do not install either rdoc indexes or C API docs
+  --with-ext=EXTS         pass to --with-ext option of extmk.rb
+  --without-gmp           disable GNU GMP to accelerate Bignum operations
+";
+        let args = [
+            "--prefix=/usr",
+            "--disable-shared",
+            "--disable-install-docs",
+            "--with-ext=zlib",
+            "--with-gmp",
+            "--with-openssl-dir=/usr/local",
+            "--without-tk",
+            "optflags=-O3",
+        ];
+        let unknown = unknown_flags(help, args.iter().map(OsStr::new));
+        assert_eq!(unknown, ["--disable-install-docs", "--without-tk"]);
+    }
+
+    #[test]
+    fn parse_rbconfig() {
+        let rbconfig = r#"
+  CONFIG = {}
+  CONFIG["DESTDIR"] = DESTDIR
+  CONFIG["MAJOR"] = "2"
+  CONFIG["MINOR"] = "7"
+  CONFIG["TEENY"] = "1"
+  CONFIG["PATCHLEVEL"] = "83"
+"#;
+        let parse = |rbconfig| {
+            let prefix = Path::new("/usr/local");
+            rbconfig_version(&RbConfig::parse_rb(rbconfig, prefix, prefix))
+        };
+        assert_eq!(parse(rbconfig).unwrap(), crate::Version::new(2, 7, 1));
+        assert_eq!(parse("CONFIG = {}"), None);
+    }
+
+    #[test]
+    fn cross_args() {
+        let src = RubySrc::new("aloxide-missing-src");
+        let baseruby = Path::new("/usr/bin/ruby");
+        let cross = CrossBuild::new()
+            .build_triple("x86_64-unknown-linux-gnu")
+            .baseruby_path(baseruby)
+            .tool("CC", "aarch64-linux-gnu-gcc");
+        let builder = src.builder("out", "aarch64-unknown-linux-gnu")
+            .cross(cross);
+
+        let plan = builder.dry_run();
+        let configure = plan.step(Phase::Configure).unwrap();
+        for arg in &[
+            "--host=aarch64-unknown-linux-gnu",
+            "--build=x86_64-unknown-linux-gnu",
+            "--with-baseruby=/usr/bin/ruby",
+        ] {
+            assert!(configure.args.iter().any(|a| a == arg), "{}", arg);
+        }
+
+        let make = plan.step(Phase::Make).unwrap();
+        let cc = (OsString::from("CC"), Some("aarch64-linux-gnu-gcc".into()));
+        assert!(make.envs.contains(&cc));
+    }
+
+    #[test]
+    fn cross_into_ruby_links() {
+        let dir = std::env::temp_dir().join("aloxide-cross-into-ruby");
+        let _ = fs::remove_dir_all(&dir);
+
+        // There's no `ruby` to run, as if it were built for another machine
+        let arch_dir = dir.join("lib/ruby/3.2.0/aarch64-linux");
+        fs::create_dir_all(&arch_dir).unwrap();
+        fs::write(arch_dir.join("rbconfig.rb"), r#"
+  CONFIG["MAJOR"] = "3"
+  CONFIG["MINOR"] = "2"
+  CONFIG["TEENY"] = "2"
+  CONFIG["prefix"] = (TOPDIR || DESTDIR + "/usr/local")
+  CONFIG["exec_prefix"] = "$(prefix)"
+  CONFIG["libdir"] = "$(exec_prefix)/lib"
+  CONFIG["target"] = "aarch64-unknown-linux-gnu"
+  CONFIG["RUBY_SO_NAME"] = "ruby"
+  CONFIG["LIBRUBY_A"] = "lib$(RUBY_SO_NAME)-static.a"
+  CONFIG["LIBRUBYARG_STATIC"] = "-L$(libdir) -l$(RUBY_SO_NAME)-static"
+  CONFIG["MAINLIBS"] = "-lz -lm"
+"#).unwrap();
+        fs::write(dir.join("lib/libruby-static.a"), "").unwrap();
+
+        let src = RubySrc::new("aloxide-missing-src");
+        let cross = CrossBuild::new().baseruby_path("/usr/bin/ruby");
+        let ruby = src.builder(&dir, "aarch64-unknown-linux-gnu")
+            .cross(cross)
+            .into_ruby()
+            .unwrap();
+        assert_eq!(*ruby.version(), crate::Version::new(3, 2, 2));
+
+        let mut out = Vec::<String>::new();
+        let options = crate::LinkOptions::new().static_lib(true);
+        ruby.link_to(&options, &mut out).unwrap();
+        for directive in &[
+            "cargo:rustc-link-lib=static=ruby-static",
+            "cargo:rustc-link-lib=dylib=z",
+            "cargo:rustc-link-lib=dylib=m",
+        ] {
+            assert!(out.iter().any(|d| d == directive), "{}", directive);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn native_binary() {
+        let dir = std::env::temp_dir().join("aloxide-native-binary");
+        fs::create_dir_all(&dir).unwrap();
+
+        let cases: &[(&str, &[u8], bool)] = &[
+            ("elf", b"\x7FELF\x02\x01", true),
+            ("macho", b"\xCF\xFA\xED\xFE\x07", true),
+            ("script", b"#!/bin/sh\n", false),
+            ("empty", b"", false),
+        ];
+        for &(name, data, expected) in cases {
+            let path = dir.join(name);
+            fs::write(&path, data).unwrap();
+            assert_eq!(is_native_binary(&path).unwrap(), expected, "{}", name);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn merge_slices_copies() {
+        let dir = std::env::temp_dir().join("aloxide-merge-slices");
+        let _ = fs::remove_dir_all(&dir);
+
+        let slices = [dir.join("arm64"), dir.join("x86_64")];
+        for slice in &slices {
+            fs::create_dir_all(slice.join("lib")).unwrap();
+            let name = slice.file_name().unwrap().to_str().unwrap();
+            fs::write(slice.join("lib/libruby.3.0.dylib"), name).unwrap();
+            std::os::unix::fs::symlink(
+                "libruby.3.0.dylib",
+                slice.join("lib/libruby.dylib"),
+            ).unwrap();
+        }
+
+        let out_dir = dir.join("out");
+        merge_slices(&slices, &out_dir).unwrap();
+
+        let lib = out_dir.join("lib/libruby.3.0.dylib");
+        assert_eq!(fs::read_to_string(&lib).unwrap(), "arm64");
+
+        let link = fs::read_link(out_dir.join("lib/libruby.dylib")).unwrap();
+        assert_eq!(link, Path::new("libruby.3.0.dylib"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn musl_args() {
+        let src = RubySrc::new("aloxide-missing-src");
+        let configure_args = |target: &str| {
+            let plan = src.builder("out", target).musl().dry_run();
+            plan.step(Phase::Configure).unwrap().args.clone()
+        };
+
+        let args = configure_args("x86_64-unknown-linux-musl");
+        for arg in &["CC=musl-gcc", "LDFLAGS=-static", "--disable-shared"] {
+            assert!(args.iter().any(|a| a == arg), "{}", arg);
+        }
+
+        let args = configure_args("x86_64-unknown-linux-gnu");
+        assert!(!args.iter().any(|a| a == "CC=musl-gcc"));
+    }
+
+    #[test]
+    fn dry_run() {
+        let src = RubySrc::new("aloxide-missing-src");
+        let builder = src.builder("out", "x86_64-unknown-linux-gnu")
+            .build_dir("aloxide-missing-build")
+            .configure()
+            .enable("shared")
+            .envs(vec![("CC", "my cc")])
+            .make()
+            .jobs(3);
+
+        let plan = builder.dry_run();
+        let phases: Vec<Phase> = plan.steps.iter().map(|s| s.phase).collect();
+        assert_eq!(phases, [Phase::Autoconf, Phase::Configure, Phase::Make]);
+
+        let configure = plan.step(Phase::Configure).unwrap();
+        assert_eq!(configure.dir, Path::new("aloxide-missing-build"));
+        assert!(configure.args.iter().any(|arg| arg == "--enable-shared"));
+        assert!(configure.to_string().contains(" CC='my cc' "));
+
+        let make = plan.step(Phase::Make).unwrap();
+        assert_eq!(make.args[make.args.len() - 2..], ["-j3", "install"]);
+    }
+
+    #[test]
+    fn dry_run_stale_markers() {
+        let dir = std::env::temp_dir().join("aloxide-dry-run-stale-markers");
+        let _ = fs::remove_dir_all(&dir);
+        let src_dir = dir.join("src");
+        let out_dir = dir.join("out");
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::create_dir_all(out_dir.join("bin")).unwrap();
+        fs::write(src_dir.join("configure"), "").unwrap();
+        fs::write(src_dir.join("Makefile"), "").unwrap();
+        fs::write(out_dir.join("bin").join("ruby"), "").unwrap();
+
+        let src = RubySrc::new(&src_dir);
+        let builder = || src.builder(&out_dir, "x86_64-unknown-linux-gnu");
+        let phases = |plan: BuildPlan| -> Vec<Phase> {
+            plan.steps.iter().map(|step| step.phase).collect()
+        };
+
+        // Mark each phase as completed
+        let plan = builder().configure().force().dry_run();
+        let configure = plan.step(Phase::Configure).unwrap();
+        let fingerprint_path = builder().marker_path(Phase::Configure);
+        fs::write(fingerprint_path, fingerprint(configure)).unwrap();
+        fs::write(builder().marker_path(Phase::Make), "").unwrap();
+        assert!(phases(builder().dry_run()).is_empty());
+
+        // Markers are kept out of the sources
+        assert!(!src_dir.join(FINGERPRINT_FILE).exists());
+        assert!(out_dir.join(FINGERPRINT_FILE).exists());
+
+        // Different arguments make the configuration stale
+        let plan = builder().configure().enable("shared").dry_run();
+        assert_eq!(phases(plan), [Phase::Configure, Phase::Make]);
+
+        // As does configuring the same sources for another `out_dir`
+        let config_status = fs::File::create(src_dir.join("config.status"))
+            .unwrap();
+        let later = std::time::SystemTime::now()
+            + std::time::Duration::from_secs(60);
+        config_status.set_modified(later).unwrap();
+        let plan = builder().dry_run();
+        assert_eq!(phases(plan), [Phase::Configure, Phase::Make]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dry_run_missing_markers() {
+        let dir = std::env::temp_dir().join("aloxide-dry-run-missing-markers");
+        let _ = fs::remove_dir_all(&dir);
+        let src_dir = dir.join("src");
+        let out_dir = dir.join("out");
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::create_dir_all(out_dir.join("bin")).unwrap();
+        fs::write(src_dir.join("configure"), "").unwrap();
+        fs::write(src_dir.join("Makefile"), "").unwrap();
+        fs::write(out_dir.join("bin").join("ruby"), "").unwrap();
+
+        let src = RubySrc::new(&src_dir);
+        let builder = || src.builder(&out_dir, "x86_64-unknown-linux-gnu");
+        let phases = || -> Vec<Phase> {
+            builder().dry_run().steps.iter().map(|step| step.phase).collect()
+        };
+
+        // A tree built without markers isn't rebuilt
+        assert!(phases().is_empty());
+
+        // Unlike one where a phase didn't finish
+        let make_marker = builder().marker_path(Phase::Make);
+        fs::write(&make_marker, PENDING_MARKER).unwrap();
+        assert_eq!(phases(), [Phase::Make]);
+
+        let fingerprint_path = builder().marker_path(Phase::Configure);
+        fs::write(fingerprint_path, PENDING_MARKER).unwrap();
+        assert_eq!(phases(), [Phase::Configure, Phase::Make]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dry_run_optimized() {
+        let src = RubySrc::new("aloxide-missing-src");
+        let builder = src.builder("out", "x86_64-unknown-linux-gnu")
+            .configure()
+            .preset(Preset::Musl)
+            .optimized();
+        assert!(builder.0.strip);
+
+        let plan = builder.dry_run();
+        let configure = plan.step(Phase::Configure).unwrap();
+        let ldflags = configure.args.iter()
+            .rfind(|arg| arg.to_str().unwrap().starts_with("LDFLAGS="))
+            .unwrap();
+        assert_eq!(ldflags, "LDFLAGS=-static -flto");
+    }
+
+    #[test]
+    fn dry_run_relocatable() {
+        let src = RubySrc::new("aloxide-missing-src");
+        let plan = src.builder("out", "x86_64-unknown-linux-gnu")
+            .configure()
+            .relocatable()
+            .dry_run();
+
+        let configure = plan.step(Phase::Configure).unwrap();
+        assert_eq!(configure.args[2..], ["--enable-load-relative"]);
+    }
+
+    #[test]
+    fn dry_run_clean_env() {
+        let src = RubySrc::new("aloxide-missing-src");
+        let plan = src.builder("out", "x86_64-unknown-linux-gnu")
+            .clean_env()
+            .configure()
+            .envs(vec![("CC", "cc")])
+            .remove_envs(vec!["CFLAGS"])
+            .dry_run();
+
+        let configure = plan.step(Phase::Configure).unwrap();
+        assert!(configure.clear_env);
+
+        let keys: Vec<&OsStr> = configure.envs.iter()
+            .map(|(key, _)| key.as_os_str())
+            .collect();
+        assert!(keys.contains(&OsStr::new("CC")));
+        assert!(!keys.contains(&OsStr::new("CFLAGS")));
+        assert!(keys.iter().all(|key| {
+            *key == "CC" || ENV_WHITELIST.iter().any(|k| key == k)
+        }));
+        assert!(configure.to_string().contains(" env -i "));
+    }
+
+    #[test]
+    fn dry_run_container() {
+        let src = RubySrc::new("aloxide-missing-src");
+        let plan = src.builder("out", "x86_64-unknown-linux-gnu")
+            .in_container("ruby-build:latest")
+            .container_runtime("podman")
+            .configure()
+            .envs(vec![("CC", "cc")])
+            .dry_run();
+
+        let configure = plan.step(Phase::Configure).unwrap();
+        assert_eq!(configure.program, "podman");
+        assert!(configure.envs.is_empty());
+
+        let args: Vec<_> = configure.args.iter()
+            .map(|arg| arg.to_str().unwrap())
+            .collect();
+        assert_eq!(args[..2], ["run", "--rm"]);
+        assert!(args.windows(2).any(|w| w == ["-e", "CC=cc"]));
+
+        let image = args.iter()
+            .position(|&arg| arg == "ruby-build:latest")
+            .unwrap();
+        assert!(args[image + 1].ends_with("configure"));
+        assert!(args[image + 2..].iter().any(|a| a.starts_with("--prefix=")));
+    }
+
+    #[test]
+    fn dry_run_reproducible() {
+        let src = RubySrc::new("aloxide-missing-src");
+        let plan = src.builder("out", "x86_64-unknown-linux-gnu")
+            .build_dir("aloxide-missing-build")
+            .clean_env()
+            .reproducible()
+            .configure()
+            .envs(vec![("CPPFLAGS", "-DFOO"), ("TZ", "EST")])
+            .dry_run();
+
+        let env = |phase: Phase, key: &str| {
+            plan.step(phase).unwrap().envs.iter()
+                .find(|(k, _)| k == key)
+                .and_then(|(_, val)| val.clone())
+                .map(|val| val.into_string().unwrap())
+        };
+
+        for step in &plan.steps {
+            assert_eq!(env(step.phase, "LC_ALL").unwrap(), "C");
+            assert!(env(step.phase, "SOURCE_DATE_EPOCH").is_some());
+        }
+        assert_eq!(env(Phase::Configure, "TZ").unwrap(), "EST");
+        assert_eq!(env(Phase::Make, "TZ").unwrap(), "UTC");
+
+        let flags = env(Phase::Configure, "CPPFLAGS").unwrap();
+        assert!(flags.starts_with("-DFOO -ffile-prefix-map="));
+        assert!(flags.contains("aloxide-missing-src=."));
+        assert!(flags.ends_with("aloxide-missing-build=."));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn build_spawned_kill() {
+        let dir = std::env::temp_dir().join("aloxide-build-spawned-kill");
+        fs::create_dir_all(&dir).unwrap();
+
+        let src = RubySrc::new(&dir);
+        let target = "x86_64-unknown-linux-gnu";
+        let mut handle = src.builder(dir.join("out"), target)
+            .autoconf()
+            .force()
+            .with_command(|cmd| {
+                *cmd = Command::new("sleep");
+                cmd.arg("10");
+            })
+            .build_spawned()
+            .unwrap();
+
+        assert!(!handle.try_wait());
+        assert_eq!(handle.phase(), Some(Phase::Autoconf));
+        handle.kill().unwrap();
+
+        match handle.wait() {
+            Err(RubyBuildError::Killed) => {},
+            other => panic!("unexpected result: {:?}", other),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!dir.with_extension("lock").exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn build_with_report() {
+        let dir = std::env::temp_dir().join("aloxide-build-with-report");
+        fs::create_dir_all(&dir).unwrap();
+
+        let src = RubySrc::new(&dir);
+        let target = "x86_64-unknown-linux-gnu";
+        let (result, report) = src.builder(dir.join("out"), target)
+            .autoconf()
+            .with_command(|cmd| *cmd = Command::new("true"))
+            .configure()
+            .with_command(|cmd| *cmd = Command::new("false"))
+            .build_with_report();
+
+        match result {
+            Err(RubyBuildError::ConfigureFail(_)) => {},
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let phases: Vec<Phase> = report.phases.iter()
+            .map(|phase| phase.phase)
+            .collect();
+        assert_eq!(phases, [Phase::Autoconf, Phase::Configure]);
+        assert!(report.phases.iter().all(|phase| !phase.is_skipped()));
+        assert!(report.to_string().contains("configure: "));
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!dir.with_extension("lock").exists());
+    }
+
+    #[test]
+    fn dry_run_until() {
+        let src = RubySrc::new("aloxide-missing-src");
+        let mut builder = src.builder("out", "x86_64-unknown-linux-gnu");
+        builder.until = Some(Phase::Configure);
+
+        let plan = builder.dry_run();
+        assert!(plan.runs(Phase::Configure));
+        assert!(!plan.runs(Phase::Make));
+    }
+}
@@ -3,32 +3,108 @@
 use std::env;
 use std::fs::{self, File};
 use std::io::{self, Seek, SeekFrom};
+use std::mem;
 use std::path::{Path, PathBuf};
 
-use ureq::Response;
+use ureq::{Proxy, Response};
 
-use crate::{Archive, RubySrc, Version};
+use crate::{util, Archive, Cache, RubySrc, Version};
+use crate::archive::UnpackOptions;
+use super::Snapshot;
+
+// Called with the number of bytes downloaded and the total, if known
+pub(crate) type Progress<'a> = dyn FnMut(u64, Option<u64>) + 'a;
+
+// The default `User-Agent` header sent with requests
+const USER_AGENT: &str = concat!("aloxide/", env!("CARGO_PKG_VERSION"));
+
+// Options for the GET request of an archive
+#[derive(Default)]
+pub(crate) struct RequestOptions<'a> {
+    pub proxy: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub headers: Vec<(&'a str, &'a str)>,
+}
+
+// The sources being downloaded
+#[derive(Clone, Copy)]
+enum Source<'a> {
+    Release(&'a Version),
+    Snapshot(&'a Snapshot),
+}
+
+impl Source<'_> {
+    // The name of the directory that the archive unpacks to
+    fn src_name(self) -> String {
+        match self {
+            Source::Release(version) => format!("ruby-{}", version.release()),
+            Source::Snapshot(snapshot) => snapshot.name().to_owned(),
+        }
+    }
+
+    fn archive_name(self) -> String {
+        match self {
+            Source::Release(version) => version.archive_name(),
+            Source::Snapshot(snapshot) => snapshot.archive_name(),
+        }
+    }
+
+    fn url(self) -> String {
+        match self {
+            Source::Release(version) => version.url(),
+            Source::Snapshot(snapshot) => snapshot.url(),
+        }
+    }
+
+    // Whether the archive at `url` is replaced over time
+    fn is_moving(self) -> bool {
+        match self {
+            Source::Release(_) => false,
+            Source::Snapshot(snapshot) => snapshot.is_moving(),
+        }
+    }
+}
 
 /// Downloads and unpacks Ruby's source code.
 pub struct RubySrcDownloader<'a> {
-    version: &'a Version,
+    source: Source<'a>,
     dst_dir: &'a Path,
     ignore_existing_dir: bool,
     ignore_cache: bool,
     cache: bool,
     cache_dir: Option<&'a Path>,
+    skip_unpack: bool,
+    max_cache_size: Option<u64>,
+    request: RequestOptions<'a>,
+    progress: Option<Box<Progress<'a>>>,
+    unpack: UnpackOptions<'a>,
 }
 
 impl<'a> RubySrcDownloader<'a> {
     #[inline]
     pub(crate) fn new(version: &'a Version, dst_dir: &'a Path) -> Self {
+        Self::with_source(Source::Release(version), dst_dir)
+    }
+
+    #[inline]
+    pub(crate) fn snapshot(snapshot: &'a Snapshot, dst_dir: &'a Path) -> Self {
+        Self::with_source(Source::Snapshot(snapshot), dst_dir)
+    }
+
+    #[inline]
+    fn with_source(source: Source<'a>, dst_dir: &'a Path) -> Self {
         RubySrcDownloader {
-            version,
+            source,
             dst_dir,
             ignore_existing_dir: false,
             ignore_cache: false,
             cache: false,
             cache_dir: None,
+            skip_unpack: false,
+            max_cache_size: None,
+            request: RequestOptions::default(),
+            progress: None,
+            unpack: UnpackOptions::new(),
         }
     }
 
@@ -67,26 +143,123 @@ impl<'a> RubySrcDownloader<'a> {
         self.cache()
     }
 
+    /// Only downloads the archive, without unpacking it.
+    ///
+    /// The archive is verified and kept in the cache directory if caching is
+    /// enabled, or in `dst_dir` otherwise. `download` then returns the path of
+    /// the archive.
+    #[inline]
+    pub fn skip_unpack(mut self) -> Self {
+        self.skip_unpack = true;
+        self
+    }
+
+    /// Sets the maximum size of the cache directory in bytes.
+    ///
+    /// After a successful download, the least recently used archives are
+    /// removed from the cache until it fits within `bytes`. The archive that
+    /// was just used is never removed.
+    #[inline]
+    pub fn max_cache_size(mut self, bytes: u64) -> Self {
+        self.max_cache_size = Some(bytes);
+        self.cache()
+    }
+
+    /// Sets the proxy server to download through.
+    ///
+    /// The format is `<protocol>://<user>:<password>@<host>:<port>`, where all
+    /// parts except `host` are optional. Only `http` proxies are supported.
+    ///
+    /// If not set, the proxy is taken from the `HTTPS_PROXY`, `HTTP_PROXY`,
+    /// and `ALL_PROXY` environment variables, unless the host is excluded by
+    /// `NO_PROXY`.
+    #[inline]
+    pub fn proxy(mut self, url: &'a str) -> Self {
+        self.request.proxy = Some(url);
+        self
+    }
+
+    /// Sets the `User-Agent` header of the request.
+    ///
+    /// The default is "aloxide/" followed by the version of this crate.
+    #[inline]
+    pub fn user_agent(mut self, user_agent: &'a str) -> Self {
+        self.request.user_agent = Some(user_agent);
+        self
+    }
+
+    /// Adds the `name: value` header to the request, such as for
+    /// authentication.
+    #[inline]
+    pub fn header(mut self, name: &'a str, value: &'a str) -> Self {
+        self.request.headers.push((name, value));
+        self
+    }
+
+    /// Sets a callback for reporting download progress.
+    ///
+    /// The callback is passed the number of bytes downloaded so far and the
+    /// total number of bytes, if the server reported a `Content-Length`. It is
+    /// not called when a cached archive is reused.
+    #[inline]
+    pub fn progress<F>(mut self, f: F) -> Self
+        where F: FnMut(u64, Option<u64>) + 'a
+    {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    /// Only unpacks the entries whose paths within the archive `f` returns
+    /// `true` for.
+    ///
+    /// See [`UnpackOptions::filter`](../../archive/struct.UnpackOptions.html#method.filter)
+    /// for details.
+    #[inline]
+    pub fn unpack_filter<F>(mut self, f: F) -> Self
+        where F: FnMut(&Path) -> bool + 'a
+    {
+        self.unpack = self.unpack.filter(f);
+        self
+    }
+
+    /// Sets a callback for reporting unpack progress.
+    ///
+    /// See [`UnpackOptions::progress`](../../archive/struct.UnpackOptions.html#method.progress)
+    /// for details.
+    #[inline]
+    pub fn unpack_progress<F>(mut self, f: F) -> Self
+        where F: FnMut(u64, u64) + 'a
+    {
+        self.unpack = self.unpack.progress(f);
+        self
+    }
+
     /// Downloads and returns the directory containing the Ruby sources.
     ///
     /// If `skip_unpack` is set, the returned path is that of the archive.
-    pub fn download(self) -> Result<Box<RubySrc>, RubySrcDownloadError> {
+    pub fn download(mut self) -> Result<Box<RubySrc>, RubySrcDownloadError> {
         use RubySrcDownloadError::*;
 
-        let archive_name = self.version.archive_name();
-        let archive_ext = ".tar.bz2";
-        let archive_ext_len = archive_ext.len();
-        debug_assert!(archive_name.ends_with(archive_ext));
+        let archive_name = self.source.archive_name();
+        let src_dir = self.dst_dir.join(self.source.src_name());
 
-        // Use substring of `archive_name`
-        let src_name_len = archive_name.len() - archive_ext_len;
-        let src_name = &archive_name[..src_name_len];
-        let src_dir = self.dst_dir.join(src_name);
+        // Moving snapshots are always downloaded and unpacked anew
+        let moving = self.source.is_moving();
 
-        if !self.ignore_existing_dir && src_dir.exists() {
-            // Reuse the existing sources
-            return Ok(src_dir.into());
-        }
+        fs::create_dir_all(self.dst_dir).map_err(UnpackArchive)?;
+
+        let _src_lock = if self.skip_unpack {
+            None
+        } else {
+            // Wait for other processes unpacking the same sources
+            let lock = util::lock(&src_dir).map_err(Lock)?;
+
+            if !self.ignore_existing_dir && !moving && src_dir.exists() {
+                // Reuse the existing sources
+                return Ok(src_dir.into());
+            }
+            Some(lock)
+        };
 
         let new_archive_dir: PathBuf;
         let (archive_dir, ignore_existing): (&Path, bool) = if self.cache {
@@ -102,7 +275,10 @@ impl<'a> RubySrcDownloader<'a> {
                     None => return Err(MissingCache),
                 },
             };
-            (dir, self.ignore_cache)
+            (dir, self.ignore_cache || moving)
+        } else if self.skip_unpack {
+            // Keep the archive where the sources would have been
+            (self.dst_dir, true)
         } else {
             let mut dir = env::temp_dir();
             dir.push("aloxide");
@@ -113,55 +289,87 @@ impl<'a> RubySrcDownloader<'a> {
 
         let archive_path = archive_dir.join(&archive_name);
 
-        let remove_archive: Option<RemoveFileHandle> = if !self.cache {
+        // Wait for other processes downloading the same archive
+        let _archive_lock = util::lock(&archive_path).map_err(Lock)?;
+
+        let keep_archive = self.cache || self.skip_unpack;
+        let remove_archive: Option<RemoveFileHandle> = if !keep_archive {
             // Clean up archive in temp dir
             Some(RemoveFileHandle { file: &archive_path })
         } else {
             None
         };
 
-        let archive_exists = archive_path.exists();
-
-        let mut file = if ignore_existing || !archive_exists {
-            Self::_download(self.version, &archive_path)?
+        let cached = if ignore_existing || !archive_path.exists() {
+            None
         } else {
-            File::open(&archive_path).map_err(OpenArchive)?
-        };
+            let mut file = File::open(&archive_path).map_err(OpenArchive)?;
 
-        file.unpack(&self.dst_dir)
-            .map_err(RubySrcDownloadError::UnpackArchive)?;
+            // Re-download archives that were truncated or corrupted
+            if file.verify().is_ok() {
+                file.seek(SeekFrom::Start(0)).map_err(OpenArchive)?;
+                Some(file)
+            } else {
+                None
+            }
+        };
 
-        drop(remove_archive);
-        Ok(src_dir.into())
-    }
+        let mut file = match cached {
+            Some(file) => file,
+            None => {
+                let progress = self.progress.as_mut().map(|f| &mut **f as _);
+                let url = self.source.url();
+                let mut file =
+                    fetch(&url, &archive_path, &self.request, progress)?;
+                if self.skip_unpack {
+                    file.verify().map_err(InvalidArchive)?;
+                }
+                file
+            },
+        };
 
-    fn _download(version: &Version, archive_path: &Path) -> Result<File, RubySrcDownloadError> {
-        use RubySrcDownloadError::*;
+        if !self.skip_unpack {
+            // Don't leave files from an older snapshot behind
+            if moving && src_dir.exists() {
+                fs::remove_dir_all(&src_dir).map_err(UnpackArchive)?;
+            }
 
-        let response = ureq::get(&version.url()).call();
-        if response.ok() {
-            Self::_read_response(response, archive_path).map_err(CreateArchive)
-        } else {
-            Err(RequestArchive(response))
+            let options = mem::take(&mut self.unpack);
+            file.unpack_threaded(self.dst_dir, options)
+                .map_err(RubySrcDownloadError::UnpackArchive)?;
         }
-    }
 
-    fn _read_response(response: Response, archive_path: &Path) -> io::Result<File> {
-        let mut response = response.into_reader();
-        let mut file = fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(archive_path)?;
+        if self.cache {
+            // Failing to maintain the cache shouldn't fail the download
+            let cache = Cache::new(archive_dir);
+            let _ = cache.mark_used(&archive_path);
+            if let Some(max_size) = self.max_cache_size {
+                let _ = cache.evict_except(max_size, Some(&archive_path));
+            }
+        }
 
-        io::copy(&mut response, &mut file)?;
-        file.sync_data()?;
-        file.seek(SeekFrom::Start(0))?;
+        drop(remove_archive);
 
-        Ok(file)
+        if self.skip_unpack {
+            Ok(archive_path.into())
+        } else {
+            Ok(src_dir.into())
+        }
     }
 }
 
+// Downloads `versions` across up to `jobs` threads
+pub(crate) fn download_all(
+    versions: &[Version],
+    dst_dir: &Path,
+    jobs: usize,
+    f: &(dyn Fn(RubySrcDownloader) -> RubySrcDownloader + Sync),
+) -> Vec<Result<Box<RubySrc>, RubySrcDownloadError>> {
+    util::parallel_map(versions, jobs, |version| {
+        f(RubySrcDownloader::new(version, dst_dir)).download()
+    })
+}
+
 /// The error returned when
 /// [`RubySrcDownloader::download`](struct.RubySrcDownloader.html#method.download)
 /// fails.
@@ -169,6 +377,10 @@ impl<'a> RubySrcDownloader<'a> {
 pub enum RubySrcDownloadError {
     /// No cache directory could be found for the current user.
     MissingCache,
+    /// The proxy URL could not be parsed.
+    InvalidProxy(ureq::Error),
+    /// Failed to lock the sources directory or the archive.
+    Lock(io::Error),
     /// Failed to open an existing archive.
     OpenArchive(io::Error),
     /// Failed to create a directory for the archive.
@@ -176,16 +388,203 @@ pub enum RubySrcDownloadError {
     /// Failed to download the archive.
     CreateArchive(io::Error),
     /// Failed to GET the archive.
-    RequestArchive(Response),
+    RequestArchive(Box<Response>),
+    /// The downloaded archive is malformed or truncated.
+    InvalidArchive(io::Error),
     /// Failed to unpack the `.tar.gz` archive.
     UnpackArchive(io::Error),
 }
 
+// Downloads `url` to `archive_path` and returns the opened file
+pub(crate) fn fetch(
+    url: &str,
+    archive_path: &Path,
+    options: &RequestOptions,
+    progress: Option<&mut Progress>,
+) -> Result<File, RubySrcDownloadError> {
+    use RubySrcDownloadError::*;
+
+    let mut request = ureq::get(url);
+    request.set("User-Agent", options.user_agent.unwrap_or(USER_AGENT));
+    for &(name, value) in &options.headers {
+        request.set(name, value);
+    }
+
+    let env_proxy: Option<String>;
+    let proxy = match options.proxy {
+        Some(proxy) => Some(proxy),
+        None => {
+            env_proxy = proxy_from_env(url);
+            env_proxy.as_deref()
+        },
+    };
+    if let Some(proxy) = proxy {
+        let proxy = Proxy::new(proxy.trim_end_matches('/'))
+            .map_err(InvalidProxy)?;
+        request.set_proxy(proxy);
+    }
+
+    let response = request.call();
+    if !response.ok() {
+        return Err(RequestArchive(Box::new(response)));
+    }
+
+    // Download next to the archive and move it into place once complete so
+    // that an interrupted download never leaves a partial archive behind
+    let mut part_path = archive_path.as_os_str().to_owned();
+    part_path.push(".part");
+    let part_path = PathBuf::from(part_path);
+    let remove_part = RemoveFileHandle { file: &part_path };
+
+    read_response(response, &part_path, progress).map_err(CreateArchive)?;
+    fs::rename(&part_path, archive_path).map_err(CreateArchive)?;
+    drop(remove_part);
+
+    File::open(archive_path).map_err(OpenArchive)
+}
+
+fn read_response(
+    response: Response,
+    path: &Path,
+    progress: Option<&mut Progress>,
+) -> io::Result<()> {
+    let total = response.header("Content-Length")
+        .and_then(|len| len.parse().ok());
+
+    let response = response.into_reader();
+    let mut response: Box<dyn io::Read> = match progress {
+        Some(progress) => {
+            progress(0, total);
+            Box::new(ProgressReader {
+                reader: response,
+                read: 0,
+                total,
+                progress,
+            })
+        },
+        None => Box::new(response),
+    };
+
+    let mut file = File::create(path)?;
+    io::copy(&mut response, &mut file)?;
+    file.sync_data()
+}
+
+// Returns the proxy to use for `url` based on the environment
+fn proxy_from_env(url: &str) -> Option<String> {
+    fn var(keys: &[&str]) -> Option<String> {
+        keys.iter()
+            .filter_map(|key| env::var(key).ok())
+            .find(|var| !var.is_empty())
+    }
+
+    if let Some(no_proxy) = var(&["NO_PROXY", "no_proxy"]) {
+        if is_no_proxy(&no_proxy, url_host(url)) {
+            return None;
+        }
+    }
+
+    let proxy = if url.starts_with("https:") {
+        var(&["HTTPS_PROXY", "https_proxy"])
+    } else {
+        var(&["HTTP_PROXY", "http_proxy"])
+    };
+    proxy.or_else(|| var(&["ALL_PROXY", "all_proxy"]))
+}
+
+// Returns the host of `url` without its scheme, credentials, path, or port
+fn url_host(url: &str) -> &str {
+    let url = match url.find("://") {
+        Some(index) => &url[(index + 3)..],
+        None => url,
+    };
+    let authority = url.split('/').next().unwrap_or(url);
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    host.split(':').next().unwrap_or(host)
+}
+
+// Returns whether `host` matches a comma-separated `NO_PROXY` list
+fn is_no_proxy(no_proxy: &str, host: &str) -> bool {
+    no_proxy.split(',').map(str::trim).any(|entry| {
+        let entry = url_host(entry).trim_start_matches('.');
+        if entry == "*" || entry.eq_ignore_ascii_case(host) {
+            return true;
+        }
+        let suffix_len = entry.len() + 1;
+        host.len() > suffix_len && {
+            let (sub, domain) = host.split_at(host.len() - entry.len());
+            sub.ends_with('.') && domain.eq_ignore_ascii_case(entry)
+        }
+    })
+}
+
+// Reports the number of bytes read from `reader` to `progress`
+struct ProgressReader<'p, R> {
+    reader: R,
+    read: u64,
+    total: Option<u64>,
+    progress: &'p mut Progress<'p>,
+}
+
+impl<R: io::Read> io::Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        if n != 0 {
+            self.read += n as u64;
+            (self.progress)(self.read, self.total);
+        }
+        Ok(n)
+    }
+}
+
 // Removes `file` when an instance goes out of scope
-struct RemoveFileHandle<'p> { file: &'p Path }
+pub(crate) struct RemoveFileHandle<'p> { pub file: &'p Path }
 
 impl Drop for RemoveFileHandle<'_> {
     fn drop(&mut self) {
         let _ = fs::remove_file(self.file);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_names() {
+        // Archives of patch level releases unpack without the patch level
+        let version: Version = "2.6.2p47".parse().unwrap();
+        let source = Source::Release(&version);
+        assert_eq!(source.src_name(), "ruby-2.6.2");
+        assert_eq!(source.archive_name(), "ruby-2.6.2.tar.bz2");
+    }
+
+    #[test]
+    fn no_proxy() {
+        let host = url_host("https://cache.ruby-lang.org/pub/ruby/2.6/");
+        assert_eq!(host, "cache.ruby-lang.org");
+
+        let matching = [
+            "*",
+            "cache.ruby-lang.org",
+            "ruby-lang.org",
+            ".ruby-lang.org",
+            "localhost, .ruby-lang.org",
+            "RUBY-LANG.ORG:443",
+        ];
+        for no_proxy in &matching {
+            assert!(is_no_proxy(no_proxy, host), "{:?}", no_proxy);
+        }
+
+        let non_matching = [
+            "",
+            "localhost",
+            "lang.org.example",
+            "ang.org",
+            "che.ruby-lang.org",
+        ];
+        for no_proxy in &non_matching {
+            assert!(!is_no_proxy(no_proxy, host), "{:?}", no_proxy);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Utilities for getting Ruby's sources via Git.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use crate::RubySrc;
+
+/// The URL of Ruby's official Git repository.
+pub const RUBY_REPO: &str = "https://github.com/ruby/ruby.git";
+
+// Written within `.git` once the sources are fully checked out
+const CHECKOUT_MARKER: &str = "aloxide-checkout";
+
+/// Shallow-clones Ruby's sources at a given revision from a Git repository.
+///
+/// Building sources from Git requires an existing Ruby installation (the
+/// "baseruby") to generate some files.
+pub struct RubySrcGit<'a> {
+    url: &'a str,
+    rev: &'a str,
+    dst_dir: &'a Path,
+    depth: u32,
+    ignore_existing_dir: bool,
+    autogen: bool,
+}
+
+impl<'a> RubySrcGit<'a> {
+    #[inline]
+    pub(crate) fn new(url: &'a str, rev: &'a str, dst_dir: &'a Path) -> Self {
+        RubySrcGit {
+            url,
+            rev,
+            dst_dir,
+            depth: 1,
+            ignore_existing_dir: false,
+            autogen: true,
+        }
+    }
+
+    /// Re-clone into the sources directory in `dst_dir` if it already exists.
+    ///
+    /// **Warning:** This will remove the existing sources directory. Use
+    /// carefully!
+    #[inline]
+    pub fn ignore_existing_dir(mut self) -> Self {
+        self.ignore_existing_dir = true;
+        self
+    }
+
+    /// Sets the number of commits of history to fetch.
+    ///
+    /// The default value is 1.
+    #[inline]
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Skips running `./autogen.sh` after checking out the sources.
+    ///
+    /// `RubyBuilder` will still run `autoconf` if `configure` is missing.
+    #[inline]
+    pub fn skip_autogen(mut self) -> Self {
+        self.autogen = false;
+        self
+    }
+
+    /// Returns the directory that the sources are checked out into.
+    ///
+    /// This is `ruby-{rev}` in the destination directory, with any `/` in the
+    /// revision replaced with `-`.
+    #[inline]
+    pub fn src_dir(&self) -> PathBuf {
+        self.dst_dir.join(format!("ruby-{}", self.rev.replace('/', "-")))
+    }
+
+    /// Checks out and returns the directory containing the Ruby sources.
+    pub fn checkout(self) -> Result<Box<RubySrc>, RubySrcGitError> {
+        use RubySrcGitError::*;
+
+        let src_dir = self.src_dir();
+        let marker = src_dir.join(".git").join(CHECKOUT_MARKER);
+
+        if src_dir.exists() {
+            if !self.ignore_existing_dir && marker.is_file() {
+                // Reuse the existing sources
+                return Ok(src_dir.into());
+            }
+            // Remove sources left behind by a failed or interrupted checkout
+            fs::remove_dir_all(&src_dir).map_err(CreateDir)?;
+        }
+        fs::create_dir_all(&src_dir).map_err(CreateDir)?;
+
+        let git = |args: &[&str]| -> Result<(), RubySrcGitError> {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(&src_dir)
+                .output()
+                .map_err(GitSpawnFail)?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(GitFail(output))
+            }
+        };
+
+        let depth = format!("--depth={}", self.depth);
+
+        git(&["init", "--quiet"])?;
+        git(&["remote", "add", "origin", self.url])?;
+        git(&["fetch", "--quiet", &depth, "origin", self.rev])?;
+        git(&["checkout", "--quiet", "--detach", "FETCH_HEAD"])?;
+
+        let autogen = src_dir.join("autogen.sh");
+        if self.autogen && autogen.exists() {
+            let output = Command::new("sh")
+                .arg(&autogen)
+                .current_dir(&src_dir)
+                .output()
+                .map_err(AutogenSpawnFail)?;
+            if !output.status.success() {
+                return Err(AutogenFail(output));
+            }
+        }
+
+        fs::write(&marker, self.rev).map_err(CreateDir)?;
+        Ok(src_dir.into())
+    }
+}
+
+/// The error returned when
+/// [`RubySrcGit::checkout`](struct.RubySrcGit.html#method.checkout) fails.
+#[derive(Debug)]
+pub enum RubySrcGitError {
+    /// Failed to create or replace the sources directory.
+    CreateDir(io::Error),
+    /// Failed to spawn a process for `git`.
+    GitSpawnFail(io::Error),
+    /// `git` exited unsuccessfully.
+    GitFail(Output),
+    /// Failed to spawn a process for `autogen.sh`.
+    AutogenSpawnFail(io::Error),
+    /// `autogen.sh` exited unsuccessfully.
+    AutogenFail(Output),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse_only_complete_checkout() {
+        let dir = std::env::temp_dir().join("aloxide-git-checkout");
+        let _ = fs::remove_dir_all(&dir);
+
+        // A local repository stands in for Ruby's
+        let repo = dir.join("repo");
+        fs::create_dir_all(&repo).unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=aloxide", "-c", "user.email=a@b.c"])
+                .args(args)
+                .current_dir(&repo)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "--quiet"]);
+        fs::write(repo.join("file.txt"), "contents").unwrap();
+        git(&["add", "file.txt"]);
+        git(&["commit", "--quiet", "-m", "initial"]);
+
+        let url = repo.to_str().unwrap();
+        let checkout = |rev| RubySrcGit::new(url, rev, &dir).checkout();
+
+        // A failed fetch leaves no reusable sources behind
+        assert!(checkout("missing").is_err());
+        assert!(checkout("missing").is_err());
+
+        let src = checkout("HEAD").unwrap();
+        assert!(src.as_path().join("file.txt").is_file());
+
+        // Complete checkouts are reused
+        let extra = src.as_path().join("extra.txt");
+        fs::write(&extra, "").unwrap();
+        checkout("HEAD").unwrap();
+        assert!(extra.exists());
+
+        // Incomplete checkouts are replaced
+        fs::remove_file(src.as_path().join(".git").join(CHECKOUT_MARKER))
+            .unwrap();
+        checkout("HEAD").unwrap();
+        assert!(!extra.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
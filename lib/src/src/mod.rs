@@ -1,21 +1,37 @@
 //! Utilities for Ruby's source code.
 
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use crate::Version;
 
 pub mod build;
+pub mod git;
+pub mod snapshot;
 
 #[cfg(feature = "download")]
 pub mod download;
+#[cfg(feature = "download")]
+pub mod multi;
 
 #[doc(inline)]
 pub use build::RubyBuilder;
 
+#[doc(inline)]
+pub use git::RubySrcGit;
+
+#[doc(inline)]
+pub use snapshot::Snapshot;
+
 #[cfg(feature = "download")]
 #[doc(inline)]
-pub use download::RubySrcDownloader;
+pub use download::{RubySrcDownloader, RubySrcDownloadError};
+
+#[cfg(feature = "download")]
+#[doc(inline)]
+pub use multi::MultiBuilder;
 
 /// A path to Ruby's source code.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -73,6 +89,64 @@ impl RubySrc {
         RubySrcDownloader::new(version, parent.as_ref())
     }
 
+    /// Returns a downloader for `snapshot` targeted towards `self`.
+    #[inline]
+    #[cfg(feature = "download")]
+    pub fn snapshot_downloader<'a, P: AsRef<Path> + ?Sized>(
+        snapshot: &'a Snapshot,
+        parent: &'a P,
+    ) -> RubySrcDownloader<'a> {
+        RubySrcDownloader::snapshot(snapshot, parent.as_ref())
+    }
+
+    /// Downloads the sources for each of `versions` into `parent`, returning
+    /// the result for each version in the same order.
+    ///
+    /// Up to 4 archives are downloaded at once. Use
+    /// [`download_all_with`](#method.download_all_with) to configure this.
+    #[inline]
+    #[cfg(feature = "download")]
+    pub fn download_all<P: AsRef<Path> + ?Sized>(
+        versions: &[Version],
+        parent: &P,
+    ) -> Vec<Result<Box<RubySrc>, RubySrcDownloadError>> {
+        Self::download_all_with(versions, parent, 4, |downloader| downloader)
+    }
+
+    /// Downloads the sources for each of `versions` into `parent` with at
+    /// most `jobs` downloads at once, returning the result for each version in
+    /// the same order.
+    ///
+    /// Each downloader is passed through `f` before downloading, which allows
+    /// for configuring caching, proxies, etc.
+    #[cfg(feature = "download")]
+    pub fn download_all_with<P, F>(
+        versions: &[Version],
+        parent: &P,
+        jobs: usize,
+        f: F,
+    ) -> Vec<Result<Box<RubySrc>, RubySrcDownloadError>>
+    where
+        P: AsRef<Path> + ?Sized,
+        F: Fn(RubySrcDownloader) -> RubySrcDownloader + Sync,
+    {
+        download::download_all(versions, parent.as_ref(), jobs, &f)
+    }
+
+    /// Returns a shallow clone of the Git repository at `url` checked out at
+    /// `rev` (a tag, branch, or commit SHA) in `parent`.
+    ///
+    /// Use [`git::RUBY_REPO`](git/constant.RUBY_REPO.html) for Ruby's official
+    /// repository.
+    #[inline]
+    pub fn from_git<'a, P: AsRef<Path> + ?Sized>(
+        url: &'a str,
+        rev: &'a str,
+        parent: &'a P,
+    ) -> RubySrcGit<'a> {
+        RubySrcGit::new(url, rev, parent.as_ref())
+    }
+
     /// Returns the directory path.
     #[inline]
     pub fn as_path(&self) -> &Path {
@@ -111,4 +185,75 @@ impl RubySrc {
         cmd.current_dir(self);
         cmd
     }
+
+    /// Removes the build artifacts via `make clean`, keeping the results of
+    /// `configure`.
+    ///
+    /// This does nothing if `configure` has not been run.
+    #[inline]
+    pub fn clean(&self, target: impl AsRef<str>) -> io::Result<()> {
+        self.run_make_target(target.as_ref(), "clean")
+    }
+
+    /// Removes the build artifacts and the results of `configure` via
+    /// `make distclean`.
+    ///
+    /// This does nothing if `configure` has not been run.
+    #[inline]
+    pub fn distclean(&self, target: impl AsRef<str>) -> io::Result<()> {
+        self.run_make_target(target.as_ref(), "distclean")
+    }
+
+    /// Removes `build_dir` of an out-of-tree build.
+    ///
+    /// See [`RubyBuilder::build_dir`](build/struct.RubyBuilder.html#method.build_dir).
+    ///
+    /// This is the out-of-tree equivalent of
+    /// [`distclean`](#method.distclean).
+    pub fn clean_build_dir(
+        &self,
+        build_dir: impl AsRef<Path>,
+    ) -> io::Result<()> {
+        let build_dir = build_dir.as_ref();
+        if build_dir == self.as_path() {
+            let msg = "build directory is the sources directory";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+
+        // Wait for builds in `build_dir` to finish
+        let _lock = match crate::util::lock(build_dir) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(());
+            },
+            result => result?,
+        };
+        match fs::remove_dir_all(build_dir) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn run_make_target(
+        &self,
+        target: &str,
+        make_target: &str,
+    ) -> io::Result<()> {
+        // Wait for builds of the same sources to finish
+        let _lock = crate::util::lock(self.as_path())?;
+
+        if !self.as_path().join("Makefile").exists() {
+            return Ok(());
+        }
+
+        let status = self.make(target).arg(make_target).status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "`make {}` exited unsuccessfully ({})",
+                make_target,
+                status,
+            )))
+        }
+    }
 }
@@ -0,0 +1,208 @@
+//! Building multiple Ruby versions at once.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::{util, Ruby, RubySrc, Version};
+use super::{RubyBuilder, RubySrcDownloader};
+use super::build::RubyBuildError;
+
+type DownloadFn<'a> = dyn Fn(RubySrcDownloader) -> RubySrcDownloader
+    + Sync
+    + 'a;
+type BuildFn<'a> = dyn for<'b> Fn(RubyBuilder<'b>) -> RubyBuilder<'b>
+    + Sync
+    + 'a;
+
+/// Downloads and builds multiple Ruby versions, optionally in parallel.
+///
+/// Sources are downloaded into `src_dir/ruby-{version}` via the download
+/// cache, and each version is built into `out_dir/ruby-{version}`. Each
+/// version is built once; adding a version again replaces how it was added
+/// before.
+pub struct MultiBuilder<'a> {
+    versions: Vec<(Version, Option<PathBuf>)>,
+    src_dir: PathBuf,
+    out_dir: PathBuf,
+    target: String,
+    jobs: usize,
+    download: Option<Box<DownloadFn<'a>>>,
+    build: Option<Box<BuildFn<'a>>>,
+}
+
+impl<'a> MultiBuilder<'a> {
+    /// Creates a new instance that builds for `target`.
+    #[inline]
+    pub fn new(
+        src_dir: impl Into<PathBuf>,
+        out_dir: impl Into<PathBuf>,
+        target: impl Into<String>,
+    ) -> Self {
+        MultiBuilder {
+            versions: Vec::new(),
+            src_dir: src_dir.into(),
+            out_dir: out_dir.into(),
+            target: target.into(),
+            jobs: 1,
+            download: None,
+            build: None,
+        }
+    }
+
+    /// Adds `version` to be downloaded and built.
+    #[inline]
+    pub fn version(mut self, version: Version) -> Self {
+        self.add(version, None);
+        self
+    }
+
+    /// Adds each of `versions` to be downloaded and built.
+    #[inline]
+    pub fn versions<I>(mut self, versions: I) -> Self
+        where I: IntoIterator<Item = Version>
+    {
+        for version in versions {
+            self.add(version, None);
+        }
+        self
+    }
+
+    /// Adds `version` to be built from the existing sources in `dir`.
+    #[inline]
+    pub fn src(mut self, version: Version, dir: impl Into<PathBuf>) -> Self {
+        self.add(version, Some(dir.into()));
+        self
+    }
+
+    // Adds `version`, replacing any previous entry so that two builds never
+    // share the same output directory
+    fn add(&mut self, version: Version, dir: Option<PathBuf>) {
+        match self.versions.iter_mut().find(|(v, _)| *v == version) {
+            Some(entry) => entry.1 = dir,
+            None => self.versions.push((version, dir)),
+        }
+    }
+
+    /// Builds up to `n` versions at once.
+    ///
+    /// The default is 1. Note that each build also runs `make` with parallel
+    /// jobs.
+    #[inline]
+    pub fn jobs(mut self, n: usize) -> Self {
+        self.jobs = n.max(1);
+        self
+    }
+
+    /// Configures each downloader, which uses the download cache by default.
+    #[inline]
+    pub fn downloader<F>(mut self, f: F) -> Self
+        where F: Fn(RubySrcDownloader) -> RubySrcDownloader + Sync + 'a
+    {
+        self.download = Some(Box::new(f));
+        self
+    }
+
+    /// Configures each builder before building.
+    #[inline]
+    pub fn builder<F>(mut self, f: F) -> Self
+        where F: for<'b> Fn(RubyBuilder<'b>) -> RubyBuilder<'b> + Sync + 'a
+    {
+        self.build = Some(Box::new(f));
+        self
+    }
+
+    /// Downloads and builds each version, returning the result for each.
+    pub fn build(self) -> BTreeMap<Version, Result<Ruby, RubyBuildError>> {
+        let results = util::parallel_map(&self.versions, self.jobs, |entry| {
+            let (version, dir) = entry;
+            self.build_one(version, dir.as_deref())
+        });
+
+        self.versions.iter()
+            .map(|(version, _)| version.clone())
+            .zip(results)
+            .collect()
+    }
+
+    fn build_one(
+        &self,
+        version: &Version,
+        dir: Option<&Path>,
+    ) -> Result<Ruby, RubyBuildError> {
+        let downloaded: Box<RubySrc>;
+        let src = match dir {
+            Some(dir) => RubySrc::new(dir),
+            None => {
+                let downloader = RubySrcDownloader::new(version, &self.src_dir)
+                    .cache();
+                let downloader = match &self.download {
+                    Some(f) => f(downloader),
+                    None => downloader,
+                };
+                downloaded = downloader.download().map_err(|error| {
+                    RubyBuildError::Download(Box::new(error))
+                })?;
+                &downloaded
+            },
+        };
+
+        let out_dir = self.out_dir.join(format!("ruby-{}", version.release()));
+        let builder = src.builder(out_dir, &self.target);
+        match &self.build {
+            Some(f) => f(builder).build(),
+            None => builder.build(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+    #[test]
+    fn dedupe_versions() {
+        let a = Version::new(3, 2, 2);
+        let b = Version::new(3, 3, 0);
+        let multi = MultiBuilder::new("src", "out", TARGET)
+            .version(a.clone())
+            .versions(vec![b.clone(), a.clone()])
+            .src(a.clone(), "ruby-src");
+
+        assert_eq!(multi.versions, [
+            (a, Some(PathBuf::from("ruby-src"))),
+            (b, None),
+        ]);
+    }
+
+    #[test]
+    fn build_existing_sources() {
+        let dir = std::env::temp_dir().join("aloxide-multi-build");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let versions = [Version::new(3, 2, 2), Version::new(3, 3, 0)];
+        let mut multi = MultiBuilder::new(&dir, dir.join("out"), TARGET)
+            .jobs(2);
+        for version in &versions {
+            let src_dir = dir.join(format!("ruby-{}", version));
+            std::fs::create_dir_all(&src_dir).unwrap();
+            multi = multi.version(version.clone())
+                .src(version.clone(), src_dir);
+        }
+
+        // The empty sources fail to build, but are never downloaded
+        let results = multi.build();
+        assert!(results.keys().eq(&versions));
+        for result in results.values() {
+            match result {
+                Err(RubyBuildError::Download(_)) | Ok(_) => {
+                    panic!("unexpected result: {:?}", result);
+                },
+                Err(_) => {},
+            }
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,116 @@
+//! Snapshots of Ruby's sources.
+
+use std::fmt;
+
+/// A snapshot of Ruby's sources, as published to
+/// [cache.ruby-lang.org](https://cache.ruby-lang.org/pub/ruby/snapshot/).
+///
+/// Snapshots are built from the development branches of Ruby and can be used
+/// to test against upcoming releases.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Snapshot {
+    name: Box<str>,
+    moving: bool,
+}
+
+impl fmt::Display for Snapshot {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.name.fmt(f)
+    }
+}
+
+impl Snapshot {
+    /// Creates a new instance for the snapshot archive named `name`, without
+    /// its file extension (e.g. `snapshot-master-20240101`).
+    ///
+    /// This can be used for snapshots not covered by the other constructors.
+    /// The archive is assumed to never change once published, so it's cached
+    /// like a release.
+    #[inline]
+    pub fn new(name: impl Into<Box<str>>) -> Self {
+        Snapshot { name: name.into(), moving: false }
+    }
+
+    /// The latest snapshot of Ruby's `master` branch.
+    ///
+    /// This snapshot is replaced every night, so it's never reused from a
+    /// cache or an existing sources directory.
+    #[inline]
+    pub fn master() -> Self {
+        Snapshot { moving: true, ..Snapshot::new("snapshot-master") }
+    }
+
+    /// The latest snapshot of the maintenance branch for `major.minor`
+    /// (e.g. `ruby_3_3`).
+    ///
+    /// Like [`master`](#method.master), this is never reused from a cache or
+    /// an existing sources directory.
+    #[inline]
+    pub fn branch(major: u16, minor: u16) -> Self {
+        let name = format!("snapshot-ruby_{}_{}", major, minor);
+        Snapshot { moving: true, ..Snapshot::new(name) }
+    }
+
+    /// The nightly snapshot of Ruby's `master` branch taken on the given date
+    /// (e.g. `snapshot-master-20240101`).
+    #[inline]
+    pub fn nightly(year: u16, month: u8, day: u8) -> Self {
+        Snapshot::new(format!(
+            "snapshot-master-{:04}{:02}{:02}",
+            year,
+            month,
+            day,
+        ))
+    }
+
+    /// Returns the name of the snapshot.
+    ///
+    /// This is also the name of the directory that the archive unpacks to.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns whether `self` is replaced over time, such as the latest
+    /// snapshot of a branch.
+    #[inline]
+    pub fn is_moving(&self) -> bool {
+        self.moving
+    }
+
+    /// Returns the name of the archive file corresponding to `self`.
+    #[inline]
+    pub fn archive_name(&self) -> String {
+        format!("{}.tar.gz", self.name)
+    }
+
+    /// Returns an HTTPS URL for `self`.
+    #[inline]
+    pub fn url(&self) -> String {
+        format!(
+            "https://cache.ruby-lang.org/pub/ruby/snapshot/{}",
+            self.archive_name(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        let master = Snapshot::master();
+        assert_eq!(master.name(), "snapshot-master");
+        assert!(master.is_moving());
+
+        let branch = Snapshot::branch(3, 3);
+        assert_eq!(branch.archive_name(), "snapshot-ruby_3_3.tar.gz");
+        assert!(branch.is_moving());
+
+        let nightly = Snapshot::nightly(2024, 1, 2);
+        assert_eq!(nightly.archive_name(), "snapshot-master-20240102.tar.gz");
+        assert!(!nightly.is_moving());
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The disk space used by the components of a directory, as returned by
+/// [`Ruby::disk_usage`](struct.Ruby.html#method.disk_usage) and
+/// [`Cache::disk_usage`](cache/struct.Cache.html#method.disk_usage).
+///
+/// Each component is an entry directly within the directory, such as "bin" or
+/// "lib" for a Ruby installation or an archive for a cache.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    components: BTreeMap<String, u64>,
+}
+
+impl DiskUsage {
+    /// Returns the size of `component` in bytes, or 0 if it does not exist.
+    #[inline]
+    pub fn get(&self, component: &str) -> u64 {
+        self.components.get(component).copied().unwrap_or(0)
+    }
+
+    /// Returns an iterator over the components and their sizes in bytes,
+    /// ordered by name.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.components.iter().map(|(name, &size)| (name.as_str(), size))
+    }
+
+    /// Returns the total size of all components in bytes.
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.components.values().sum()
+    }
+}
+
+// Sums the sizes of the files within each entry of `dir`, without following
+// symbolic links
+pub(crate) fn dir_usage(dir: &Path) -> io::Result<DiskUsage> {
+    let mut components = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        components.insert(name, size(&entry.path())?);
+    }
+    Ok(DiskUsage { components })
+}
+
+fn size(path: &Path) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += size(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage() {
+        let dir = std::env::temp_dir().join("aloxide-disk-usage");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("lib").join("ruby")).unwrap();
+        fs::write(dir.join("lib").join("libruby.so"), [0; 100]).unwrap();
+        fs::write(dir.join("lib").join("ruby").join("a.rb"), [0; 20]).unwrap();
+        fs::write(dir.join("README"), [0; 3]).unwrap();
+
+        let usage = dir_usage(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(usage.get("lib"), 120);
+        assert_eq!(usage.get("README"), 3);
+        assert_eq!(usage.get("bin"), 0);
+        assert_eq!(usage.total(), 123);
+        assert_eq!(usage.iter().count(), 2);
+    }
+}
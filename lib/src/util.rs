@@ -1,4 +1,4 @@
-use std::fs;
+use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::io;
@@ -29,6 +29,24 @@ pub fn nmake(_target: &str) -> Option<Command> {
     return None;
 }
 
+#[inline]
+pub fn msvc_lib(_target: &str) -> Option<Command> {
+    #[cfg(target_os = "windows")]
+    return cc::windows_registry::find(_target, "lib.exe");
+
+    #[cfg(not(target_os = "windows"))]
+    return None;
+}
+
+#[inline]
+pub fn msvc_dumpbin(_target: &str) -> Option<Command> {
+    #[cfg(target_os = "windows")]
+    return cc::windows_registry::find(_target, "dumpbin.exe");
+
+    #[cfg(not(target_os = "windows"))]
+    return None;
+}
+
 pub fn walk_files<F>(dir: &Path, mut f: F) -> io::Result<()>
     where for<'a> F: FnMut(PathBuf) -> io::Result<()>
 {
@@ -50,3 +68,159 @@ fn _walk_files<F>(dir: &Path, f: &mut F) -> io::Result<()>
     }
     Ok(())
 }
+
+// Blocks until an exclusive advisory lock is acquired on a file next to `path`
+// and returns a guard that releases the lock when dropped
+pub fn lock(path: &Path) -> io::Result<Lock> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+
+    loop {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)?;
+        file.lock()?;
+
+        // The previous holder removes the file once done, so the lock only
+        // excludes others if the file is still the one at `lock_path`
+        if is_same_file(&file, &lock_path)? {
+            return Ok(Lock { file, path: lock_path });
+        }
+    }
+}
+
+// An exclusive lock acquired via `lock`
+//
+// On Unix, the lock file is removed when the lock is released. Elsewhere, it's
+// left behind since an open file can't always be removed.
+#[derive(Debug)]
+pub struct Lock {
+    file: File,
+    path: PathBuf,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // Removed before `file` is closed so that waiting processes see that
+        // the file is stale
+        if cfg!(unix) {
+            let _ = fs::remove_file(&self.path);
+        }
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let locked = file.metadata()?;
+    match fs::metadata(path) {
+        Ok(current) => {
+            Ok(current.dev() == locked.dev() && current.ino() == locked.ino())
+        },
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, _path: &Path) -> io::Result<bool> {
+    Ok(true)
+}
+
+// Maps `items` via `f` across up to `jobs` threads, returning the results in
+// the same order
+#[cfg(feature = "download")]
+pub fn parallel_map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    let jobs = jobs.clamp(1, items.len().max(1));
+    let next = AtomicUsize::new(0);
+
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs).map(|_| scope.spawn(|| {
+            let mut results = Vec::new();
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let item = match items.get(index) {
+                    Some(item) => item,
+                    None => break results,
+                };
+                results.push((index, f(item)));
+            }
+        })).collect();
+
+        workers.into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn lock_blocks() {
+        let path = std::env::temp_dir().join("aloxide-util-lock");
+        let lock_path = path.with_extension("lock");
+        let _ = fs::remove_file(&lock_path);
+
+        let acquired = AtomicBool::new(false);
+        let lock = lock(&path).unwrap();
+        assert!(lock_path.exists());
+
+        thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                let lock = super::lock(&path).unwrap();
+                acquired.store(true, Ordering::SeqCst);
+                lock
+            });
+
+            thread::sleep(Duration::from_millis(200));
+            assert!(!acquired.load(Ordering::SeqCst));
+
+            drop(lock);
+            let lock = waiter.join().unwrap();
+            assert!(acquired.load(Ordering::SeqCst));
+            assert!(lock_path.exists());
+            drop(lock);
+        });
+
+        if cfg!(unix) {
+            assert!(!lock_path.exists());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "download")]
+    fn parallel_map_order() {
+        let items: Vec<u64> = (0..20).collect();
+        let doubled: Vec<u64> = items.iter().map(|item| item * 2).collect();
+        for &jobs in &[0, 1, 4, 100] {
+            let results = parallel_map(&items, jobs, |&item| {
+                // Finish out of order
+                thread::sleep(Duration::from_millis(20 - item));
+                item * 2
+            });
+            assert_eq!(results, doubled);
+        }
+        assert!(parallel_map(&[] as &[u64], 4, |&item| item).is_empty());
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use crate::{util, Ruby, RubyExecError, Version};
+use crate::config::RbConfig;
+use crate::version::{RubyVersionError, VersionParseError};
+
+/// A problem with a Ruby installation, as found by
+/// [`Ruby::verify`](struct.Ruby.html#method.verify).
+#[derive(Debug)]
+pub enum InstallProblem {
+    /// The `ruby` executable does not exist.
+    MissingBin(PathBuf),
+    /// Failed to run the `ruby` executable.
+    Exec(RubyExecError),
+    /// The version reported by `ruby` could not be parsed.
+    InvalidVersion(VersionParseError),
+    /// The version reported by `ruby` differs from the installation's.
+    VersionMismatch {
+        /// The version of the installation.
+        expected: Version,
+        /// The version reported by `ruby`.
+        found: Version,
+    },
+    /// The Ruby library does not exist in the library directory.
+    MissingLib(PathBuf),
+    /// A header file does not exist.
+    MissingHeader(PathBuf),
+}
+
+impl fmt::Display for InstallProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InstallProblem::MissingBin(path) => {
+                write!(f, "`ruby` not found at {}", path.display())
+            },
+            InstallProblem::Exec(error) => error.fmt(f),
+            InstallProblem::InvalidVersion(error) => {
+                write!(f, "`ruby` reported an invalid version: {:?}", error)
+            },
+            InstallProblem::VersionMismatch { expected, found } => {
+                write!(f, "expected Ruby {}, found {}", expected, found)
+            },
+            InstallProblem::MissingLib(path) => {
+                write!(f, "Ruby library not found at {}", path.display())
+            },
+            InstallProblem::MissingHeader(path) => {
+                write!(f, "Ruby header not found at {}", path.display())
+            },
+        }
+    }
+}
+
+/// The error returned by
+/// [`Ruby::verify_symbols`](struct.Ruby.html#method.verify_symbols).
+#[derive(Debug)]
+pub enum VerifySymbolsError {
+    /// Failed to run `ruby` to find the library.
+    Exec(RubyExecError),
+    /// Neither the static nor the shared Ruby library exists.
+    MissingLib,
+    /// Failed to run `nm`.
+    Nm(io::Error),
+    /// `nm` exited with a failure.
+    NmFail(Output),
+    /// Failed to run the MSVC toolchain's `dumpbin`.
+    Dumpbin(io::Error),
+    /// `dumpbin` exited with a failure.
+    DumpbinFail(Output),
+}
+
+impl std::error::Error for VerifySymbolsError {}
+
+impl fmt::Display for VerifySymbolsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifySymbolsError::Exec(error) => error.fmt(f),
+            VerifySymbolsError::MissingLib => {
+                f.write_str("no Ruby library found to read symbols from")
+            },
+            VerifySymbolsError::Nm(error) => {
+                write!(f, "failed to run `nm`: {}", error)
+            },
+            VerifySymbolsError::NmFail(output) => write!(
+                f,
+                "`nm` exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ),
+            VerifySymbolsError::Dumpbin(error) => {
+                write!(f, "failed to run `dumpbin`: {}", error)
+            },
+            VerifySymbolsError::DumpbinFail(output) => write!(
+                f,
+                "`dumpbin` exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stdout).trim(),
+            ),
+        }
+    }
+}
+
+impl From<RubyExecError> for VerifySymbolsError {
+    #[inline]
+    fn from(error: RubyExecError) -> Self {
+        VerifySymbolsError::Exec(error)
+    }
+}
+
+// The external symbols defined in the output of `nm -g`, where each line is
+// "[address] type name" and undefined symbols have a type of "U", "u", "v",
+// or "w"
+fn defined_symbols(nm_output: &str, mach_o: bool) -> HashSet<&str> {
+    nm_output.lines().filter_map(|line| {
+        let mut parts = line.split_whitespace().rev();
+        let name = parts.next()?;
+        let kind = parts.next()?;
+        if kind.len() != 1 || "Uuvw".contains(kind) {
+            return None;
+        }
+        // Mach-O prefixes C symbols with an underscore
+        Some(if mach_o { name.strip_prefix('_')? } else { name })
+    }).collect()
+}
+
+// The external symbols defined in the output of `dumpbin /SYMBOLS`, where each
+// line looks like "008 00000000 SECT3  notype ()    External     | rb_ary_new"
+// and undefined symbols are in the "UNDEF" section
+//
+// 32-bit x86 prefixes C symbols with an underscore
+fn defined_coff_symbols(output: &str, x86: bool) -> HashSet<&str> {
+    output.lines().filter_map(|line| {
+        let (info, name) = line.split_once('|')?;
+        let mut info = info.split_whitespace();
+        if info.nth(2)? == "UNDEF" || !info.any(|part| part == "External") {
+            return None;
+        }
+        let name = name.split_whitespace().next()?;
+        Some(if x86 { name.strip_prefix('_')? } else { name })
+    }).collect()
+}
+
+// Returns `dumpbin` if `config` is for an MSVC build of Ruby
+fn msvc_dumpbin(config: &RbConfig) -> Option<Command> {
+    let target = config.get("target").unwrap_or_default();
+    if !target.contains("msvc") && !target.contains("mswin") {
+        return None;
+    }
+    // Outside of build scripts, look for the toolchain matching Ruby
+    let rust_target = env::var("TARGET").unwrap_or_else(|_| {
+        let arch = match config.get("target_cpu").unwrap_or_default() {
+            "x64" | "x86_64" => "x86_64",
+            "arm64" | "aarch64" => "aarch64",
+            _ => "i686",
+        };
+        format!("{}-pc-windows-msvc", arch)
+    });
+    util::msvc_dumpbin(&rust_target)
+}
+
+// Runs `command` and returns its stdout
+fn run(
+    command: &mut Command,
+    spawn_fail: fn(io::Error) -> VerifySymbolsError,
+    fail: fn(Output) -> VerifySymbolsError,
+) -> Result<String, VerifySymbolsError> {
+    let output = command.output().map_err(spawn_fail)?;
+    if !output.status.success() {
+        return Err(fail(output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub(crate) fn missing_symbols(
+    ruby: &Ruby,
+    symbols: &[&str],
+) -> Result<Vec<String>, VerifySymbolsError> {
+    let (lib, shared) = match ruby.static_lib_path()? {
+        Some(lib) => (lib, false),
+        None => match ruby.shared_lib_path()? {
+            Some(lib) => (lib, true),
+            None => return Err(VerifySymbolsError::MissingLib),
+        },
+    };
+    let config = ruby.config()?;
+
+    let stdout: String;
+    let defined = match msvc_dumpbin(config) {
+        Some(mut dumpbin) => {
+            use VerifySymbolsError::{Dumpbin, DumpbinFail};
+
+            // DLLs list their exports, whereas static libraries list the
+            // symbols of each object
+            let flag = if shared { "/EXPORTS" } else { "/SYMBOLS" };
+            dumpbin.arg("/NOLOGO").arg(flag).arg(&lib);
+            stdout = run(&mut dumpbin, Dumpbin, DumpbinFail)?;
+
+            if shared {
+                crate::link::dumpbin_exports(&stdout).into_iter().collect()
+            } else {
+                let cpu = config.get("target_cpu").unwrap_or_default();
+                let x86 = cpu.starts_with('i') && cpu.ends_with("86");
+                defined_coff_symbols(&stdout, x86)
+            }
+        },
+        None => {
+            use VerifySymbolsError::{Nm, NmFail};
+
+            let mach_o = config.get("target_os")
+                .is_some_and(|os| os.starts_with("darwin"));
+
+            let program = env::var_os("NM").unwrap_or_else(|| "nm".into());
+            let mut nm = Command::new(program);
+            nm.arg("-g");
+            // ELF shared libraries export via their dynamic symbol table
+            if shared && !mach_o {
+                nm.arg("-D");
+            }
+            stdout = run(nm.arg(&lib), Nm, NmFail)?;
+            defined_symbols(&stdout, mach_o)
+        },
+    };
+    Ok(symbols.iter()
+        .filter(|symbol| !defined.contains(**symbol))
+        .map(|&symbol| symbol.to_owned())
+        .collect())
+}
+
+pub(crate) fn verify(ruby: &Ruby) -> Vec<InstallProblem> {
+    use InstallProblem::*;
+
+    let mut problems = Vec::new();
+
+    if !ruby.bin_path().is_file() {
+        problems.push(MissingBin(ruby.bin_path().to_owned()));
+        return problems;
+    }
+
+    match Version::from_bin(ruby.bin_path()) {
+        Ok(found) => if found != *ruby.version() {
+            let expected = ruby.version().clone();
+            problems.push(VersionMismatch { expected, found });
+        },
+        Err(RubyVersionError::Exec(error)) => {
+            problems.push(Exec(error));
+            return problems;
+        },
+        Err(RubyVersionError::Parse(error)) => {
+            problems.push(InvalidVersion(error));
+        },
+    }
+
+    let config = match ruby.config() {
+        Ok(config) => config,
+        Err(error) => {
+            problems.push(Exec(error));
+            return problems;
+        },
+    };
+
+    // `LIBRUBY` is the shared library if enabled and the static one otherwise
+    if let Some(lib) = config.get("LIBRUBY").filter(|lib| !lib.is_empty()) {
+        let path = ruby.lib_dir().join(lib);
+        if !path.exists() {
+            problems.push(MissingLib(path));
+        }
+    }
+
+    let headers = [
+        (config.header_dir(), "ruby.h"),
+        (config.arch_header_dir(), "ruby/config.h"),
+    ];
+    for (dir, header) in headers.iter() {
+        if let Some(dir) = dir {
+            let path = PathBuf::from(dir).join(header);
+            if !path.is_file() {
+                problems.push(MissingHeader(path));
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nm_symbols() {
+        let output = "\
+            libruby-static.a(eval.o):\n\
+            0000000000001a30 T ruby_setup\n\
+                             U rb_eval_string_protect\n\
+            0000000000000010 D ruby_api_version\n\
+                             w __gmon_start__\n\
+            0000000000002b80 W rb_weak_fn\n";
+        let defined = defined_symbols(output, false);
+        assert!(defined.contains("ruby_setup"));
+        assert!(defined.contains("ruby_api_version"));
+        assert!(defined.contains("rb_weak_fn"));
+        assert!(!defined.contains("rb_eval_string_protect"));
+        assert!(!defined.contains("__gmon_start__"));
+        assert!(!defined.contains("libruby-static.a(eval.o):"));
+
+        let output = "0000000000001a30 T _ruby_setup\n";
+        assert!(defined_symbols(output, true).contains("ruby_setup"));
+    }
+
+    #[test]
+    fn coff_symbols() {
+        let output = "\
+Dump of file x64-vcruntime140-ruby320-static.lib
+
+File Type: LIBRARY
+
+COFF SYMBOL TABLE
+000 01047A81 ABS    notype       Static       | @comp.id
+008 00000000 SECT3  notype ()    External     | ruby_setup
+009 00000000 UNDEF  notype ()    External     | rb_eval_string_protect
+00A 00000010 SECT4  notype       Static       | $SG1234
+00B 00000000 SECT5  notype       External     | ruby_api_version
+";
+        let defined = defined_coff_symbols(output, false);
+        assert!(defined.contains("ruby_setup"));
+        assert!(defined.contains("ruby_api_version"));
+        assert!(!defined.contains("rb_eval_string_protect"));
+        assert!(!defined.contains("$SG1234"));
+        assert!(!defined.contains("@comp.id"));
+
+        let output = "008 00000000 SECT3  notype ()  External  | _ruby_setup";
+        assert!(defined_coff_symbols(output, true).contains("ruby_setup"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn missing_symbols_without_ruby() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join("aloxide-verify-symbols");
+        let _ = fs::remove_dir_all(&dir);
+
+        // Built for another machine, so there's no `ruby` to run
+        let arch_dir = dir.join("lib/ruby/3.2.0/aarch64-linux");
+        fs::create_dir_all(&arch_dir).unwrap();
+        fs::write(arch_dir.join("rbconfig.rb"), r#"
+  CONFIG["prefix"] = (TOPDIR || DESTDIR + "/usr/local")
+  CONFIG["target_os"] = "linux"
+  CONFIG["RUBY_SO_NAME"] = "ruby"
+  CONFIG["LIBRUBY_A"] = "lib$(RUBY_SO_NAME)-static.a"
+"#).unwrap();
+        fs::write(dir.join("lib/libruby-static.a"), "").unwrap();
+
+        let nm = dir.join("nm");
+        fs::write(&nm, "#!/bin/sh\necho '0000000000001a30 T ruby_setup'\n")
+            .unwrap();
+        fs::set_permissions(&nm, fs::Permissions::from_mode(0o755)).unwrap();
+        std::env::set_var("NM", &nm);
+
+        let ruby = Ruby::new(Version::new(3, 2, 2), &dir);
+        let missing = ruby.verify_symbols(&["ruby_setup", "rb_missing"]);
+        std::env::remove_var("NM");
+        assert_eq!(missing.unwrap(), ["rb_missing"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_bin() {
+        let dir = std::env::temp_dir().join("aloxide-verify-missing-bin");
+        let ruby = Ruby::new(Version::new(3, 2, 2), &dir);
+
+        match ruby.verify().as_slice() {
+            [InstallProblem::MissingBin(path)] => {
+                assert_eq!(path, ruby.bin_path());
+            },
+            problems => panic!("unexpected problems: {:?}", problems),
+        }
+    }
+}
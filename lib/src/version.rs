@@ -21,6 +21,7 @@ pub struct Version {
     pub teeny: u16,
     /// The pre-release identifier for `self`.
     pub pre: Option<Box<str>>,
+    patchlevel: Option<u16>,
 }
 
 impl PartialOrd for Version {
@@ -48,7 +49,7 @@ impl Ord for Version {
         }
 
         match (self.pre(), other.pre()) {
-            (None, None) => Ordering::Equal,
+            (None, None) => self.patchlevel.cmp(&other.patchlevel),
             (None, Some(_)) => Ordering::Greater,
             (Some(_), None) => Ordering::Less,
             (Some(a), Some(b)) => {
@@ -65,21 +66,21 @@ impl Ord for Version {
 impl<S: Into<Box<str>>> From<(u16, u16, u16, S)> for Version {
     #[inline]
     fn from((major, minor, teeny, pre): (u16, u16, u16, S)) -> Self {
-        Version { major, minor, teeny, pre: Some(pre.into()) }
+        Version { major, minor, teeny, pre: Some(pre.into()), patchlevel: None }
     }
 }
 
 impl From<(u16, u16, u16)> for Version {
     #[inline]
     fn from((major, minor, teeny): (u16, u16, u16)) -> Self {
-        Version { major, minor, teeny, pre: None }
+        Version { major, minor, teeny, pre: None, patchlevel: None }
     }
 }
 
 impl From<(u16, u16)> for Version {
     #[inline]
     fn from((major, minor): (u16, u16)) -> Self {
-        Version { major, minor, teeny: 0, pre: None }
+        Version { major, minor, teeny: 0, pre: None, patchlevel: None }
     }
 }
 
@@ -93,13 +94,16 @@ impl From<(u16,)> for Version {
 impl From<u16> for Version {
     #[inline]
     fn from(major: u16) -> Version {
-        Version { major, minor: 0, teeny: 0, pre: None }
+        Version { major, minor: 0, teeny: 0, pre: None, patchlevel: None }
     }
 }
 
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}.{}.{}", self.major, self.minor, self.teeny)?;
+        if let Some(patchlevel) = self.patchlevel {
+            write!(f, "p{}", patchlevel)?;
+        }
         if let Some(pre) = &self.pre {
             write!(f, "-{}", pre)?;
         }
@@ -156,11 +160,35 @@ impl FromStr for Version {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for Version {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Version {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        use serde::de::Error;
+
+        let version = String::deserialize(deserializer)?;
+        version.parse().map_err(|_| {
+            D::Error::custom(format!("invalid Ruby version \"{}\"", version))
+        })
+    }
+}
+
 impl Version {
     /// Creates a new instance from `major`, `minor`, and `teeny`.
     #[inline]
     pub fn new(major: u16, minor: u16, teeny: u16) -> Self {
-        Version { major, minor, teeny, pre: None }
+        Version { major, minor, teeny, pre: None, patchlevel: None }
     }
 
     /// Creates a new instance from `major`, `minor`, `teeny`, and `pre`.
@@ -171,13 +199,30 @@ impl Version {
         teeny: u16,
         pre: impl Into<Box<str>>,
     ) -> Self {
-        Version { major, minor, teeny, pre: Some(pre.into()) }
+        Version { major, minor, teeny, pre: Some(pre.into()), patchlevel: None }
     }
 
     /// Returns the pre-release identifier string for `self`.
     #[inline]
     pub fn pre(&self) -> Option<&str> {
-        self.pre.as_ref().map(|s| &**s)
+        self.pre.as_deref()
+    }
+
+    /// Returns the patch level, such as 47 for `2.6.2p47`.
+    ///
+    /// This is only reported by Ruby before 2.1 and by `ruby -v` before 3.0.
+    /// It's considered when comparing versions, so `2.6.2p47` is greater than
+    /// `2.6.2`. However, a [`VersionReq`](struct.VersionReq.html) without a
+    /// patch level ignores it, so `2.6.2p47` satisfies `= 2.6.2`.
+    #[inline]
+    pub fn patchlevel(&self) -> Option<u16> {
+        self.patchlevel
+    }
+
+    /// Returns whether `self` satisfies `req`, such as `~> 3.1`.
+    #[inline]
+    pub fn satisfies(&self, req: &VersionReq) -> bool {
+        req.matches(self)
     }
 
     /// Attempts to get the version of the current Ruby found in `PATH`.
@@ -187,16 +232,34 @@ impl Version {
     }
 
     /// Attempts to get the version of a `ruby` executable.
-    #[inline]
+    ///
+    /// If `ruby` fails to evaluate `RUBY_VERSION`, the output of `ruby -v` is
+    /// parsed instead.
     pub fn from_bin(ruby: impl AsRef<OsStr>) -> Result<Self, RubyVersionError> {
-        Self::from_cmd(&mut Command::new(ruby))
+        let ruby = ruby.as_ref();
+        let result = Self::from_cmd(&mut Command::new(ruby));
+        match &result {
+            // `ruby` itself couldn't be run
+            Ok(_) | Err(RubyVersionError::Exec(RubyExecError::ExecFail(_))) => {
+                result
+            },
+            Err(_) => Self::from_cmd_v(&mut Command::new(ruby)),
+        }
+    }
+
+    /// Attempts to get the version of `ruby` by parsing the output of
+    /// `ruby -v`, including its patch level if reported.
+    #[inline]
+    pub fn from_cmd_v(ruby: &mut Command) -> Result<Self, RubyVersionError> {
+        Ok(RubyExecError::process(ruby.arg("-v"))?.parse()?)
     }
 
     /// Attempts to get the version of `ruby` by executing it.
     #[inline]
     pub fn from_cmd(ruby: &mut Command) -> Result<Self, RubyVersionError> {
         Ok(RubyExecError::process(
-            ruby.args(&["-e", "print RbConfig::CONFIG['RUBY_PROGRAM_VERSION']"])
+            // Unlike `RbConfig`, this is defined by every Ruby implementation
+            ruby.args(["-e", "print RUBY_VERSION"])
         )?.parse()?)
     }
 
@@ -207,10 +270,15 @@ impl Version {
         VersionParser::default()
     }
 
+    // `self` without its patch level, as used for release archives
+    pub(crate) fn release(&self) -> Version {
+        Version { patchlevel: None, ..self.clone() }
+    }
+
     /// Returns the name of the archive file corresponding to `self`.
     #[inline]
     pub fn archive_name(&self) -> String {
-        format!("ruby-{}.tar.bz2", self)
+        format!("ruby-{}.tar.bz2", self.release())
     }
 
     /// Returns an HTTPS URL for `self`.
@@ -220,7 +288,7 @@ impl Version {
             "https://cache.ruby-lang.org/pub/ruby/{major}.{minor}/ruby-{version}.tar.bz2",
             major = self.major,
             minor = self.minor,
-            version = self,
+            version = self.release(),
         )
     }
 }
@@ -255,10 +323,20 @@ impl VersionParser {
     }
 
     /// Convert `s` into a `Version` based on the rules defined on `self`.
+    ///
+    /// Along with `x.y.z-pre`, this accepts a patch level or pre-release
+    /// directly after the teeny version, such as `2.6.2p47` or
+    /// `3.3.0preview1`, and the full output of `ruby -v`, such as
+    /// `ruby 3.2.2 (2023-03-30 revision e51014f9c0) [x86_64-linux]`.
     pub fn parse(&self, mut s: &str) -> Result<Version, VersionParseError> {
         use VersionParseError::*;
         use crate::util::memchr;
 
+        // e.g. "ruby 2.6.2p47 (2019-03-13 revision 67232) [x86_64-linux]"
+        if let Some(rest) = s.trim().strip_prefix("ruby ") {
+            s = rest.split_whitespace().next().unwrap_or_default();
+        }
+
         fn split_at(s: &str, byte: u8) -> (&str, Option<&str>) {
             if let Some(index) = memchr(byte, s.as_bytes()) {
                 (&s[..index], Some(&s[(index + 1)..]))
@@ -292,6 +370,7 @@ impl VersionParser {
                     minor: 0,
                     teeny: 0,
                     pre: pre.map(|pre| pre.into()),
+                    patchlevel: None,
                 }),
                 Err(error) => return Err(MajorInt(error)),
             }
@@ -312,17 +391,42 @@ impl VersionParser {
             (remaining, None) => match remaining.parse() {
                 Ok(minor) => {
                     version.minor = minor;
+                    version.pre = pre.map(|pre| pre.into());
+                    return Ok(version);
                 },
                 Err(error) => return Err(MinorInt(error)),
             }
         }
 
-        match s.parse() {
+        // e.g. "2p47" or "0preview1"
+        let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+        let (teeny, suffix) = s.split_at(digits);
+        match teeny.parse() {
             Ok(teeny) => version.teeny = teeny,
             Err(error) => return Err(TeenyInt(error)),
         }
 
-        version.pre = pre.map(|pre| pre.into());
+        let patchlevel = suffix.strip_prefix('p')
+            .filter(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()));
+        match patchlevel {
+            Some(patchlevel) => match patchlevel.parse() {
+                Ok(patchlevel) => version.patchlevel = Some(patchlevel),
+                Err(error) => return Err(TeenyInt(error)),
+            },
+            None if suffix.starts_with(|c: char| c.is_ascii_alphabetic())
+                && pre.is_none() => {
+                version.pre = Some(suffix.into());
+            },
+            None if !suffix.is_empty() => {
+                // Reuse the error for non-numeric characters
+                return Err(TeenyInt(s.parse::<u16>().unwrap_err()));
+            },
+            None => {},
+        }
+
+        if let Some(pre) = pre {
+            version.pre = Some(pre.into());
+        }
 
         Ok(version)
     }
@@ -354,6 +458,160 @@ impl From<Utf8Error> for VersionParseError {
     }
 }
 
+/// A requirement that a `Version` must satisfy, such as `>= 2.7, < 3.3` or
+/// `~> 3.1`.
+///
+/// Each comma-separated comparator must match. Versions within comparators
+/// may omit the minor and teeny versions, which default to 0.
+///
+/// As with RubyGems, the pessimistic operator (`~>`) allows for only the last
+/// version component given to increase: `~> 3.1` matches 3.1 up to but not
+/// including 4.0, and `~> 3.1.2` matches 3.1.2 up to but not including 3.2.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionReq {
+    comparators: Vec<(Op, Version)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    // `~>` with the number of components given, such as 2 for "3.1"
+    Pessimistic(usize),
+}
+
+impl Op {
+    // Ordered such that operators are matched before their prefixes
+    const ALL: [(Op, &'static str); 7] = [
+        (Op::Pessimistic(0), "~>"),
+        (Op::Ge, ">="),
+        (Op::Le, "<="),
+        (Op::Ne, "!="),
+        (Op::Gt, ">"),
+        (Op::Lt, "<"),
+        (Op::Eq, "="),
+    ];
+
+    fn as_str(self) -> &'static str {
+        let op = match self {
+            Op::Pessimistic(_) => Op::Pessimistic(0),
+            op => op,
+        };
+        Op::ALL.iter().find(|(other, _)| *other == op).unwrap().1
+    }
+
+    fn matches(self, version: &Version, req: &Version) -> bool {
+        // A requirement without a patch level applies to every patch level
+        let release;
+        let has_patchlevel = version.patchlevel.is_some();
+        let version = if req.patchlevel.is_none() && has_patchlevel {
+            release = version.release();
+            &release
+        } else {
+            version
+        };
+
+        match self {
+            Op::Eq => version == req,
+            Op::Ne => version != req,
+            Op::Gt => version > req,
+            Op::Ge => version >= req,
+            Op::Lt => version < req,
+            Op::Le => version <= req,
+            Op::Pessimistic(components) => version >= req && if components < 3 {
+                version.major == req.major
+            } else {
+                (version.major, version.minor) == (req.major, req.minor)
+            },
+        }
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (op, version)) in self.comparators.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            match op {
+                Op::Pessimistic(1) => write!(f, "~> {}", version.major)?,
+                Op::Pessimistic(2) if version.pre.is_none() => write!(
+                    f,
+                    "~> {}.{}",
+                    version.major,
+                    version.minor,
+                )?,
+                op => write!(f, "{} {}", op.as_str(), version)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = VersionReqParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut comparators = Vec::new();
+        for comparator in s.split(',') {
+            let comparator = comparator.trim();
+            if comparator.is_empty() {
+                return Err(VersionReqParseError::Empty);
+            }
+            let (mut op, version) = Op::ALL.iter()
+                .find_map(|&(op, prefix)| {
+                    comparator.strip_prefix(prefix).map(|rest| (op, rest))
+                })
+                .unwrap_or((Op::Eq, comparator));
+            let version = version.trim();
+            if let Op::Pessimistic(_) = op {
+                let components = version.split('.')
+                    .take_while(|c| c.bytes().all(|b| b.is_ascii_digit()))
+                    .count();
+                op = Op::Pessimistic(components.min(3));
+            }
+            comparators.push((op, version.parse()?));
+        }
+        Ok(VersionReq { comparators })
+    }
+}
+
+impl From<Version> for VersionReq {
+    /// Creates a requirement matching exactly `version`.
+    #[inline]
+    fn from(version: Version) -> Self {
+        VersionReq { comparators: vec![(Op::Eq, version)] }
+    }
+}
+
+impl VersionReq {
+    /// Returns whether `version` satisfies every comparator of `self`.
+    #[inline]
+    pub fn matches(&self, version: &Version) -> bool {
+        self.comparators.iter().all(|(op, req)| op.matches(version, req))
+    }
+}
+
+/// The error returned when parsing a string into a `VersionReq` fails.
+#[derive(Clone, Debug)]
+pub enum VersionReqParseError {
+    /// A comparator was empty.
+    Empty,
+    /// A comparator's version could not be parsed.
+    Version(VersionParseError),
+}
+
+impl From<VersionParseError> for VersionReqParseError {
+    #[inline]
+    fn from(error: VersionParseError) -> Self {
+        VersionReqParseError::Version(error)
+    }
+}
+
 /// Failed to get a Ruby version from a `ruby` executable.
 #[derive(Debug)]
 pub enum RubyVersionError {
@@ -389,6 +647,7 @@ mod tests {
             (Version::from(1),                "1"),
             (Version::from((1, 0, 0, "rc1")), "1-rc1"),
             (Version::from((1, 0, 0, "rc2")), "1.0-rc2"),
+            (Version::from((2, 7, 0)),        "2.7"),
             (Version::from((1, 0, 0)),        "1.0.0"),
             (Version::from((1, 0, 0, "dev")), "1.0.0-dev"),
         ];
@@ -449,4 +708,79 @@ mod tests {
             assert!(b > a, "{} > {}", b, a);
         }
     }
+
+    #[test]
+    fn patchlevel() {
+        let version: Version = "2.6.2p47".parse().unwrap();
+        assert_eq!(version.patchlevel(), Some(47));
+        assert_eq!(version.to_string(), "2.6.2p47");
+        assert_eq!(version.archive_name(), "ruby-2.6.2.tar.bz2");
+        assert!(version > Version::new(2, 6, 2));
+        assert!(version < Version::new(2, 6, 3));
+
+        let outputs = [
+            ("ruby 3.2.2 (2023-03-30 revision e51014f9c0) [x86_64-linux]",
+                Version::new(3, 2, 2)),
+            ("ruby 2.6.2p47 (2019-03-13 revision 67232) [x86_64-darwin18]",
+                Version { patchlevel: Some(47), ..Version::new(2, 6, 2) }),
+            ("ruby 3.3.0preview1 (2023-05-12 master a1b01e7701) [arm64]\n",
+                Version::with_pre(3, 3, 0, "preview1")),
+            ("ruby 3.3.0 (2023-12-25 revision 5124f9ac75) +YJIT [arm64]",
+                Version::new(3, 3, 0)),
+        ];
+        for (output, expected) in &outputs {
+            assert_eq!(&output.parse::<Version>().unwrap(), expected);
+        }
+
+        for bad in &["2.6.2.1", "2.6.2_1", "ruby"] {
+            assert!(bad.parse::<Version>().is_err(), "{}", bad);
+        }
+        for bad in &["2.6.é", "2.6.", "2.6.p47"] {
+            match bad.parse::<Version>() {
+                Err(VersionParseError::TeenyInt(_)) => {},
+                other => panic!("unexpected result for {}: {:?}", bad, other),
+            }
+        }
+
+        // Requirements without a patch level ignore it
+        let req: VersionReq = "2.6.2".parse().unwrap();
+        assert!(version.satisfies(&req));
+        assert!(version.satisfies(&"<= 2.6.2".parse().unwrap()));
+        assert!(!version.satisfies(&"2.6.2p46".parse().unwrap()));
+        assert!(version.satisfies(&"> 2.6.2p46".parse().unwrap()));
+    }
+
+    #[test]
+    fn req() {
+        let req: VersionReq = ">= 2.7, < 3.3".parse().unwrap();
+        assert_eq!(req.to_string(), ">= 2.7.0, < 3.3.0");
+        assert!(req.matches(&Version::new(2, 7, 0)));
+        assert!(req.matches(&Version::new(3, 2, 2)));
+        assert!(!req.matches(&Version::new(3, 3, 0)));
+        assert!(!req.matches(&Version::with_pre(2, 7, 0, "preview1")));
+
+        let req: VersionReq = "3.2.2".parse().unwrap();
+        assert!(req.matches(&Version::new(3, 2, 2)));
+        assert!(!req.matches(&Version::new(3, 2, 1)));
+
+        let req: VersionReq = "~> 3.1".parse().unwrap();
+        assert_eq!(req.to_string(), "~> 3.1");
+        assert!(req.matches(&Version::new(3, 1, 0)));
+        assert!(req.matches(&Version::new(3, 3, 5)));
+        assert!(!req.matches(&Version::new(3, 0, 9)));
+        assert!(!req.matches(&Version::new(4, 0, 0)));
+        assert!(!req.matches(&Version::with_pre(4, 0, 0, "preview1")));
+
+        let req: VersionReq = "~> 3.1.2, != 3.1.3".parse().unwrap();
+        assert_eq!(req.to_string(), "~> 3.1.2, != 3.1.3");
+        assert!(Version::new(3, 1, 4).satisfies(&req));
+        assert!(!Version::new(3, 1, 3).satisfies(&req));
+        assert!(!Version::new(3, 1, 1).satisfies(&req));
+        assert!(!Version::new(3, 2, 0).satisfies(&req));
+
+        let bad = ["", ">= 2.7,", "> x", "=> 3", "~>"];
+        for string in &bad {
+            string.parse::<VersionReq>().unwrap_err();
+        }
+    }
 }